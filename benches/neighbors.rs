@@ -0,0 +1,58 @@
+//! Times updates of `f32` flocks, whose neighbor searches go through `simd::distances`.
+//!
+//! Run it with and without the `simd` feature to compare:
+//!
+//! ```sh
+//! cargo bench --bench neighbors
+//! cargo bench --bench neighbors --features simd
+//! ```
+
+use std::time::{Duration, Instant};
+
+use boids::{Boid3D, Flock};
+use cgmath::Vector3;
+
+/// Time `steps` updates of `flock`, keeping the fastest to cut out noise from the rest of the system
+fn fastest_update(flock: &mut Flock<Boid3D<f32>, f32>, steps: usize) -> Duration {
+    (0..steps)
+        .map(|_| {
+            let start = Instant::now();
+            flock.update();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// Make a flock of `count` boids spread over a square lattice, with varied headings
+fn lattice(count: usize) -> Flock<Boid3D<f32>, f32> {
+    let side = (count as f32).sqrt().ceil() as usize;
+    Flock {
+        boids: (0..count)
+            .map(|i| {
+                let position = Vector3::new((i % side) as f32 * 4.0, (i / side) as f32 * 4.0, 0.0);
+                Boid3D::new_with_angle(position, i as f32 * 2.4)
+            })
+            .collect(),
+        ..Flock::default()
+    }
+}
+
+fn main() {
+    // Below the default `grid_min_boids`, so every search checks every boid
+    let mut direct = lattice(700);
+    let direct = fastest_update(&mut direct, 40);
+
+    let mut gridded = lattice(4000);
+    gridded.rebuild_grid(25.0);
+    let gridded = fastest_update(&mut gridded, 20);
+
+    println!(
+        "700 boids, checking every boid   {:>10.2?} per update",
+        direct
+    );
+    println!(
+        "4000 boids, with a spatial grid  {:>10.2?} per update",
+        gridded
+    );
+}
@@ -2,8 +2,16 @@ use cgmath::{num_traits::Float, BaseNum, InnerSpace, MetricSpace, Vector2, Vecto
 use rand::{distributions::Standard, prelude::Distribution, Rng};
 use std::ops::{AddAssign, Div, DivAssign, Mul, MulAssign, Sub};
 
-use super::{convert::LossyConvert, limits::limit_magnitude_v2, Boid, BoidWeights};
-use crate::flock::Flock;
+use super::{
+    convert::LossyConvert,
+    frame::{reflect_point, reflect_vector},
+    limits::{add_force_conserving, limit_magnitude_v2, raise_magnitude},
+    Boid, BoidWeights,
+};
+use crate::{
+    error::{scalar, BoidError},
+    flock::{Flock, Integration},
+};
 
 /// A Boid in 2 dimensions.
 #[derive(Debug, Clone, PartialEq)]
@@ -17,23 +25,40 @@ pub struct Boid2D<U: BaseNum + Float> {
     pub acceleration: Vector2<U>,
     /// Boid maximum speed
     pub max_speed: U,
+    /// Slowest a moving boid is allowed to go. A boid that has come to a stop stays stopped.
+    #[cfg_attr(feature = "serde", serde(default = "cgmath::num_traits::Zero::zero"))]
+    pub min_speed: U,
     /// Boid maximum force
     pub max_force: U,
     /// Boid weights
     pub weights: BoidWeights<U>,
+    /// Sub-group this boid belongs to, used to pick its target
+    pub group: u16,
+    /// Radius of the boid's body, used for hit testing
+    pub radius: U,
 }
 
 impl<U: BaseNum + Float> Boid2D<U> {
     /// Create a new Boid2D from a position and angle
     pub fn new_with_angle(position: Vector2<U>, angle: U) -> Self {
-        Self {
+        Self::try_new_with_angle(position, angle)
+            .expect("scalar type cannot represent the default boid parameters")
+    }
+
+    /// Create a new Boid2D from a position and angle, failing if the scalar type cannot represent the
+    /// default parameters
+    pub fn try_new_with_angle(position: Vector2<U>, angle: U) -> Result<Self, BoidError> {
+        Ok(Self {
             position,
             velocity: Vector2::new(angle.cos(), angle.sin()),
             acceleration: Vector2::new(U::zero(), U::zero()),
-            max_speed: U::from(2.0).unwrap(),
-            max_force: U::from(0.03).unwrap(),
-            weights: BoidWeights::default(),
-        }
+            max_speed: scalar(2.0)?,
+            min_speed: U::zero(),
+            max_force: scalar(0.03)?,
+            weights: BoidWeights::try_default()?,
+            group: 0,
+            radius: U::one(),
+        })
     }
 
     /// Create a new Boid2D from a position and random angle
@@ -44,10 +69,52 @@ impl<U: BaseNum + Float> Boid2D<U> {
         let angle = rand::thread_rng().gen::<U>() * U::from(std::f64::consts::PI * 2.0).unwrap();
         Self::new_with_angle(position, angle)
     }
+
+    /// Add a force to the acceleration, to be applied by the next `integrate_acceleration`
+    pub fn accumulate_force(&mut self, force: Vector2<U>) {
+        self.acceleration.add_assign(force);
+    }
+
+    /// Apply the accumulated acceleration to the velocity once, keeping a moving boid between `min_speed`
+    /// and `max_speed`, then move by the new velocity. With `conserving`, the acceleration only turns the
+    /// boid, as with `add_force_conserving`.
+    ///
+    /// The acceleration is left as it was, so it still holds the net force afterwards.
+    pub fn integrate_acceleration(&mut self, conserving: bool) {
+        // Apply acceleration to velocity, limiting the speed
+        let velocity = if conserving {
+            add_force_conserving(self.velocity, self.acceleration, self.max_speed)
+        } else {
+            limit_magnitude_v2(self.velocity + self.acceleration, self.max_speed)
+        };
+        self.velocity = raise_magnitude(velocity, self.min_speed);
+
+        // Apply velocity to position
+        self.position.add_assign(self.velocity);
+    }
+
+    /// Apply a force and move the boid, clamping the speed either by vector addition or, if
+    /// `conserving`, with `add_force_conserving`
+    fn integrate(&self, force: Vector3<U>, conserving: bool) -> Boid2D<U> {
+        // Alloc a new boid
+        let mut boid = self.clone();
+        boid.acceleration = force.lossy_convert();
+        boid.integrate_acceleration(conserving);
+
+        // Reset acceleration
+        boid.acceleration.mul_assign(U::zero());
+        boid
+    }
 }
 
-impl<U: BaseNum + Float> Boid<Boid2D<U>, U> for Boid2D<U> {
-    fn separate(&self, flock: &Flock<Boid2D<U>, U>) -> Vector3<U> {
+impl<U: BaseNum + Float> Boid2D<U> {
+    /// Calculate the separation force, steering at `speed`
+    fn separation_force(
+        &self,
+        own: Option<usize>,
+        flock: &Flock<Boid2D<U>, U>,
+        speed: U,
+    ) -> Vector3<U> {
         #[cfg(feature = "puffin")]
         puffin::profile_function!();
 
@@ -57,18 +124,48 @@ impl<U: BaseNum + Float> Boid<Boid2D<U>, U> for Boid2D<U> {
         // Tracker for number of boids nearby
         let mut count = U::zero();
 
-        // Steer away from nearby boids
-        for boid in flock.boids.iter() {
+        // Total and highest priority of those boids
+        let mut priority = U::zero();
+        let mut highest = U::zero();
+
+        // Steer away from nearby boids, including co-located ones
+        let position = self.position();
+        let scale = flock.perception_scale(self);
+        let reach = flock.max_separation_radius() * scale;
+        flock.neighbors_excluding_self(self, own, reach, |index, _| {
+            let boid = &flock.boids[index];
             let boid_pos = boid.position().lossy_convert();
             let distance = self.position.distance(boid_pos);
 
             // Only operate on nearby boids
-            if distance > U::zero() && distance < flock.goal_separation {
+            let radius =
+                flock.separation_radius(self.velocity(), boid.position() - position) * scale;
+            if distance < radius && flock.separates_from(self, boid) {
                 // Calculate vector pointing away from neighbor
-                let diff = (self.position - boid_pos).normalize().div(distance);
-                steer.add_assign(diff);
+                let diff = if distance > U::zero() {
+                    (self.position - boid_pos).normalize().div(distance)
+                } else {
+                    // Break the tie between co-located boids by index, pushing along the X axis as
+                    // hard as if their bodies were just touching
+                    let touching = (self.radius + boid.radius()).max(U::epsilon());
+                    let away = if own.is_some_and(|own| own > index) {
+                        U::one()
+                    } else {
+                        -U::one()
+                    };
+                    Vector2::new(away, U::zero()).div(touching)
+                };
+                let weight = flock.separation_priority_of(self, boid);
+                steer.add_assign(diff.mul(weight));
                 count += U::one();
+                priority += weight;
+                highest = highest.max(weight);
             }
+        });
+
+        // Ignore isolated encounters until enough neighbors crowd in
+        if count < U::from(flock.separation_min_neighbors).unwrap() {
+            return Vector3::new(U::zero(), U::zero(), U::zero());
         }
 
         // Average the steering factor
@@ -76,18 +173,37 @@ impl<U: BaseNum + Float> Boid<Boid2D<U>, U> for Boid2D<U> {
             steer.div_assign(count);
         }
 
-        // Implement Reynolds: Limit the steering force to max_force
+        // Implement Reynolds: Limit the steering force to max_force, then push harder away from
+        // high-priority neighbors, up to max_force times the highest priority among them
         if steer.magnitude() > U::zero() {
-            steer = limit_magnitude_v2(
-                steer.normalize().mul(self.max_speed).sub(self.velocity),
+            let reynolds = limit_magnitude_v2(
+                steer.normalize().mul(speed).sub(self.velocity),
                 self.max_force,
             );
+            steer = limit_magnitude_v2(reynolds.mul(priority / count), self.max_force * highest);
         }
 
         steer.lossy_convert()
     }
 
-    fn align(&self, flock: &Flock<Boid2D<U>, U>) -> Vector3<U> {
+    /// Get the force on this boid when it has no neighbors
+    fn isolated_force(&self, flock: &Flock<Boid2D<U>, U>) -> Vector3<U> {
+        flock.isolated_force(
+            self.group,
+            self.position(),
+            self.velocity(),
+            self.max_force,
+            &flock.effective_weights(self.get_weights()),
+        )
+    }
+
+    /// Calculate the alignment force, steering at `speed`
+    fn alignment_force(
+        &self,
+        own: Option<usize>,
+        flock: &Flock<Boid2D<U>, U>,
+        speed: U,
+    ) -> Vector3<U> {
         #[cfg(feature = "puffin")]
         puffin::profile_function!();
 
@@ -98,15 +214,18 @@ impl<U: BaseNum + Float> Boid<Boid2D<U>, U> for Boid2D<U> {
         let mut count = U::zero();
 
         // Align with nearby boids
-        for boid in flock.boids.iter() {
-            let distance = self.position.distance(boid.position().lossy_convert());
-
-            // Only operate on nearby boids
-            if distance > U::zero() && distance < flock.goal_alignment {
-                align.add_assign(boid.velocity().lossy_convert());
-                count += U::one();
-            }
-        }
+        let radius = flock.perception_for(self).alignment();
+        flock.neighbors_excluding_self(self, own, radius, |index, _| {
+            let boid = &flock.boids[index];
+
+            // Optionally anticipate turns using the neighbor's acceleration
+            let heading = match flock.alignment_acceleration_blend {
+                Some(blend) => boid.velocity() + boid.acceleration().mul(blend),
+                None => boid.velocity(),
+            };
+            align.add_assign(heading.lossy_convert());
+            count += U::one();
+        });
 
         // Average the alignment factor
         if count > U::zero() {
@@ -114,77 +233,83 @@ impl<U: BaseNum + Float> Boid<Boid2D<U>, U> for Boid2D<U> {
 
             // Implement Reynolds: Limit the steering force to max_force
             limit_magnitude_v2(
-                align.normalize().mul(self.max_speed).sub(self.velocity),
+                align.normalize().mul(speed).sub(self.velocity),
                 self.max_force,
             )
             .lossy_convert()
         } else {
-            Vector3::new(U::zero(), U::zero(), U::zero())
+            self.isolated_force(flock)
         }
     }
 
-    fn cohesion(&self, flock: &Flock<Boid2D<U>, U>) -> Vector3<U> {
+    /// Calculate the cohesion force, steering at `speed`
+    fn cohesion_force(
+        &self,
+        own: Option<usize>,
+        flock: &Flock<Boid2D<U>, U>,
+        speed: U,
+    ) -> Vector3<U> {
         #[cfg(feature = "puffin")]
         puffin::profile_function!();
 
-        // Alloc a steering force
-        let mut cohesion = Vector2::new(U::zero(), U::zero());
-
-        // Tracker for number of boids nearby
-        let mut count = U::zero();
-
-        // Steer towards nearby boids
-        for boid in flock.boids.iter() {
-            let boid_pos = boid.position().lossy_convert();
-            let distance = self.position.distance(boid_pos);
-
-            // Only operate on nearby boids
-            if distance > U::zero() && distance < flock.goal_cohesion {
-                cohesion.add_assign(boid_pos);
-                count += U::one();
-            }
-        }
-
-        // Average the cohesion factor
-        if count > U::zero() {
-            cohesion.div_assign(count);
-            cohesion = cohesion.sub(self.position);
+        // Find the middle of nearby boids
+        let target = match flock.cohesion_point(self, own) {
+            Some(target) => target,
+            None => return self.isolated_force(flock),
+        };
+        let cohesion: Vector2<U> = target.lossy_convert().sub(self.position);
 
+        if cohesion.magnitude2() > U::zero() {
             // Implement Reynolds: Limit the steering force to max_force
             limit_magnitude_v2(
-                cohesion.normalize().mul(self.max_speed).sub(self.velocity),
+                cohesion.normalize().mul(speed).sub(self.velocity),
                 self.max_force,
             )
+            .mul(flock.cohesion_scale(self, own))
             .lossy_convert()
         } else {
             Vector3::new(U::zero(), U::zero(), U::zero())
         }
     }
+}
 
-    fn set_weights(&mut self, weights: BoidWeights<U>) {
-        self.weights = weights;
+impl<U: BaseNum + Float> Boid<Boid2D<U>, U> for Boid2D<U> {
+    fn separate_at(&self, index: Option<usize>, flock: &Flock<Boid2D<U>, U>) -> Vector3<U> {
+        self.separation_force(index, flock, self.desired_speed_at(index, flock))
     }
 
-    fn get_weights<'a>(&'a self) -> &'a BoidWeights<U> {
-        &self.weights
+    fn align_at(&self, index: Option<usize>, flock: &Flock<Boid2D<U>, U>) -> Vector3<U> {
+        self.alignment_force(index, flock, self.desired_speed_at(index, flock))
     }
 
-    fn with_force(&self, force: Vector3<U>) -> Boid2D<U> {
-        // Alloc a new boid
-        let mut boid = self.clone();
+    fn cohesion_at(&self, index: Option<usize>, flock: &Flock<Boid2D<U>, U>) -> Vector3<U> {
+        self.cohesion_force(index, flock, self.desired_speed_at(index, flock))
+    }
 
-        // Apply acceleration to velocity
-        boid.velocity.add_assign(force.lossy_convert());
+    fn desired_speed_at(&self, index: Option<usize>, flock: &Flock<Boid2D<U>, U>) -> U {
+        match flock.density_speed {
+            Some(curve) => {
+                let radius = flock.goal_alignment();
+                let neighbors = match index {
+                    Some(index) => flock.neighbor_count_of(index, radius),
+                    None => flock.neighbor_count(self.position(), radius),
+                };
+                self.max_speed * curve.scale(neighbors)
+            }
+            None => self.max_speed,
+        }
+    }
 
-        // Limit the speed
-        boid.velocity = limit_magnitude_v2(boid.velocity, self.max_speed);
+    fn set_weights(&mut self, weights: BoidWeights<U>) {
+        self.weights = weights;
+    }
 
-        // Apply velocity to position
-        boid.position.add_assign(boid.velocity);
+    fn get_weights(&self) -> &BoidWeights<U> {
+        &self.weights
+    }
 
-        // Reset acceleration
-        boid.acceleration.mul_assign(U::zero());
-        boid
+    fn with_force(&self, force: Vector3<U>) -> Boid2D<U> {
+        self.integrate(force, false)
     }
 
     fn position(&self) -> Vector3<U> {
@@ -199,22 +324,136 @@ impl<U: BaseNum + Float> Boid<Boid2D<U>, U> for Boid2D<U> {
         self.acceleration.lossy_convert()
     }
 
-    fn update(&self, flock: &Flock<Boid2D<U>, U>) -> Boid2D<U> {
+    fn group(&self) -> u16 {
+        self.group
+    }
+
+    fn max_speed(&self) -> U {
+        self.max_speed
+    }
+
+    fn radius(&self) -> U {
+        self.radius
+    }
+
+    fn set_position(&mut self, position: Vector3<U>) {
+        self.position = position.lossy_convert();
+    }
+
+    fn set_velocity(&mut self, velocity: Vector3<U>) {
+        self.velocity = velocity.lossy_convert();
+    }
+
+    /// 2D boids stay in the XY plane, so the mirror plane should be upright, with no Z in its normal
+    fn mirror(&self, plane_normal: Vector3<U>, plane_point: Vector3<U>) -> Boid2D<U> {
+        Boid2D {
+            position: reflect_point(self.position(), plane_normal, plane_point).lossy_convert(),
+            velocity: reflect_vector(self.velocity(), plane_normal).lossy_convert(),
+            acceleration: reflect_vector(self.acceleration(), plane_normal).lossy_convert(),
+            ..self.clone()
+        }
+    }
+
+    fn update_at(&self, index: Option<usize>, flock: &Flock<Boid2D<U>, U>) -> Boid2D<U> {
         #[cfg(feature = "puffin")]
         puffin::profile_function!();
 
-        let weights = self.get_weights();
-        let separation = self.separate(flock).mul(weights.separation);
-        let alignment = self.align(flock).mul(weights.alignment);
-        let cohesion = self.cohesion(flock).mul(weights.cohesion);
+        let weights = flock.effective_weights(self.get_weights());
+        let zero = Vector3::new(U::zero(), U::zero(), U::zero());
+
+        // Crowding sets the target speed of every steering behavior, so only work it out once
+        let speed = self.desired_speed_at(index, flock);
+
+        // Skip disabled or zero-weight behaviors entirely, since they cannot contribute anything
+        let behaviors = flock.behaviors;
+        let separation = if behaviors.separation && weights.separation != U::zero() {
+            self.separation_force(index, flock, speed)
+                .mul(weights.separation)
+        } else {
+            zero
+        };
+        let alignment = if behaviors.alignment && weights.alignment != U::zero() {
+            self.alignment_force(index, flock, speed)
+                .mul(weights.alignment)
+        } else {
+            zero
+        };
+        let cohesion = if behaviors.cohesion && weights.cohesion != U::zero() {
+            self.cohesion_force(index, flock, speed)
+                .mul(weights.cohesion)
+        } else {
+            zero
+        };
         let targeting = flock
-            .target
-            .map(|target| target.sub(self.position.lossy_convert()))
-            .unwrap_or(Vector3::new(U::zero(), U::zero(), U::zero()))
+            .targeting_force(self.group, self.position())
             .mul(weights.targeting);
-        self.with_force(separation)
-            .with_force(alignment)
-            .with_force(cohesion)
-            .with_force(targeting)
+        let flee = flock.startle_force(self.position(), index);
+        let global_cohesion = flock
+            .global_cohesion_force(self.position(), self.velocity(), speed, self.max_force)
+            .mul(weights.global_cohesion);
+        let formation =
+            flock.formation_force(self.position(), self.velocity(), speed, self.max_force);
+
+        let anchor = flock
+            .anchor_force(self.position(), self.velocity(), speed, self.max_force)
+            .mul(weights.anchor);
+
+        let avoidance =
+            flock.avoid_obstacles(self.position(), self.velocity(), speed, self.max_force);
+
+        let flow = flock.flow_force(self.position());
+
+        // Keep any single behavior from drowning out the others
+        let mut forces = [
+            separation,
+            alignment,
+            cohesion,
+            targeting,
+            flee,
+            global_cohesion,
+            formation,
+            anchor,
+            avoidance,
+            flow,
+        ]
+        .map(|force| flock.clamp_contribution(force, self.max_force));
+        flock.clamp_net_force(&mut forces);
+
+        // Slow down from drag before applying the new forces
+        let mut dragged = self.clone();
+        dragged.velocity.mul_assign(U::one() - flock.drag);
+
+        let mut boid = match flock.integration {
+            Integration::PerForce => {
+                // Move after each core behavior, then once more for everything else, so extra forces do
+                // not add extra moves
+                let (core, rest) = forces.split_at(3);
+                let rest = rest.iter().fold(zero, |sum, force| sum + *force);
+                core.iter()
+                    .chain(std::iter::once(&rest))
+                    .fold(dragged, |boid, force| {
+                        boid.integrate(*force, flock.energy_conserving)
+                    })
+            }
+            Integration::Accumulated => {
+                let mut boid = dragged;
+                boid.acceleration = Vector2::new(U::zero(), U::zero());
+                for force in forces.iter() {
+                    boid.accumulate_force(force.lossy_convert());
+                }
+                boid.integrate_acceleration(flock.energy_conserving);
+                boid
+            }
+        };
+
+        // Record the net steering force so neighbors can see it
+        boid.acceleration = forces
+            .iter()
+            .fold(
+                Vector3::new(U::zero(), U::zero(), U::zero()),
+                |sum, force| sum + *force,
+            )
+            .lossy_convert();
+        boid
     }
 }
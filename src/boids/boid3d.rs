@@ -1,13 +1,30 @@
-use cgmath::{num_traits::Float, BaseNum, InnerSpace, MetricSpace, Vector3};
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, Vector3};
 use rand::{distributions::Standard, prelude::Distribution, Rng};
 use std::ops::{AddAssign, Div, DivAssign, Mul, MulAssign, Sub};
 
-use super::{limits::limit_magnitude_v3, Boid, BoidWeights};
-use crate::flock::Flock;
+use super::{
+    frame::{reflect_point, reflect_vector, snap},
+    limits::{add_force_conserving, limit_magnitude_v3, raise_magnitude, steer_v3},
+    Boid, BoidParams, BoidWeights,
+};
+use crate::{
+    error::{scalar, BoidError},
+    flock::{Flock, Integration},
+    obstacle::Obstacle,
+    perception::PerceptionConfig,
+};
+
+/// Separation weight multiplier for a fully panicked boid
+const PANIC_SEPARATION_BOOST: f64 = 4.0;
 
 /// A Boid in 3 dimensions.
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+// Missing options default to `None`, which needs no bounds
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "U: serde::Deserialize<'de>"))
+)]
 pub struct Boid3D<U: BaseNum + Float> {
     /// Boid position
     pub position: Vector3<U>,
@@ -15,28 +32,75 @@ pub struct Boid3D<U: BaseNum + Float> {
     pub velocity: Vector3<U>,
     /// Boid acceleration
     pub acceleration: Vector3<U>,
+    /// Boid velocity before the last update
+    pub last_velocity: Vector3<U>,
     /// Boid maximum speed
     pub max_speed: U,
+    /// Slowest a moving boid is allowed to go. A boid that has come to a stop stays stopped.
+    #[cfg_attr(feature = "serde", serde(default = "cgmath::num_traits::Zero::zero"))]
+    pub min_speed: U,
     /// Boid maximum force
     pub max_force: U,
-    /// Boid maximum turn rate
+    /// Boid maximum turn rate, in radians per update. Prefer `set_turn_rate`, which rejects invalid rates.
     pub r: U,
     /// Boid weights
     pub weights: BoidWeights<U>,
+    /// Sub-group this boid belongs to, used to pick its target
+    pub group: u16,
+    /// Radius of the boid's body, used for hit testing
+    pub radius: U,
+    /// Panic level from `0` (calm) to `1` (fully panicked), boosting separation and suppressing cohesion
+    pub panic: U,
+    /// Amount the panic level drops by each update
+    pub panic_decay: U,
+    /// Filtered average heading of the neighbors, kept while `Flock::neighbor_smoothing` is set
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub smoothed_heading: Option<Vector3<U>>,
+    /// Filtered center of the neighbors, kept while `Flock::neighbor_smoothing` is set
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub smoothed_center: Option<Vector3<U>>,
+    /// Point this boid heads for instead of its group's or the flock's target. `None` follows those.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub target: Option<Vector3<U>>,
 }
 
 impl<U: BaseNum + Float> Boid3D<U> {
     /// Create a new Boid3D from a position and angle
     pub fn new_with_angle(position: Vector3<U>, angle: U) -> Self {
-        Self {
+        Self::try_new_with_angle(position, angle)
+            .expect("scalar type cannot represent the default boid parameters")
+    }
+
+    /// Create a new Boid3D from a position and angle, failing if the scalar type cannot represent the
+    /// default parameters
+    pub fn try_new_with_angle(position: Vector3<U>, angle: U) -> Result<Self, BoidError> {
+        let velocity = Vector3::new(angle.cos(), angle.sin(), U::zero());
+        Ok(Self {
             position,
-            velocity: Vector3::new(angle.cos(), angle.sin(), U::zero()),
+            velocity,
+            last_velocity: velocity,
             acceleration: Vector3::new(U::zero(), U::zero(), U::zero()),
             r: U::one() + U::one(),
             max_speed: U::one() + U::one(),
-            max_force: U::from(0.03).unwrap(),
-            weights: BoidWeights::default(),
-        }
+            min_speed: U::zero(),
+            max_force: scalar(0.03)?,
+            weights: BoidWeights::try_default()?,
+            group: 0,
+            radius: U::one(),
+            panic: U::zero(),
+            panic_decay: scalar(0.05)?,
+            smoothed_heading: None,
+            smoothed_center: None,
+            target: None,
+        })
+    }
+
+    /// Create a new Boid3D from a position and velocity, limited to the default maximum speed
+    pub fn new_with_velocity(position: Vector3<U>, velocity: Vector3<U>) -> Self {
+        let mut boid = Self::new_with_angle(position, U::zero());
+        boid.velocity = limit_magnitude_v3(velocity, boid.max_speed);
+        boid.last_velocity = boid.velocity;
+        boid
     }
 
     /// Create a new Boid3D from a position and random angle
@@ -47,52 +111,153 @@ impl<U: BaseNum + Float> Boid3D<U> {
         let angle = rand::thread_rng().gen::<U>() * U::from(std::f64::consts::PI * 2.0).unwrap();
         Self::new_with_angle(position, angle)
     }
-}
 
-impl<U: BaseNum + Float> Boid<Boid3D<U>, U> for Boid3D<U> {
-    fn separate(&self, flock: &Flock<Boid3D<U>, U>) -> Vector3<U> {
-        #[cfg(feature = "puffin")]
-        puffin::profile_function!();
-
-        // Alloc a steering force
-        let mut steer = Vector3::new(U::zero(), U::zero(), U::zero());
+    /// Get the Reynolds steering force towards `desired`: the change in velocity needed to head that way
+    /// at `max_speed`, limited to `max_force`.
+    ///
+    /// A zero `desired` brakes, steering against the current velocity.
+    pub fn steer_toward(&self, desired: Vector3<U>) -> Vector3<U> {
+        self.steer_toward_at(desired, self.max_speed)
+    }
 
-        // Tracker for number of boids nearby
-        let mut count = U::zero();
+    /// Like `steer_toward`, but aiming for `speed` instead of `max_speed`
+    pub fn steer_toward_at(&self, desired: Vector3<U>, speed: U) -> Vector3<U> {
+        steer_v3(desired, self.velocity, speed, self.max_force)
+    }
 
-        // Steer away from nearby boids
-        for boid in flock.boids.iter() {
-            let distance = self.position.distance(boid.position());
+    /// Get the maximum turn rate, in radians per update
+    pub fn turn_rate(&self) -> U {
+        self.r
+    }
 
-            // Only operate on nearby boids
-            if distance > U::zero() && distance < flock.goal_separation {
-                // Calculate vector pointing away from neighbor
-                let diff = (self.position - boid.position()).normalize().div(distance);
-                steer.add_assign(diff);
-                count += U::one();
-            }
+    /// Set the maximum turn rate, in radians per update.
+    ///
+    /// Fails and leaves the rate unchanged unless `rate` is positive and finite.
+    pub fn set_turn_rate(&mut self, rate: U) -> Result<(), BoidError> {
+        if rate > U::zero() && rate.is_finite() {
+            self.r = rate;
+            Ok(())
+        } else {
+            Err(BoidError::InvalidParameter { name: "turn_rate" })
         }
+    }
 
-        // Average the steering factor
-        if count > U::zero() {
-            steer.div_assign(count);
+    /// Get the boid's tuning parameters
+    pub fn params(&self) -> BoidParams<U> {
+        BoidParams {
+            max_speed: self.max_speed,
+            min_speed: self.min_speed,
+            max_force: self.max_force,
+            turn_rate: self.r,
+            weights: self.weights,
         }
+    }
 
-        // Implement Reynolds: Limit the steering force to max_force
-        if steer.magnitude() > U::zero() {
-            steer = limit_magnitude_v3(
-                steer.normalize().mul(self.max_speed).sub(self.velocity),
-                self.max_force,
-            );
+    /// Set every tuning parameter from `params`, leaving the boid's motion untouched.
+    ///
+    /// Fails and changes nothing if `params` does not pass `BoidParams::validate`.
+    pub fn apply_params(&mut self, params: &BoidParams<U>) -> Result<(), BoidError> {
+        params.validate()?;
+        self.max_speed = params.max_speed;
+        self.min_speed = params.min_speed;
+        self.max_force = params.max_force;
+        self.r = params.turn_rate;
+        self.weights = params.weights;
+        Ok(())
+    }
+
+    /// Add a force to the acceleration, to be applied by the next `integrate_acceleration`
+    pub fn accumulate_force(&mut self, force: Vector3<U>) {
+        self.acceleration.add_assign(force);
+    }
+
+    /// Apply the accumulated acceleration to the velocity once, keeping a moving boid between `min_speed`
+    /// and `max_speed`, then move by the new velocity. With `conserving`, the acceleration only turns the
+    /// boid, as with `add_force_conserving`.
+    ///
+    /// The acceleration is left as it was, so it still holds the net force afterwards.
+    pub fn integrate_acceleration(&mut self, conserving: bool) {
+        // Apply acceleration to velocity, limiting the speed
+        let velocity = if conserving {
+            add_force_conserving(self.velocity, self.acceleration, self.max_speed)
+        } else {
+            limit_magnitude_v3(self.velocity + self.acceleration, self.max_speed)
+        };
+        self.velocity = raise_magnitude(velocity, self.min_speed);
+
+        // Apply velocity to position
+        self.position.add_assign(self.velocity);
+    }
+
+    /// Raise the panic level, capped at `1`
+    pub fn raise_panic(&mut self, amount: U) {
+        self.panic = (self.panic + amount).min(U::one()).max(U::zero());
+    }
+
+    /// Check if the straight line from this boid to `point` is clear of every obstacle
+    pub fn can_see(&self, point: Vector3<U>, obstacles: &[Obstacle<U>]) -> bool {
+        !obstacles
+            .iter()
+            .any(|obstacle| obstacle.blocks(self.position, point))
+    }
+
+    /// Mirror this boid across the plane through `plane_point` with normal `plane_normal`.
+    ///
+    /// Position, velocity, acceleration, the previous velocity, and the boid's own target are all
+    /// reflected.
+    pub fn reflect(&self, plane_normal: Vector3<U>, plane_point: Vector3<U>) -> Self {
+        Self {
+            position: reflect_point(self.position, plane_normal, plane_point),
+            velocity: reflect_vector(self.velocity, plane_normal),
+            acceleration: reflect_vector(self.acceleration, plane_normal),
+            last_velocity: reflect_vector(self.last_velocity, plane_normal),
+            smoothed_heading: self
+                .smoothed_heading
+                .map(|heading| reflect_vector(heading, plane_normal)),
+            smoothed_center: self
+                .smoothed_center
+                .map(|center| reflect_point(center, plane_normal, plane_point)),
+            target: self
+                .target
+                .map(|target| reflect_point(target, plane_normal, plane_point)),
+            ..self.clone()
         }
+    }
 
-        steer
+    /// Get the position rounded to the nearest multiple of `cell` on each axis, for grid-snapped rendering.
+    ///
+    /// Returns the exact position if `cell` is not positive.
+    pub fn snapped_position(&self, cell: U) -> Vector3<U> {
+        snap(self.position, cell)
     }
 
-    fn align(&self, flock: &Flock<Boid3D<U>, U>) -> Vector3<U> {
-        #[cfg(feature = "puffin")]
-        puffin::profile_function!();
+    /// Get how fast the boid is turning, in radians per update.
+    ///
+    /// This is zero if the boid was or is stationary.
+    pub fn angular_velocity(&self) -> U {
+        if self.velocity.magnitude2() <= U::zero() || self.last_velocity.magnitude2() <= U::zero() {
+            return U::zero();
+        }
+        let cross = self.last_velocity.cross(self.velocity).magnitude();
+        cross.atan2(self.last_velocity.dot(self.velocity))
+    }
 
+    /// Get the perception radii in effect for this boid in `flock`, for drawing its neighborhoods.
+    ///
+    /// Boids do not carry their own perception radii, so this is the flock's `perception`, scaled up
+    /// with this boid's speed if the flock has a `perception_speed_factor`.
+    pub fn perception_radii(&self, flock: &Flock<Boid3D<U>, U>) -> PerceptionConfig<U> {
+        flock.perception_for(self)
+    }
+}
+
+impl<U: BaseNum + Float> Boid3D<U> {
+    /// Get the average heading of the neighbors to align with, or `None` if there are none
+    fn alignment_heading(
+        &self,
+        own: Option<usize>,
+        flock: &Flock<Boid3D<U>, U>,
+    ) -> Option<Vector3<U>> {
         // Alloc an alignment force
         let mut align = Vector3::new(U::zero(), U::zero(), U::zero());
 
@@ -100,90 +265,231 @@ impl<U: BaseNum + Float> Boid<Boid3D<U>, U> for Boid3D<U> {
         let mut count = U::zero();
 
         // Align with nearby boids
-        for boid in flock.boids.iter() {
-            let distance = self.position.distance(boid.position());
+        let radius = flock.perception_for(self).alignment();
+        flock.neighbors_excluding_self(self, own, radius, |index, _| {
+            let boid = &flock.boids[index];
 
-            // Only operate on nearby boids
-            if distance > U::zero() && distance < flock.goal_alignment {
-                align.add_assign(boid.velocity());
-                count += U::one();
-            }
-        }
+            // Optionally anticipate turns using the neighbor's acceleration
+            let heading = match flock.alignment_acceleration_blend {
+                Some(blend) => boid.velocity() + boid.acceleration().mul(blend),
+                None => boid.velocity(),
+            };
+            align.add_assign(heading);
+            count += U::one();
+        });
 
         // Average the alignment factor
-        if count > U::zero() {
-            align.div_assign(count);
+        let heading = (count > U::zero()).then(|| align.div(count));
+        flock.smooth_neighbor_average(self.smoothed_heading, heading, self.velocity)
+    }
+
+    /// Get the center of the neighbors to cohere with, or `None` if there are none
+    fn cohesion_center(
+        &self,
+        own: Option<usize>,
+        flock: &Flock<Boid3D<U>, U>,
+    ) -> Option<Vector3<U>> {
+        flock.smooth_neighbor_average(
+            self.smoothed_center,
+            flock.cohesion_point(self, own),
+            self.position,
+        )
+    }
+
+    /// Get the force on this boid when it has no neighbors, which a boid heading for its own target
+    /// never brakes with
+    fn isolated_force(&self, flock: &Flock<Boid3D<U>, U>) -> Vector3<U> {
+        if flock.behaviors.targeting && self.target.is_some() {
+            return Vector3::new(U::zero(), U::zero(), U::zero());
+        }
+        let weights = flock
+            .effective_weights(self.get_weights())
+            .panicked(self.panic, U::from(PANIC_SEPARATION_BOOST).unwrap());
+        flock.isolated_force(
+            self.group,
+            self.position(),
+            self.velocity(),
+            self.max_force,
+            &weights,
+        )
+    }
+
+    /// Steer at `speed` to match the neighbors' average heading
+    fn steer_alignment(
+        &self,
+        heading: Option<Vector3<U>>,
+        speed: U,
+        flock: &Flock<Boid3D<U>, U>,
+    ) -> Vector3<U> {
+        match heading {
+            // Implement Reynolds: Limit the steering force to max_force
+            Some(heading) => self.steer_toward_at(heading, speed),
+            None => self.isolated_force(flock),
+        }
+    }
 
+    /// Steer at `speed` towards the neighbors' center, scaled following `cohesion_normalization`
+    fn steer_cohesion(
+        &self,
+        center: Option<Vector3<U>>,
+        own: Option<usize>,
+        speed: U,
+        flock: &Flock<Boid3D<U>, U>,
+    ) -> Vector3<U> {
+        let cohesion = match center {
+            Some(center) => center.sub(self.position),
+            None => return self.isolated_force(flock),
+        };
+
+        if cohesion.magnitude2() > U::zero() {
             // Implement Reynolds: Limit the steering force to max_force
-            limit_magnitude_v3(
-                align.normalize().mul(self.max_speed).sub(self.velocity),
-                self.max_force,
-            )
+            self.steer_toward_at(cohesion, speed)
+                .mul(flock.cohesion_scale(self, own))
         } else {
             Vector3::new(U::zero(), U::zero(), U::zero())
         }
     }
 
-    fn cohesion(&self, flock: &Flock<Boid3D<U>, U>) -> Vector3<U> {
+    /// Calculate the separation force, steering at `speed`
+    fn separation_force(
+        &self,
+        own: Option<usize>,
+        flock: &Flock<Boid3D<U>, U>,
+        speed: U,
+    ) -> Vector3<U> {
         #[cfg(feature = "puffin")]
         puffin::profile_function!();
 
         // Alloc a steering force
-        let mut cohesion = Vector3::new(U::zero(), U::zero(), U::zero());
+        let mut steer = Vector3::new(U::zero(), U::zero(), U::zero());
 
         // Tracker for number of boids nearby
         let mut count = U::zero();
 
-        // Steer towards nearby boids
-        for boid in flock.boids.iter() {
-            let distance = self.position.distance(boid.position());
+        // Total and highest priority of those boids
+        let mut priority = U::zero();
+        let mut highest = U::zero();
+
+        // Steer away from nearby boids, including co-located ones
+        let scale = flock.perception_scale(self);
+        let reach = flock.max_separation_radius() * scale;
+        flock.neighbors_excluding_self(self, own, reach, |index, distance| {
+            let boid = &flock.boids[index];
 
             // Only operate on nearby boids
-            if distance > U::zero() && distance < flock.goal_cohesion {
-                cohesion.add_assign(boid.position());
+            let radius =
+                flock.separation_radius(self.velocity, boid.position() - self.position) * scale;
+            if distance < radius && flock.separates_from(self, boid) {
+                // Calculate vector pointing away from neighbor
+                let diff = if distance > U::zero() {
+                    (self.position - boid.position()).normalize().div(distance)
+                } else {
+                    // Break the tie between co-located boids by index, pushing along the X axis as
+                    // hard as if their bodies were just touching
+                    let touching = (self.radius + boid.radius()).max(U::epsilon());
+                    let away = if own.is_some_and(|own| own > index) {
+                        U::one()
+                    } else {
+                        -U::one()
+                    };
+                    Vector3::new(away, U::zero(), U::zero()).div(touching)
+                };
+                let weight = flock.separation_priority_of(self, boid);
+                steer.add_assign(diff.mul(weight));
                 count += U::one();
+                priority += weight;
+                highest = highest.max(weight);
             }
+        });
+
+        // Ignore isolated encounters until enough neighbors crowd in
+        if count < U::from(flock.separation_min_neighbors).unwrap() {
+            return Vector3::new(U::zero(), U::zero(), U::zero());
         }
 
-        // Average the cohesion factor
+        // Average the steering factor
         if count > U::zero() {
-            cohesion.div_assign(count);
-            cohesion = cohesion.sub(self.position);
+            steer.div_assign(count);
+        }
 
-            // Implement Reynolds: Limit the steering force to max_force
-            limit_magnitude_v3(
-                cohesion.normalize().mul(self.max_speed).sub(self.velocity),
-                self.max_force,
-            )
-        } else {
-            Vector3::new(U::zero(), U::zero(), U::zero())
+        // Implement Reynolds: Limit the steering force to max_force, then push harder away from
+        // high-priority neighbors, up to max_force times the highest priority among them
+        if steer.magnitude() > U::zero() {
+            steer = limit_magnitude_v3(
+                self.steer_toward_at(steer, speed).mul(priority / count),
+                self.max_force * highest,
+            );
         }
+
+        steer
     }
 
-    fn set_weights(&mut self, weights: BoidWeights<U>) {
-        self.weights = weights;
+    /// Apply a force and move the boid, clamping the speed either by vector addition or, if
+    /// `conserving`, with `add_force_conserving`
+    fn integrate(&self, force: Vector3<U>, conserving: bool) -> Boid3D<U> {
+        // Alloc a new boid
+        let mut boid = self.clone();
+        boid.acceleration = force;
+        boid.integrate_acceleration(conserving);
+
+        // Reset acceleration
+        boid.acceleration.mul_assign(U::zero());
+        boid
     }
+}
 
-    fn get_weights<'a>(&'a self) -> &'a BoidWeights<U> {
-        &self.weights
+impl<U: BaseNum + Float> Boid<Boid3D<U>, U> for Boid3D<U> {
+    fn separate_at(&self, index: Option<usize>, flock: &Flock<Boid3D<U>, U>) -> Vector3<U> {
+        self.separation_force(index, flock, self.desired_speed_at(index, flock))
     }
 
-    fn with_force(&self, force: Vector3<U>) -> Boid3D<U> {
-        // Alloc a new boid
-        let mut boid = self.clone();
+    fn align_at(&self, index: Option<usize>, flock: &Flock<Boid3D<U>, U>) -> Vector3<U> {
+        #[cfg(feature = "puffin")]
+        puffin::profile_function!();
 
-        // Apply acceleration to velocity
-        boid.velocity.add_assign(force);
+        self.steer_alignment(
+            self.alignment_heading(index, flock),
+            self.desired_speed_at(index, flock),
+            flock,
+        )
+    }
 
-        // Limit the speed
-        boid.velocity = limit_magnitude_v3(boid.velocity, self.max_speed);
+    fn cohesion_at(&self, index: Option<usize>, flock: &Flock<Boid3D<U>, U>) -> Vector3<U> {
+        #[cfg(feature = "puffin")]
+        puffin::profile_function!();
 
-        // Apply velocity to position
-        boid.position.add_assign(boid.velocity);
+        self.steer_cohesion(
+            self.cohesion_center(index, flock),
+            index,
+            self.desired_speed_at(index, flock),
+            flock,
+        )
+    }
 
-        // Reset acceleration
-        boid.acceleration.mul_assign(U::zero());
-        boid
+    fn desired_speed_at(&self, index: Option<usize>, flock: &Flock<Boid3D<U>, U>) -> U {
+        match flock.density_speed {
+            Some(curve) => {
+                let radius = flock.goal_alignment();
+                let neighbors = match index {
+                    Some(index) => flock.neighbor_count_of(index, radius),
+                    None => flock.neighbor_count(self.position(), radius),
+                };
+                self.max_speed * curve.scale(neighbors)
+            }
+            None => self.max_speed,
+        }
+    }
+
+    fn set_weights(&mut self, weights: BoidWeights<U>) {
+        self.weights = weights;
+    }
+
+    fn get_weights(&self) -> &BoidWeights<U> {
+        &self.weights
+    }
+
+    fn with_force(&self, force: Vector3<U>) -> Boid3D<U> {
+        self.integrate(force, false)
     }
 
     fn position(&self) -> Vector3<U> {
@@ -198,22 +504,816 @@ impl<U: BaseNum + Float> Boid<Boid3D<U>, U> for Boid3D<U> {
         self.acceleration
     }
 
-    fn update(&self, flock: &Flock<Boid3D<U>, U>) -> Boid3D<U> {
+    fn group(&self) -> u16 {
+        self.group
+    }
+
+    fn target(&self) -> Option<Vector3<U>> {
+        self.target
+    }
+
+    fn max_speed(&self) -> U {
+        self.max_speed
+    }
+
+    fn radius(&self) -> U {
+        self.radius
+    }
+
+    fn set_position(&mut self, position: Vector3<U>) {
+        self.position = position;
+    }
+
+    fn set_velocity(&mut self, velocity: Vector3<U>) {
+        self.velocity = velocity;
+    }
+
+    fn panic(&self) -> U {
+        self.panic
+    }
+
+    fn set_panic(&mut self, panic: U) {
+        self.panic = panic;
+    }
+
+    fn mirror(&self, plane_normal: Vector3<U>, plane_point: Vector3<U>) -> Boid3D<U> {
+        self.reflect(plane_normal, plane_point)
+    }
+
+    fn update_at(&self, index: Option<usize>, flock: &Flock<Boid3D<U>, U>) -> Boid3D<U> {
         #[cfg(feature = "puffin")]
         puffin::profile_function!();
 
-        let weights = self.get_weights();
-        let separation = self.separate(flock).mul(weights.separation);
-        let alignment = self.align(flock).mul(weights.alignment);
-        let cohesion = self.cohesion(flock).mul(weights.cohesion);
+        let weights = flock
+            .effective_weights(self.get_weights())
+            .panicked(self.panic, U::from(PANIC_SEPARATION_BOOST).unwrap());
+        let zero = Vector3::new(U::zero(), U::zero(), U::zero());
+
+        // Crowding sets the target speed of every steering behavior, so only work it out once
+        let speed = self.desired_speed_at(index, flock);
+
+        // Skip disabled or zero-weight behaviors entirely, since they cannot contribute anything
+        let behaviors = flock.behaviors;
+        let separation = if behaviors.separation && weights.separation != U::zero() {
+            self.separation_force(index, flock, speed)
+                .mul(weights.separation)
+        } else {
+            zero
+        };
+        let heading = (behaviors.alignment && weights.alignment != U::zero())
+            .then(|| self.alignment_heading(index, flock));
+        let alignment = heading
+            .map(|heading| {
+                self.steer_alignment(heading, speed, flock)
+                    .mul(weights.alignment)
+            })
+            .unwrap_or(zero);
+        let center = (behaviors.cohesion && weights.cohesion != U::zero())
+            .then(|| self.cohesion_center(index, flock));
+        let cohesion = center
+            .map(|center| {
+                self.steer_cohesion(center, index, speed, flock)
+                    .mul(weights.cohesion)
+            })
+            .unwrap_or(zero);
         let targeting = flock
-            .target
-            .map(|target| target.sub(self.position))
-            .unwrap_or(Vector3::new(U::zero(), U::zero(), U::zero()))
+            .targeting_force_towards(flock.target_of(self), self.position())
             .mul(weights.targeting);
-        self.with_force(separation)
-            .with_force(alignment)
-            .with_force(cohesion)
-            .with_force(targeting)
+        let flee = flock.startle_force(self.position(), index);
+        let global_cohesion = flock
+            .global_cohesion_force(self.position(), self.velocity(), speed, self.max_force)
+            .mul(weights.global_cohesion);
+        let formation =
+            flock.formation_force(self.position(), self.velocity(), speed, self.max_force);
+
+        let anchor = flock
+            .anchor_force(self.position(), self.velocity(), speed, self.max_force)
+            .mul(weights.anchor);
+
+        let avoidance =
+            flock.avoid_obstacles(self.position(), self.velocity(), speed, self.max_force);
+
+        let flow = flock.flow_force(self.position());
+
+        // Keep any single behavior from drowning out the others
+        let mut forces = [
+            separation,
+            alignment,
+            cohesion,
+            targeting,
+            flee,
+            global_cohesion,
+            formation,
+            anchor,
+            avoidance,
+            flow,
+        ]
+        .map(|force| flock.clamp_contribution(force, self.max_force));
+        flock.clamp_net_force(&mut forces);
+
+        // Slow down from drag before applying the new forces
+        let mut dragged = self.clone();
+        dragged.velocity.mul_assign(U::one() - flock.drag);
+
+        let mut boid = match flock.integration {
+            Integration::PerForce => {
+                // Move after each core behavior, then once more for everything else, so extra forces do
+                // not add extra moves
+                let (core, rest) = forces.split_at(3);
+                let rest = rest.iter().fold(zero, |sum, force| sum + *force);
+                core.iter()
+                    .chain(std::iter::once(&rest))
+                    .fold(dragged, |boid, force| {
+                        boid.integrate(*force, flock.energy_conserving)
+                    })
+            }
+            Integration::Accumulated => {
+                let mut boid = dragged;
+                boid.acceleration = Vector3::new(U::zero(), U::zero(), U::zero());
+                for force in forces.iter() {
+                    boid.accumulate_force(*force);
+                }
+                boid.integrate_acceleration(flock.energy_conserving);
+                boid
+            }
+        };
+
+        // Calm down over time
+        boid.panic = (self.panic - self.panic_decay).max(U::zero());
+
+        // Carry the filtered neighbor averages into the next update
+        if flock.neighbor_smoothing.is_some() {
+            boid.smoothed_heading = heading.flatten();
+            boid.smoothed_center = center.flatten();
+        } else {
+            boid.smoothed_heading = None;
+            boid.smoothed_center = None;
+        }
+
+        // Remember the old heading for turn rate tracking
+        boid.last_velocity = self.velocity;
+
+        // Record the net steering force so neighbors can see it
+        boid.acceleration = forces.iter().fold(
+            Vector3::new(U::zero(), U::zero(), U::zero()),
+            |sum, force| sum + *force,
+        );
+        boid
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Give every boid the same tuning parameters, leaving their motion untouched.
+    ///
+    /// Fails and changes no boid if `params` does not pass `BoidParams::validate`.
+    pub fn set_params_all(&mut self, params: &BoidParams<U>) -> Result<(), BoidError> {
+        params.validate()?;
+        for boid in self.boids.iter_mut() {
+            boid.apply_params(params)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::{Boid3D, BoidParams};
+    use crate::{
+        boids::Boid,
+        error::BoidError,
+        flock::{CohesionNormalization, Flock, Integration},
+    };
+
+    fn boid(position: (f64, f64, f64), velocity: (f64, f64, f64)) -> Boid3D<f64> {
+        Boid3D::new_with_velocity(
+            Vector3::new(position.0, position.1, position.2),
+            Vector3::new(velocity.0, velocity.1, velocity.2),
+        )
+    }
+
+    #[test]
+    fn acceleration_aware_alignment_anticipates_turns() {
+        // The boid ahead has started turning towards +y, but has not changed heading yet
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock.boids.push(boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)));
+        let mut leader = boid((5.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+        leader.acceleration = Vector3::new(0.0, 0.5, 0.0);
+        flock.boids.push(leader);
+
+        let plain = flock.boids[0].align(&flock);
+        flock.alignment_acceleration_blend = Some(1.0);
+        let blended = flock.boids[0].align(&flock);
+
+        assert!(plain.y.abs() < 1e-12);
+        assert!(blended.y > 0.0);
+    }
+
+    #[test]
+    fn angular_velocity_tracks_turning() {
+        // Follow a circle, turning 0.1 radians each update
+        let at = |step: f64| (2.0 * (step * 0.1).cos(), 2.0 * (step * 0.1).sin(), 0.0);
+        let mut circling = boid((0.0, 0.0, 0.0), at(0.0));
+        for step in 1..20 {
+            circling.last_velocity = circling.velocity;
+            let velocity = at(step as f64);
+            circling.velocity = Vector3::new(velocity.0, velocity.1, velocity.2);
+            assert!((circling.angular_velocity() - 0.1).abs() < 1e-9);
+        }
+
+        // A lone boid flies straight
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock.boids.push(boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)));
+        flock.update();
+        assert!(flock.boids[0].angular_velocity().abs() < 1e-12);
+
+        // Starting or stopping is not a turn
+        let mut stopped = boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+        stopped.velocity = Vector3::new(0.0, 0.0, 0.0);
+        assert_eq!(stopped.angular_velocity(), 0.0);
+    }
+
+    #[test]
+    fn panic_only_changes_the_panicked_boid() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock.boids.push(boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)));
+        flock.boids.push(boid((4.0, 3.0, 0.0), (0.0, 1.0, 0.0)));
+        flock.boids.push(boid((-3.0, 5.0, 0.0), (1.0, 1.0, 0.0)));
+        let calm: Vec<Boid3D<f64>> = flock.boids.iter().map(|boid| boid.update(&flock)).collect();
+
+        flock.boids[0].raise_panic(1.0);
+        let panicked: Vec<Boid3D<f64>> =
+            flock.boids.iter().map(|boid| boid.update(&flock)).collect();
+
+        assert_ne!(panicked[0].velocity, calm[0].velocity);
+        assert_eq!(panicked[1..], calm[1..]);
+        // The panic wears off over time
+        assert!(panicked[0].panic < 1.0);
+    }
+
+    #[test]
+    fn sightlines_are_blocked_by_boxes_and_capsules() {
+        use crate::obstacle::Obstacle;
+
+        let viewer = boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+        let target = Vector3::new(10.0, 0.0, 0.0);
+        let around = Vector3::new(10.0, 5.0, 0.0);
+
+        let wall = [Obstacle::Box {
+            min: Vector3::new(4.0, -1.0, -1.0),
+            max: Vector3::new(5.0, 1.0, 1.0),
+        }];
+        assert!(!viewer.can_see(target, &wall));
+        assert!(viewer.can_see(around, &wall));
+
+        let pillar = [Obstacle::Capsule {
+            start: Vector3::new(5.0, 0.0, -3.0),
+            end: Vector3::new(5.0, 0.0, 3.0),
+            radius: 0.5,
+        }];
+        assert!(!viewer.can_see(target, &pillar));
+        assert!(viewer.can_see(around, &pillar));
+
+        assert!(viewer.can_see(target, &[]));
+    }
+
+    #[test]
+    fn crowded_boids_want_to_slow_down() {
+        use crate::flock::DensitySpeed;
+
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            density_speed: Some(DensitySpeed {
+                half_density: 4.0,
+                min_fraction: 0.25,
+            }),
+            ..Flock::default()
+        };
+        flock.boids.push(boid((1000.0, 0.0, 0.0), (1.0, 0.0, 0.0)));
+        for i in 0..12 {
+            let angle = i as f64 * 0.5;
+            flock.boids.push(boid(
+                (angle.cos() * 5.0, angle.sin() * 5.0, 0.0),
+                (1.0, 0.0, 0.0),
+            ));
+        }
+        flock.boids.push(boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)));
+
+        let isolated = flock.boids[0].desired_speed(&flock);
+        assert_eq!(isolated, flock.boids[0].max_speed);
+        let packed = flock.boids[13].desired_speed(&flock);
+        // 12 neighbors at half density 4 leaves a quarter of the top speed
+        assert!((packed - 0.5).abs() < 1e-12);
+
+        // The update steers at that speed
+        let updated = flock.boids[13].update(&flock);
+        assert!(updated.velocity.magnitude() < 1.0);
+    }
+
+    #[test]
+    fn per_force_integration_moves_once_per_core_behavior() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock.boids.push(boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)));
+        flock.update();
+        // Separation, alignment, cohesion, then everything else
+        assert_eq!(flock.boids[0].position, Vector3::new(4.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn co_located_boids_push_apart_without_swamping_other_neighbors() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock.boids.push(boid((0.0, 0.0, 0.0), (0.0, 0.0, 0.0)));
+        flock.boids.push(boid((0.0, 0.0, 0.0), (0.0, 0.0, 0.0)));
+        let first = flock.boids[0].separate_at(Some(0), &flock);
+        let second = flock.boids[1].separate_at(Some(1), &flock);
+        assert!(first.x < 0.0 && second.x > 0.0);
+        assert!(first.magnitude() <= flock.boids[0].max_force + 1e-12);
+
+        // A copy knows where it is stored just as well as the original
+        assert_eq!(flock.boids[0].clone().separate_at(Some(0), &flock), first);
+
+        // A boid outside the flock takes anything at its position for itself
+        let outsider = boid((0.0, 0.0, 0.0), (0.0, 0.0, 0.0));
+        assert_eq!(outsider.separate(&flock), Vector3::new(0.0, 0.0, 0.0));
+
+        // A neighbor just above still counts
+        flock.boids.push(boid((0.0, 1.0, 0.0), (0.0, 0.0, 0.0)));
+        let crowded = flock.boids[0].separate_at(Some(0), &flock);
+        assert!(crowded.y < -0.5 * crowded.magnitude());
+    }
+
+    #[test]
+    fn new_with_velocity_keeps_the_velocity_up_to_max_speed() {
+        let position = Vector3::new(1.0, 2.0, 3.0);
+        let slow: Boid3D<f64> = Boid3D::new_with_velocity(position, Vector3::new(0.5, -1.0, 0.25));
+        assert_eq!(slow.position, position);
+        assert_eq!(slow.velocity, Vector3::new(0.5, -1.0, 0.25));
+        assert_eq!(slow.acceleration, Vector3::new(0.0, 0.0, 0.0));
+
+        let fast: Boid3D<f64> = Boid3D::new_with_velocity(position, Vector3::new(0.0, 30.0, 40.0));
+        assert!((fast.velocity.magnitude() - fast.max_speed).abs() < 1e-12);
+        assert!((fast.velocity.normalize() - Vector3::new(0.0, 0.6, 0.8)).magnitude() < 1e-12);
+        assert_eq!(fast.acceleration, Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn snapping_rounds_positions_without_moving_the_boid() {
+        let boid = boid((1.4, -2.6, 3.5), (0.0, 0.0, 0.0));
+        assert_eq!(boid.snapped_position(1.0), Vector3::new(1.0, -3.0, 4.0));
+        assert_eq!(boid.snapped_position(2.0), Vector3::new(2.0, -2.0, 4.0));
+        assert_eq!(boid.snapped_position(0.0), boid.position);
+        assert_eq!(boid.snapped_position(-1.0), boid.position);
+        assert_eq!(boid.position, Vector3::new(1.4, -2.6, 3.5));
+
+        let flock = Flock {
+            boids: vec![boid, self::boid((0.2, 0.7, -0.7), (0.0, 0.0, 0.0))],
+            ..Flock::default()
+        };
+        assert_eq!(
+            flock.snapped_positions(1.0),
+            vec![Vector3::new(1.0, -3.0, 4.0), Vector3::new(0.0, 1.0, -1.0)]
+        );
+    }
+
+    #[test]
+    fn separation_waits_for_enough_neighbors() {
+        let crowd = |count: usize| Flock {
+            boids: (0..count)
+                .map(|i| boid((i as f64 * 3.0, (i % 2) as f64, 0.0), (1.0, 0.0, 0.0)))
+                .collect(),
+            separation_min_neighbors: 3,
+            ..Flock::default()
+        };
+
+        let pair = crowd(2);
+        assert_eq!(pair.boids[0].separate(&pair), Vector3::new(0.0, 0.0, 0.0));
+        let default = Flock {
+            separation_min_neighbors: 1,
+            ..pair.clone()
+        };
+        assert!(default.boids[0].separate(&default).magnitude() > 0.0);
+
+        let four = crowd(4);
+        assert!(four.boids[0].separate(&four).magnitude() > 0.0);
+    }
+
+    #[test]
+    fn zero_weight_behaviors_are_not_evaluated() {
+        let cluster = |alignment: f64, cohesion: f64| {
+            let mut flock = Flock {
+                boids: (0..6)
+                    .map(|i| boid((i as f64 * 4.0, (i % 3) as f64 * 4.0, 0.0), (1.0, 0.5, 0.0)))
+                    .collect(),
+                // Smoothing carries each neighbor average into the next update, showing whether it ran
+                neighbor_smoothing: Some(2.0),
+                ..Flock::default()
+            };
+            for boid in flock.boids.iter_mut() {
+                boid.weights.alignment = alignment;
+                boid.weights.cohesion = cohesion;
+            }
+            flock
+        };
+
+        let mut weighted = cluster(1.5, 1.0);
+        weighted.update();
+        assert!(weighted
+            .boids
+            .iter()
+            .all(|boid| boid.smoothed_heading.is_some()));
+        assert!(weighted
+            .boids
+            .iter()
+            .all(|boid| boid.smoothed_center.is_some()));
+
+        let mut zeroed = cluster(0.0, 0.0);
+        zeroed.update();
+        assert!(zeroed
+            .boids
+            .iter()
+            .all(|boid| boid.smoothed_heading.is_none()));
+        assert!(zeroed
+            .boids
+            .iter()
+            .all(|boid| boid.smoothed_center.is_none()));
+
+        // Anything the skipped alignment would have worked out cannot leak in, even a NaN that a zero
+        // weight would not cancel
+        let mut poisoned = Flock {
+            alignment_acceleration_blend: Some(f64::NAN),
+            ..cluster(0.0, 0.0)
+        };
+        poisoned.update();
+        assert_eq!(poisoned.boids, zeroed.boids);
+    }
+
+    #[test]
+    fn steer_toward_is_reynolds_steering() {
+        let boid = boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+
+        // Heading straight up at full speed needs a change of (-1, 2, 0), cut to max_force
+        let force = boid.steer_toward(Vector3::new(0.0, 5.0, 0.0));
+        let expected = Vector3::new(-1.0, boid.max_speed, 0.0);
+        assert!((force - expected.normalize() * boid.max_force).magnitude() < 1e-12);
+
+        // Nowhere to go brakes against the velocity
+        let brake = boid.steer_toward(Vector3::new(0.0, 0.0, 0.0));
+        assert!(brake.x < 0.0 && brake.y == 0.0 && brake.z == 0.0);
+
+        // The built-in behaviors go through the same primitive
+        let flock = Flock {
+            boids: vec![boid.clone(), self::boid((5.0, 5.0, 0.0), (0.0, 1.0, 0.0))],
+            ..Flock::default()
+        };
+        let boid = &flock.boids[0];
+        let speed = boid.desired_speed(&flock);
+        assert_eq!(
+            boid.align(&flock),
+            boid.steer_toward_at(Vector3::new(0.0, 1.0, 0.0), speed)
+        );
+        assert_eq!(
+            boid.cohesion(&flock),
+            boid.steer_toward_at(Vector3::new(5.0, 5.0, 0.0), speed)
+        );
+    }
+
+    #[test]
+    fn isolated_boids_brake_only_when_asked() {
+        let run = |brake_when_isolated: bool| {
+            let mut flock = Flock {
+                boids: vec![boid((0.0, 0.0, 0.0), (1.5, 0.0, 0.0))],
+                brake_when_isolated,
+                ..Flock::default()
+            };
+            (0..100)
+                .map(|_| {
+                    flock.update();
+                    flock.boids[0].velocity.magnitude()
+                })
+                .collect::<Vec<f64>>()
+        };
+
+        let coasting = run(false);
+        assert!(coasting.iter().all(|speed| (speed - 1.5).abs() < 1e-12));
+
+        let braking = run(true);
+        assert!(braking[0] < 1.5);
+        assert!(braking.windows(2).all(|pair| pair[1] <= pair[0]));
+
+        // The shared brake brings the boid to a stop rather than flipping it back and forth
+        assert!(braking[99] < 1e-12);
+
+        // A boid heading for a target keeps going
+        let mut flock = Flock {
+            boids: vec![boid((0.0, 0.0, 0.0), (1.5, 0.0, 0.0))],
+            brake_when_isolated: true,
+            target: Some(Vector3::new(1000.0, 0.0, 0.0)),
+            ..Flock::default()
+        };
+        flock.update();
+        assert!(flock.boids[0].velocity.magnitude() >= 1.5);
+    }
+
+    #[test]
+    fn turn_rates_must_be_positive_and_finite() {
+        let mut boid = boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+        assert_eq!(boid.set_turn_rate(0.5), Ok(()));
+        assert_eq!(boid.turn_rate(), 0.5);
+
+        for rate in [-1.0, 0.0, f64::NAN, f64::INFINITY].iter().copied() {
+            assert_eq!(
+                boid.set_turn_rate(rate),
+                Err(BoidError::InvalidParameter { name: "turn_rate" })
+            );
+            assert_eq!(boid.turn_rate(), 0.5);
+        }
+    }
+
+    #[test]
+    fn invalid_params_change_nothing() {
+        let mut flock = Flock {
+            boids: vec![boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)); 3],
+            ..Flock::default()
+        };
+        let mut params = flock.boids[0].params();
+        params.max_speed = 3.0;
+        params.turn_rate = 0.25;
+        assert_eq!(flock.set_params_all(&params), Ok(()));
+        assert!(flock.boids.iter().all(|boid| boid.params() == params));
+
+        let before = flock.boids.clone();
+        let mut invalid = params;
+        invalid.max_speed = 5.0;
+        invalid.turn_rate = f64::NAN;
+        assert_eq!(
+            flock.set_params_all(&invalid),
+            Err(BoidError::InvalidParameter { name: "turn_rate" })
+        );
+        assert_eq!(flock.boids, before);
+    }
+
+    #[test]
+    fn smoothing_eases_a_neighbor_into_the_alignment_target() {
+        let mut flock = Flock {
+            boids: vec![
+                boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)),
+                boid((1000.0, 0.0, 0.0), (0.0, 1.0, 0.0)),
+            ],
+            neighbor_smoothing: Some(5.0),
+            ..Flock::default()
+        };
+        flock.update();
+        assert_eq!(flock.boids[0].smoothed_heading, None);
+
+        // The neighbor jumps into range
+        let position = flock.boids[0].position + Vector3::new(0.0, 2.0, 0.0);
+        flock.boids[1].position = position;
+        flock.refresh_grid();
+        let (own, neighbor) = (flock.boids[0].velocity, flock.boids[1].velocity);
+        flock.update();
+
+        let alpha = 1.0 - (-1.0f64 / 5.0).exp();
+        let heading = flock.boids[0].smoothed_heading.unwrap();
+        assert!((heading - (own + (neighbor - own) * alpha)).magnitude() < 1e-12);
+        let first_gap = heading.angle(neighbor).0;
+        assert!(first_gap > 1.0);
+
+        // The target keeps closing in on the neighbor's heading over the following frames
+        for _ in 0..10 {
+            flock.update();
+        }
+        let heading = flock.boids[0].smoothed_heading.unwrap();
+        assert!(heading.angle(flock.boids[1].velocity).0 < first_gap / 2.0);
+    }
+
+    #[test]
+    fn set_params_all_configures_every_boid() {
+        let mut flock = Flock {
+            boids: (0..5)
+                .map(|i| boid((i as f64 * 30.0, 0.0, 0.0), (0.0, i as f64 * 0.3, 0.0)))
+                .collect(),
+            ..Flock::default()
+        };
+        let motion: Vec<_> = flock
+            .boids
+            .iter()
+            .map(|boid| (boid.position, boid.velocity))
+            .collect();
+
+        let mut params = flock.boids[0].params();
+        params.max_speed = 4.0;
+        params.min_speed = 0.5;
+        params.max_force = 0.1;
+        params.turn_rate = 1.0;
+        params.weights.cohesion = 2.0;
+        assert_eq!(flock.set_params_all(&params), Ok(()));
+
+        for (boid, (position, velocity)) in flock.boids.iter().zip(motion) {
+            assert_eq!(boid.params(), params);
+            assert_eq!((boid.position, boid.velocity), (position, velocity));
+        }
+
+        // The minimum speed has to fit under the maximum
+        for min_speed in [-1.0, 5.0, f64::NAN].iter().copied() {
+            let invalid = BoidParams {
+                min_speed,
+                ..params
+            };
+            assert_eq!(
+                flock.set_params_all(&invalid),
+                Err(BoidError::InvalidParameter { name: "min_speed" })
+            );
+        }
+
+        // Speed and force limits must be finite and not negative, and are reported by name
+        for value in [-1.0, f64::NAN, f64::INFINITY].iter().copied() {
+            let invalid = BoidParams {
+                max_speed: value,
+                ..params
+            };
+            assert_eq!(
+                flock.set_params_all(&invalid),
+                Err(BoidError::InvalidParameter { name: "max_speed" })
+            );
+            let invalid = BoidParams {
+                max_force: value,
+                ..params
+            };
+            assert_eq!(
+                flock.set_params_all(&invalid),
+                Err(BoidError::InvalidParameter { name: "max_force" })
+            );
+        }
+    }
+
+    #[test]
+    fn accumulated_forces_are_integrated_once() {
+        let mut single = boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+        single.acceleration = Vector3::new(0.0, 0.0, 0.0);
+        single.accumulate_force(Vector3::new(0.1, 0.0, 0.0));
+        single.accumulate_force(Vector3::new(0.0, 0.2, 0.0));
+        assert_eq!(single.acceleration, Vector3::new(0.1, 0.2, 0.0));
+
+        single.integrate_acceleration(false);
+        assert_eq!(single.velocity, Vector3::new(1.1, 0.2, 0.0));
+        assert_eq!(single.position, single.velocity);
+        assert_eq!(single.acceleration, Vector3::new(0.1, 0.2, 0.0));
+
+        // Updating the same way applies the net force the boid reports
+        let mut flock = Flock {
+            boids: vec![
+                boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)),
+                boid((10.0, 5.0, 0.0), (0.0, 1.0, 0.0)),
+            ],
+            integration: Integration::Accumulated,
+            ..Flock::default()
+        };
+        let before = flock.boids.clone();
+        flock.update();
+        for (old, new) in before.iter().zip(flock.boids.iter()) {
+            assert!(new.acceleration.magnitude() > 0.0);
+            assert!((new.velocity - old.velocity - new.acceleration).magnitude() < 1e-12);
+            assert!((new.position - old.position - new.velocity).magnitude() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn perception_radii_follow_the_flock_and_speed() {
+        let mut flock = Flock {
+            boids: vec![
+                boid((0.0, 0.0, 0.0), (0.0, 0.0, 0.0)),
+                boid((100.0, 0.0, 0.0), (2.0, 0.0, 0.0)),
+            ],
+            ..Flock::default()
+        };
+        for boid in flock.boids.iter() {
+            assert_eq!(boid.perception_radii(&flock), flock.perception);
+        }
+
+        // At full speed the radii are stretched by the speed factor
+        flock.perception_speed_factor = 0.5;
+        assert_eq!(flock.boids[0].perception_radii(&flock), flock.perception);
+        let fast = flock.boids[1].perception_radii(&flock);
+        assert_eq!(fast, flock.perception.scaled(1.5));
+        assert_eq!(fast.alignment(), flock.perception.alignment() * 1.5);
+    }
+
+    #[test]
+    fn edge_cohesion_stays_bounded_in_large_flocks() {
+        // A flat sheet of boids, pulled in from one edge
+        let edge_cohesion = |columns: usize, rows: usize, normalization: CohesionNormalization| {
+            let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+                cohesion_normalization: normalization,
+                ..Flock::default()
+            };
+            for i in 0..columns * rows {
+                flock.boids.push(boid(
+                    ((i % columns) as f64, (i / columns) as f64, 0.0),
+                    (1.0, 0.0, 0.0),
+                ));
+            }
+            flock.refresh_grid();
+            flock.boids[columns / 2].cohesion_at(Some(columns / 2), &flock)
+        };
+        let max_force = boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)).max_force;
+
+        // Bounded, the edge of a 1000-boid flock is pulled about as hard as that of a 50-boid one
+        let small = edge_cohesion(10, 5, CohesionNormalization::Bounded).magnitude();
+        let large = edge_cohesion(40, 25, CohesionNormalization::Bounded).magnitude();
+        assert!(small > 0.0 && small <= max_force + 1e-12);
+        assert!(large > 0.0 && large <= max_force + 1e-12);
+        assert!(large < small * 2.0 && small < large * 2.0);
+
+        // Unbounded, the pull grows with the neighbor count
+        let small = edge_cohesion(10, 5, CohesionNormalization::Unbounded).magnitude();
+        let large = edge_cohesion(40, 25, CohesionNormalization::Unbounded).magnitude();
+        assert!(small > max_force);
+        assert!(large > small * 5.0);
+    }
+
+    #[test]
+    fn own_targets_override_the_flock_target() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            target: Some(Vector3::new(50.0, 0.0, 0.0)),
+            ..Flock::default()
+        };
+        let mut sheltering = boid((0.0, 0.0, 0.0), (0.0, 0.0, 0.0));
+        sheltering.target = Some(Vector3::new(-50.0, 0.0, 0.0));
+        flock.boids.push(sheltering);
+        flock.boids.push(boid((0.0, 1.0, 0.0), (0.0, 0.0, 0.0)));
+        flock.boids.push(boid((0.0, -1.0, 0.0), (0.0, 0.0, 0.0)));
+        flock.refresh_grid();
+
+        assert_eq!(
+            flock.target_of(&flock.boids[0]),
+            Some(Vector3::new(-50.0, 0.0, 0.0))
+        );
+        assert_eq!(
+            flock.target_of(&flock.boids[1]),
+            Some(Vector3::new(50.0, 0.0, 0.0))
+        );
+
+        let updated: Vec<Boid3D<f64>> =
+            flock.boids.iter().map(|boid| boid.update(&flock)).collect();
+        assert!(updated[0].velocity.x < 0.0);
+        assert!(updated[1].velocity.x > 0.0);
+        assert!(updated[2].velocity.x > 0.0);
+    }
+
+    #[test]
+    fn separation_can_ignore_neighbors_moving_away() {
+        let pair = |speed: f64| {
+            let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+                separate_only_approaching: true,
+                ..Flock::default()
+            };
+            flock.boids.push(boid((0.0, 0.0, 0.0), (speed, 0.0, 0.0)));
+            flock.boids.push(boid((1.0, 0.0, 0.0), (-speed, 0.0, 0.0)));
+            flock.refresh_grid();
+            flock
+        };
+
+        let parting = pair(-1.0);
+        assert_eq!(
+            parting.boids[0].separate(&parting),
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            parting.boids[1].separate(&parting),
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+
+        let converging = pair(1.0);
+        assert!(converging.boids[0].separate(&converging).x < 0.0);
+        assert!(converging.boids[1].separate(&converging).x > 0.0);
+
+        // Without the option, parting boids still push apart
+        let parting = Flock {
+            separate_only_approaching: false,
+            ..parting
+        };
+        assert!(parting.boids[0].separate(&parting).x < 0.0);
+    }
+
+    #[test]
+    fn fast_boids_perceive_farther() {
+        let sees_neighbor = |speed: f64| {
+            let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+                perception_speed_factor: 1.0,
+                ..Flock::default()
+            };
+            let distance = flock.perception.alignment() * 1.5;
+            let mut subject = boid((0.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+            subject.velocity *= subject.max_speed * speed;
+            flock.boids.push(subject);
+            flock
+                .boids
+                .push(boid((distance, 0.0, 0.0), (0.0, 1.0, 0.0)));
+            flock.refresh_grid();
+            flock.boids[0].align(&flock).y > 0.0
+        };
+
+        assert!(!sees_neighbor(0.1));
+        assert!(sees_neighbor(1.0));
     }
 }
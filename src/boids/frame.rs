@@ -0,0 +1,90 @@
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, Vector3};
+
+/// Build an orthonormal `(forward, side, up)` frame from a heading.
+///
+/// Falls back to the world axes when the heading is zero or vertical.
+pub fn heading_frame<U: BaseNum + Float>(
+    heading: Vector3<U>,
+) -> (Vector3<U>, Vector3<U>, Vector3<U>) {
+    let world_up = Vector3::unit_z();
+    if heading.magnitude2() <= U::epsilon() {
+        return (Vector3::unit_x(), Vector3::unit_y(), world_up);
+    }
+
+    let forward = heading.normalize();
+    let side = world_up.cross(forward);
+    if side.magnitude2() <= U::epsilon() {
+        // Heading is vertical, so pick any perpendicular side vector
+        let side = Vector3::unit_y();
+        return (forward, side, forward.cross(side));
+    }
+    let side = side.normalize();
+    (forward, side, forward.cross(side))
+}
+
+/// Reflect a direction across a plane through the origin with the given normal.
+///
+/// A zero normal leaves the direction unchanged.
+pub fn reflect_vector<U: BaseNum + Float>(
+    vector: Vector3<U>,
+    plane_normal: Vector3<U>,
+) -> Vector3<U> {
+    if plane_normal.magnitude2() <= U::zero() {
+        return vector;
+    }
+    let normal = plane_normal.normalize();
+    vector - normal * ((U::one() + U::one()) * vector.dot(normal))
+}
+
+/// Reflect a point across the plane through `plane_point` with the given normal
+pub fn reflect_point<U: BaseNum + Float>(
+    point: Vector3<U>,
+    plane_normal: Vector3<U>,
+    plane_point: Vector3<U>,
+) -> Vector3<U> {
+    plane_point + reflect_vector(point - plane_point, plane_normal)
+}
+
+/// Reflect the axis-aligned box between `min` and `max` across the plane through `plane_point` with the
+/// given normal, returning the new `(min, max)`.
+///
+/// This is exact when the normal lies along an axis. Across any other plane the reflection is no longer
+/// axis-aligned, so this gives the smallest axis-aligned box around it.
+pub fn reflect_box<U: BaseNum + Float>(
+    min: Vector3<U>,
+    max: Vector3<U>,
+    plane_normal: Vector3<U>,
+    plane_point: Vector3<U>,
+) -> (Vector3<U>, Vector3<U>) {
+    let corner = |index: usize| {
+        let pick = |bit: usize, low: U, high: U| if index & bit == 0 { low } else { high };
+        let corner = Vector3::new(
+            pick(1, min.x, max.x),
+            pick(2, min.y, max.y),
+            pick(4, min.z, max.z),
+        );
+        reflect_point(corner, plane_normal, plane_point)
+    };
+    (1..8)
+        .map(corner)
+        .fold((corner(0), corner(0)), |(low, high), point| {
+            (
+                Vector3::new(low.x.min(point.x), low.y.min(point.y), low.z.min(point.z)),
+                Vector3::new(
+                    high.x.max(point.x),
+                    high.y.max(point.y),
+                    high.z.max(point.z),
+                ),
+            )
+        })
+}
+
+/// Round each component of a point to the nearest multiple of `cell`, leaving it unchanged if `cell` is
+/// not positive
+pub fn snap<U: BaseNum + Float>(point: Vector3<U>, cell: U) -> Vector3<U> {
+    if cell > U::zero() {
+        point.map(|value| (value / cell).round() * cell)
+    } else {
+        point
+    }
+}
@@ -1,23 +1,153 @@
 use std::ops::Mul;
 
-use cgmath::{BaseNum, InnerSpace, Vector2, Vector3, num_traits::Float};
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, Vector2, Vector3};
 
-/// Limit the magnitude of a vector
-pub fn limit_magnitude_v2<U: BaseNum + Float>(vector: Vector2<U>, max_magnitude: U) -> Vector2<U> {
+/// Raise the magnitude of a vector to at least `min_magnitude`.
+///
+/// Zero vectors have no direction to grow in, so they are left alone, as are vectors that are not finite.
+pub fn raise_magnitude<U: BaseNum + Float, V: InnerSpace<Scalar = U>>(
+    vector: V,
+    min_magnitude: U,
+) -> V {
+    let magnitude = vector.magnitude();
+    if magnitude > U::zero() && magnitude.is_finite() && magnitude < min_magnitude {
+        vector.mul(min_magnitude / magnitude)
+    } else {
+        vector
+    }
+}
+
+/// Limit the magnitude of a vector, without letting a bad input turn into `NaN`.
+///
+/// Vectors with a `NaN` or infinite component become zero. A `NaN` or positive infinite `max_magnitude`
+/// means there is no limit, and a negative one is treated as zero. Finite vectors too long to square are
+/// still scaled down correctly.
+pub fn limit_magnitude<U: BaseNum + Float, V: InnerSpace<Scalar = U>>(
+    vector: V,
+    max_magnitude: U,
+) -> V {
     let mag_sq = vector.magnitude2();
-    if mag_sq > max_magnitude.powi(2) {
-        vector.mul(max_magnitude / mag_sq.sqrt())
+    if mag_sq.is_nan() {
+        return V::zero();
+    }
+
+    // Measure vectors too long to square on a shrunk copy, so only truly infinite ones are rejected
+    let scale = if mag_sq.is_infinite() {
+        U::max_value()
+    } else {
+        U::one()
+    };
+    let shrunk = vector.mul(U::one() / scale);
+    let mag_sq = shrunk.magnitude2();
+    if !mag_sq.is_finite() {
+        return V::zero();
+    }
+
+    if max_magnitude.is_nan() || max_magnitude == U::infinity() {
+        return vector;
+    }
+    let max_magnitude = max_magnitude.max(U::zero());
+    if mag_sq.sqrt() > max_magnitude / scale {
+        shrunk.mul(max_magnitude / mag_sq.sqrt())
     } else {
         vector
     }
 }
 
+/// Apply `force` to `velocity` by turning it and rescaling its speed, rather than by vector addition.
+///
+/// Only the part of `force` across the heading is used, to turn the boid, so no single step can flip it
+/// around. The speed is then scaled multiplicatively towards `max_speed`, to the geometric mean of the
+/// two. A stationary boid has no heading, so it takes the force additively.
+pub fn add_force_conserving<U: BaseNum + Float, V: InnerSpace<Scalar = U>>(
+    velocity: V,
+    force: V,
+    max_speed: U,
+) -> V {
+    let speed = velocity.magnitude();
+    if speed <= U::zero() || !speed.is_finite() {
+        return limit_magnitude(velocity + force, max_speed);
+    }
+
+    let heading = velocity.mul(U::one() / speed);
+    let turned = velocity + (force - heading.mul(force.dot(heading)));
+    if turned.magnitude2() > U::zero() && turned.magnitude2().is_finite() {
+        turned
+            .normalize()
+            .mul((speed * max_speed.max(U::zero())).sqrt())
+    } else {
+        velocity
+    }
+}
+
+/// Limit the magnitude of a vector
+pub fn limit_magnitude_v2<U: BaseNum + Float>(vector: Vector2<U>, max_magnitude: U) -> Vector2<U> {
+    limit_magnitude(vector, max_magnitude)
+}
+
 /// Limit the magnitude of a vector
 pub fn limit_magnitude_v3<U: BaseNum + Float>(vector: Vector3<U>, max_magnitude: U) -> Vector3<U> {
-    let mag_sq = vector.magnitude2();
-    if mag_sq > max_magnitude.powi(2) {
-        vector.mul(max_magnitude / mag_sq.sqrt())
+    limit_magnitude(vector, max_magnitude)
+}
+
+/// Implement Reynolds: steer towards `desired` at `speed`, with the steering force limited to `max_force`.
+///
+/// A zero `desired` brakes, steering against `velocity`.
+pub fn steer_v3<U: BaseNum + Float>(
+    desired: Vector3<U>,
+    velocity: Vector3<U>,
+    speed: U,
+    max_force: U,
+) -> Vector3<U> {
+    let target = if desired.magnitude2() > U::zero() {
+        desired.normalize().mul(speed)
     } else {
-        vector
+        Vector3::new(U::zero(), U::zero(), U::zero())
+    };
+    limit_magnitude_v3(target - velocity, max_force)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::limit_magnitude_v3;
+
+    #[test]
+    fn limit_magnitude_survives_bad_inputs() {
+        let vector = Vector3::new(3.0, 4.0, 0.0);
+
+        // A NaN anywhere in the vector gives zero
+        assert_eq!(
+            limit_magnitude_v3(Vector3::new(f64::NAN, 1.0, 0.0), 1.0),
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            limit_magnitude_v3(Vector3::new(f64::INFINITY, 1.0, 0.0), 1.0),
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+
+        // A NaN or infinite limit is no limit, and a negative one is zero
+        assert_eq!(limit_magnitude_v3(vector, f64::NAN), vector);
+        assert_eq!(limit_magnitude_v3(vector, f64::INFINITY), vector);
+        assert_eq!(
+            limit_magnitude_v3(vector, -1.0),
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+
+        // Zero stays zero, and ordinary vectors are cut down to the limit
+        assert_eq!(
+            limit_magnitude_v3(Vector3::new(0.0, 0.0, 0.0), 1.0),
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+        assert_eq!(limit_magnitude_v3(vector, 10.0), vector);
+        assert!(
+            (limit_magnitude_v3(vector, 1.0) - Vector3::new(0.6, 0.8, 0.0)).magnitude() < 1e-12
+        );
+
+        // Vectors too long to square are still scaled correctly
+        let huge = limit_magnitude_v3(Vector3::new(f64::MAX, f64::MAX, 0.0), 2.0);
+        assert!((huge.magnitude() - 2.0).abs() < 1e-12);
+        assert!((huge.x - huge.y).abs() < 1e-12);
     }
 }
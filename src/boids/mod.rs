@@ -1,13 +1,21 @@
 use cgmath::{num_traits::Float, BaseNum, Vector3};
 
-use crate::flock::Flock;
+use crate::{
+    error::{scalar, BoidError},
+    flock::Flock,
+};
 
 pub mod boid2d;
 pub mod boid3d;
 mod convert;
-mod limits;
+pub(crate) mod frame;
+pub(crate) mod limits;
 
-/// Defines the force weights for a boid
+/// Defines the force weights for a boid.
+///
+/// Weights added after the first release are marked `serde(default)`, so configs saved before they
+/// existed still load, with the new weights set to zero. Their `Default` is zero too, so both ways of
+/// leaving a weight out agree.
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BoidWeights<U: BaseNum> {
@@ -15,20 +23,129 @@ pub struct BoidWeights<U: BaseNum> {
     pub cohesion: U,
     pub separation: U,
     pub targeting: U,
+    /// Weight of the pull towards the whole-flock centroid
+    #[cfg_attr(feature = "serde", serde(default = "cgmath::num_traits::Zero::zero"))]
+    pub global_cohesion: U,
+    /// Weight of the pull back towards the flock's anchor
+    #[cfg_attr(feature = "serde", serde(default = "cgmath::num_traits::Zero::zero"))]
+    pub anchor: U,
 }
 
 impl<U: BaseNum + Float> Default for BoidWeights<U> {
     fn default() -> Self {
+        Self::try_default().expect("scalar type cannot represent the default weights")
+    }
+}
+
+impl<U: BaseNum + Float> BoidWeights<U> {
+    /// Get the default weights, failing if the scalar type cannot represent them
+    pub fn try_default() -> Result<Self, BoidError> {
+        Ok(Self {
+            alignment: scalar(1.5)?,
+            cohesion: scalar(1.0)?,
+            separation: scalar(1.0)?,
+            targeting: scalar(0.0003)?,
+            global_cohesion: U::zero(),
+            anchor: U::zero(),
+        })
+    }
+
+    /// Blend these weights towards a panicked state.
+    ///
+    /// At `level = 1`, separation is multiplied by `separation_boost` and cohesion is disabled. At
+    /// `level = 0`, the weights are unchanged.
+    pub fn panicked(&self, level: U, separation_boost: U) -> Self {
         Self {
-            alignment: U::from(1.5).unwrap(),
-            cohesion: U::from(1.0).unwrap(),
-            separation: U::from(1.0).unwrap(),
-            targeting: U::from(0.0003).unwrap(),
+            separation: self.separation * (U::one() + (separation_boost - U::one()) * level),
+            cohesion: self.cohesion * (U::one() - level),
+            ..*self
+        }
+    }
+
+    /// Linearly interpolate between these weights and `other`, where `t = 0` gives `self` and `t = 1`
+    /// gives `other`
+    pub fn lerp(&self, other: &Self, t: U) -> Self {
+        let mix = |a: U, b: U| a + (b - a) * t;
+        Self {
+            alignment: mix(self.alignment, other.alignment),
+            cohesion: mix(self.cohesion, other.cohesion),
+            separation: mix(self.separation, other.separation),
+            targeting: mix(self.targeting, other.targeting),
+            global_cohesion: mix(self.global_cohesion, other.global_cohesion),
+            anchor: mix(self.anchor, other.anchor),
+        }
+    }
+
+    /// Pass every weight through `f`, for converting between scalar types
+    pub(crate) fn map<V: BaseNum>(&self, f: impl Fn(U) -> V) -> BoidWeights<V> {
+        BoidWeights {
+            alignment: f(self.alignment),
+            cohesion: f(self.cohesion),
+            separation: f(self.separation),
+            targeting: f(self.targeting),
+            global_cohesion: f(self.global_cohesion),
+            anchor: f(self.anchor),
+        }
+    }
+
+    /// Check that every weight is a finite number, naming the first that is not.
+    ///
+    /// Weights missing from a config that predates them already deserialize to zero, matching
+    /// `Default`. Tools that write configs without knowing about newer weights may instead fill them
+    /// with placeholders such as `NaN`, which this rejects rather than guesses at.
+    pub fn validate(&self) -> Result<(), BoidError> {
+        let weights = [
+            ("alignment", self.alignment),
+            ("cohesion", self.cohesion),
+            ("separation", self.separation),
+            ("targeting", self.targeting),
+            ("global_cohesion", self.global_cohesion),
+            ("anchor", self.anchor),
+        ];
+        match weights.iter().find(|(_, weight)| !weight.is_finite()) {
+            Some((name, _)) => Err(BoidError::InvalidParameter { name }),
+            None => Ok(()),
         }
     }
 }
 
-/// Common code across all boids no matter their dimensions
+/// The tuning parameters of a boid, bundled so many boids can be configured the same way at once
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BoidParams<U: BaseNum + Float> {
+    pub max_speed: U,
+    /// Slowest a moving boid is allowed to go, from zero up to `max_speed`
+    pub min_speed: U,
+    pub max_force: U,
+    /// Maximum turn rate, in radians per update
+    pub turn_rate: U,
+    pub weights: BoidWeights<U>,
+}
+
+impl<U: BaseNum + Float> BoidParams<U> {
+    /// Check that these parameters can be applied, naming the first invalid one if any
+    pub fn validate(&self) -> Result<(), BoidError> {
+        if !(self.turn_rate > U::zero() && self.turn_rate.is_finite()) {
+            return Err(BoidError::InvalidParameter { name: "turn_rate" });
+        }
+        if !(self.max_speed >= U::zero() && self.max_speed.is_finite()) {
+            return Err(BoidError::InvalidParameter { name: "max_speed" });
+        }
+        if !(self.min_speed >= U::zero() && self.min_speed <= self.max_speed) {
+            return Err(BoidError::InvalidParameter { name: "min_speed" });
+        }
+        if !(self.max_force >= U::zero() && self.max_force.is_finite()) {
+            return Err(BoidError::InvalidParameter { name: "max_force" });
+        }
+        Ok(())
+    }
+}
+
+/// Common code across all boids no matter their dimensions.
+///
+/// The behaviors ending in `_at` take the index the boid is stored at in the flock, which they skip as
+/// the boid itself, so distinct boids at the same position still see each other. The plain versions are
+/// for boids that are not stored in the flock, and skip any boid exactly at their position instead.
 pub trait Boid<T: Boid<T, U>, U: BaseNum + Float> {
     /// Get the current position of the boid
     fn position(&self) -> Vector3<U>;
@@ -39,24 +156,194 @@ pub trait Boid<T: Boid<T, U>, U: BaseNum + Float> {
     /// Get the current acceleration of the boid
     fn acceleration(&self) -> Vector3<U>;
 
+    /// Get the sub-group the boid belongs to
+    fn group(&self) -> u16;
+
+    /// Get the boid's own target, which takes precedence over its group's and the flock's
+    fn target(&self) -> Option<Vector3<U>> {
+        None
+    }
+
+    /// Get the boid's panic level, from `0` (calm) to `1`. Boids that never panic are always calm.
+    fn panic(&self) -> U {
+        U::zero()
+    }
+
+    /// Set the boid's panic level. This does nothing for boids that never panic.
+    fn set_panic(&mut self, _panic: U) {}
+
+    /// Get the fastest the boid is allowed to move
+    fn max_speed(&self) -> U;
+
+    /// Get the radius of the boid's body, used for hit testing
+    fn radius(&self) -> U;
+
+    /// Set the position of the boid
+    fn set_position(&mut self, position: Vector3<U>);
+
+    /// Set the velocity of the boid
+    fn set_velocity(&mut self, velocity: Vector3<U>);
+
+    /// Get a copy of the boid mirrored across the plane through `plane_point` with normal
+    /// `plane_normal`, with every position and direction it holds reflected
+    fn mirror(&self, plane_normal: Vector3<U>, plane_point: Vector3<U>) -> T;
+
     /// Calculate the separation force for this boid
-    fn separate(&self, flock: &Flock<T, U>) -> Vector3<U>;
+    fn separate(&self, flock: &Flock<T, U>) -> Vector3<U> {
+        self.separate_at(None, flock)
+    }
+
+    /// Calculate the separation force for this boid, stored at `index` in `flock`
+    fn separate_at(&self, index: Option<usize>, flock: &Flock<T, U>) -> Vector3<U>;
 
     /// Calculate the alignment force for this boid
-    fn align(&self, flock: &Flock<T, U>) -> Vector3<U>;
+    fn align(&self, flock: &Flock<T, U>) -> Vector3<U> {
+        self.align_at(None, flock)
+    }
+
+    /// Calculate the alignment force for this boid, stored at `index` in `flock`
+    fn align_at(&self, index: Option<usize>, flock: &Flock<T, U>) -> Vector3<U>;
+
+    /// Calculate the cohesion force for this boid.
+    ///
+    /// This steers towards the neighbors' center, limited to `max_force`. With the default
+    /// `CohesionNormalization::Bounded`, the pull stays bounded no matter how many neighbors there are.
+    fn cohesion(&self, flock: &Flock<T, U>) -> Vector3<U> {
+        self.cohesion_at(None, flock)
+    }
+
+    /// Calculate the cohesion force for this boid, stored at `index` in `flock`
+    fn cohesion_at(&self, index: Option<usize>, flock: &Flock<T, U>) -> Vector3<U>;
+
+    /// Get the speed this boid wants to travel at, given how crowded it is
+    fn desired_speed(&self, flock: &Flock<T, U>) -> U {
+        self.desired_speed_at(None, flock)
+    }
 
-    /// Calculate the cohesion force for this boid
-    fn cohesion(&self, flock: &Flock<T, U>) -> Vector3<U>;
+    /// Get the speed this boid, stored at `index` in `flock`, wants to travel at
+    fn desired_speed_at(&self, index: Option<usize>, flock: &Flock<T, U>) -> U;
 
     /// Set the weights for the boid
     fn set_weights(&mut self, weights: BoidWeights<U>);
 
     /// Get the weights for the boid
-    fn get_weights<'a>(&'a self) -> &'a BoidWeights<U>;
+    fn get_weights(&self) -> &BoidWeights<U>;
 
     /// Add another force to the boid and calculate all other internal vectors
     fn with_force(&self, force: Vector3<U>) -> T;
 
     /// Update the boid based on its flock
-    fn update(&self, flock: &Flock<T, U>) -> T;
+    fn update(&self, flock: &Flock<T, U>) -> T {
+        self.update_at(None, flock)
+    }
+
+    /// Update the boid, stored at `index` in `flock`, based on the rest of the flock
+    fn update_at(&self, index: Option<usize>, flock: &Flock<T, U>) -> T;
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector2, Vector3};
+
+    use super::{boid2d::Boid2D, boid3d::Boid3D, BoidWeights};
+    use crate::{error::BoidError, flock::Flock};
+
+    #[test]
+    fn placeholder_weights_are_rejected() {
+        let weights = BoidWeights::<f64>::default();
+        assert_eq!(weights.validate(), Ok(()));
+
+        let placeholder = BoidWeights {
+            anchor: f64::NAN,
+            ..weights
+        };
+        assert_eq!(
+            placeholder.validate(),
+            Err(BoidError::InvalidParameter { name: "anchor" })
+        );
+        let infinite = BoidWeights {
+            global_cohesion: f64::INFINITY,
+            ..weights
+        };
+        assert_eq!(
+            infinite.validate(),
+            Err(BoidError::InvalidParameter {
+                name: "global_cohesion"
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn old_configs_load_with_new_weights_zeroed() {
+        use serde::{
+            de::value::{Error, MapDeserializer},
+            Deserialize,
+        };
+
+        // Saved before global cohesion and the anchor existed
+        let old = [
+            ("alignment", 1.5),
+            ("cohesion", 1.0),
+            ("separation", 2.0),
+            ("targeting", 0.5),
+        ];
+        let weights =
+            BoidWeights::<f64>::deserialize(MapDeserializer::<_, Error>::new(old.iter().copied()))
+                .unwrap();
+        assert_eq!(
+            weights,
+            BoidWeights {
+                alignment: 1.5,
+                cohesion: 1.0,
+                separation: 2.0,
+                targeting: 0.5,
+                global_cohesion: 0.0,
+                anchor: 0.0,
+            }
+        );
+        assert_eq!(
+            weights.global_cohesion,
+            BoidWeights::<f64>::default().global_cohesion
+        );
+        assert_eq!(weights.anchor, BoidWeights::<f64>::default().anchor);
+    }
+
+    #[test]
+    fn moving_boids_keep_their_min_speed() {
+        // Braking with nothing around would otherwise bring these boids to a stop
+        let mut flock3: Flock<Boid3D<f64>, f64> = Flock {
+            boids: vec![
+                Boid3D {
+                    min_speed: 0.5,
+                    ..Boid3D::new_with_angle(Vector3::new(0.0, 0.0, 0.0), 0.0)
+                },
+                Boid3D {
+                    min_speed: 0.5,
+                    velocity: Vector3::new(0.0, 0.0, 0.0),
+                    ..Boid3D::new_with_angle(Vector3::new(500.0, 0.0, 0.0), 0.0)
+                },
+            ],
+            brake_when_isolated: true,
+            ..Flock::default()
+        };
+        let mut flock2: Flock<Boid2D<f64>, f64> = Flock {
+            boids: vec![Boid2D {
+                min_speed: 0.5,
+                ..Boid2D::new_with_angle(Vector2::new(0.0, 0.0), 0.0)
+            }],
+            brake_when_isolated: true,
+            ..Flock::default()
+        };
+
+        for _ in 0..100 {
+            flock3.update();
+            flock2.update();
+            assert!(flock3.boids[0].velocity.magnitude() >= 0.5 - 1e-12);
+            assert!(flock2.boids[0].velocity.magnitude() >= 0.5 - 1e-12);
+            assert_eq!(flock3.boids[1].velocity, Vector3::new(0.0, 0.0, 0.0));
+        }
+        assert!((flock3.boids[0].velocity.magnitude() - 0.5).abs() < 1e-12);
+        assert!((flock2.boids[0].velocity.magnitude() - 0.5).abs() < 1e-12);
+    }
 }
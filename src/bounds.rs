@@ -0,0 +1,33 @@
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+/// An axis-aligned box in world space
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Bounds<U: BaseNum + Float> {
+    /// Minimum corner
+    pub min: Vector3<U>,
+    /// Maximum corner
+    pub max: Vector3<U>,
+}
+
+impl<U: BaseNum + Float> Bounds<U> {
+    /// Create new bounds from two corners
+    pub fn new(min: Vector3<U>, max: Vector3<U>) -> Self {
+        Self { min, max }
+    }
+
+    /// Get the size of the bounds along each axis
+    pub fn size(&self) -> Vector3<U> {
+        self.max - self.min
+    }
+
+    /// Check if a point lies inside the bounds (edges included)
+    pub fn contains(&self, point: Vector3<U>) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+            && point.z >= self.min.z
+            && point.z <= self.max.z
+    }
+}
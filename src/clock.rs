@@ -0,0 +1,76 @@
+use cgmath::{num_traits::Float, BaseNum};
+
+/// A source of elapsed time, so timing-dependent features do not have to read the system clock.
+///
+/// Use [`SystemClock`] on platforms with `std::time`, or [`ManualClock`] to feed time in yourself (for
+/// example on wasm, or in deterministic replays).
+pub trait Clock<U: BaseNum + Float> {
+    /// Get the time passed since the previous call, in seconds
+    fn tick(&mut self) -> U;
+}
+
+/// A clock that reads `std::time::Instant`.
+///
+/// This is not available on `wasm32-unknown-unknown`, where `Instant` cannot be read.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    last: std::time::Instant,
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+impl SystemClock {
+    /// Create a clock that starts counting from now
+    pub fn new() -> Self {
+        Self {
+            last: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+impl<U: BaseNum + Float> Clock<U> for SystemClock {
+    fn tick(&mut self) -> U {
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        U::from(elapsed).unwrap()
+    }
+}
+
+/// A clock that only moves when told to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ManualClock<U: BaseNum + Float> {
+    pending: U,
+}
+
+impl<U: BaseNum + Float> ManualClock<U> {
+    /// Create a clock with no time pending
+    pub fn new() -> Self {
+        Self { pending: U::zero() }
+    }
+
+    /// Add `dt` seconds, to be reported by the next tick
+    pub fn advance(&mut self, dt: U) {
+        self.pending += dt;
+    }
+}
+
+impl<U: BaseNum + Float> Default for ManualClock<U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<U: BaseNum + Float> Clock<U> for ManualClock<U> {
+    fn tick(&mut self) -> U {
+        std::mem::replace(&mut self.pending, U::zero())
+    }
+}
@@ -0,0 +1,199 @@
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+use crate::{
+    boids::{boid3d::Boid3D, BoidWeights},
+    flock::Flock,
+};
+
+/// Parameters shared by every boid in a [`CompactFlock`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BoidTemplate<U: BaseNum + Float> {
+    pub max_speed: U,
+    #[cfg_attr(feature = "serde", serde(default = "cgmath::num_traits::Zero::zero"))]
+    pub min_speed: U,
+    pub max_force: U,
+    pub r: U,
+    pub weights: BoidWeights<U>,
+    pub panic_decay: U,
+    pub radius: U,
+}
+
+impl<U: BaseNum + Float> BoidTemplate<U> {
+    /// Extract the shared parameters of a boid
+    pub fn from_boid(boid: &Boid3D<U>) -> Self {
+        Self {
+            max_speed: boid.max_speed,
+            min_speed: boid.min_speed,
+            max_force: boid.max_force,
+            r: boid.r,
+            weights: boid.weights,
+            panic_decay: boid.panic_decay,
+            radius: boid.radius,
+        }
+    }
+}
+
+/// A compact checkpoint of a flock, which stores each distinct set of boid parameters once.
+///
+/// Boids refer to their parameters by an index into `templates`, so a flock whose boids mostly share
+/// parameters only stores its per-boid kinematic state, groups, and own targets per boid. Every flock
+/// setting is kept in `settings`, following `convert`: cached state, history, and the step hooks are
+/// dropped. Transient per-boid state such as panic levels is not stored either, and is reset when
+/// restoring.
+/// The regular `Flock` serialization remains the default.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CompactFlock<U: BaseNum + Float> {
+    /// Every distinct set of boid parameters, in the order they first appear
+    pub templates: Vec<BoidTemplate<U>>,
+    /// Index into `templates` of each boid's parameters
+    pub template_indices: Vec<usize>,
+    pub positions: Vec<Vector3<U>>,
+    pub velocities: Vec<Vector3<U>>,
+    pub accelerations: Vec<Vector3<U>>,
+    pub groups: Vec<u16>,
+    pub targets: Vec<Option<Vector3<U>>>,
+    /// The flock's settings, with no boids
+    pub settings: Flock<Boid3D<U>, U>,
+}
+
+impl<U: BaseNum + Float> CompactFlock<U> {
+    /// Reconstruct the full flock from this checkpoint.
+    ///
+    /// Boids are rebuilt for as long as every per-boid array has an entry, and left out if their
+    /// template index is out of range.
+    pub fn restore(&self) -> Flock<Boid3D<U>, U> {
+        let boids = self
+            .template_indices
+            .iter()
+            .zip(self.positions.iter())
+            .zip(self.velocities.iter())
+            .zip(self.accelerations.iter())
+            .zip(self.groups.iter())
+            .zip(self.targets.iter())
+            .filter_map(
+                |(((((index, position), velocity), acceleration), group), target)| {
+                    let template = self.templates.get(*index)?;
+                    Some(Boid3D {
+                        position: *position,
+                        velocity: *velocity,
+                        acceleration: *acceleration,
+                        last_velocity: *velocity,
+                        max_speed: template.max_speed,
+                        min_speed: template.min_speed,
+                        max_force: template.max_force,
+                        r: template.r,
+                        weights: template.weights,
+                        panic: U::zero(),
+                        panic_decay: template.panic_decay,
+                        group: *group,
+                        radius: template.radius,
+                        smoothed_heading: None,
+                        smoothed_center: None,
+                        target: *target,
+                    })
+                },
+            )
+            .collect();
+
+        let mut flock = self.settings.with_boids(boids);
+        flock.refresh_grid();
+        flock
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Create a compact checkpoint of this flock
+    pub fn to_compact(&self) -> CompactFlock<U> {
+        // Intern each boid's parameters. Flocks rarely have more than a handful of distinct ones.
+        let mut templates: Vec<BoidTemplate<U>> = Vec::new();
+        let template_indices = self
+            .boids
+            .iter()
+            .map(|boid| {
+                let template = BoidTemplate::from_boid(boid);
+                match templates.iter().position(|known| *known == template) {
+                    Some(index) => index,
+                    None => {
+                        templates.push(template);
+                        templates.len() - 1
+                    }
+                }
+            })
+            .collect();
+
+        CompactFlock {
+            templates,
+            template_indices,
+            positions: self.boids.iter().map(|boid| boid.position).collect(),
+            velocities: self.boids.iter().map(|boid| boid.velocity).collect(),
+            accelerations: self.boids.iter().map(|boid| boid.acceleration).collect(),
+            groups: self.boids.iter().map(|boid| boid.group).collect(),
+            targets: self.boids.iter().map(|boid| boid.target).collect(),
+            settings: self.with_boids(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::{
+        boids::boid3d::Boid3D,
+        flock::{Flock, SeparationPriority},
+        obstacle::Obstacle,
+    };
+
+    fn flock() -> Flock<Boid3D<f64>, f64> {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            target: Some(Vector3::new(10.0, 0.0, 0.0)),
+            drag: 0.05,
+            seed: 7,
+            step: 12,
+            separation_priority: SeparationPriority::BySize,
+            anchor: Some(Vector3::new(0.0, 5.0, 0.0)),
+            ..Flock::default()
+        };
+        flock.group_targets.insert(1, Vector3::new(0.0, -10.0, 0.0));
+        flock.obstacles.push(Obstacle::Sphere {
+            center: Vector3::new(3.0, 3.0, 0.0),
+            radius: 1.0,
+        });
+        for i in 0..5 {
+            let mut boid = Boid3D::new_with_velocity(
+                Vector3::new(i as f64, 2.0 * i as f64, -1.0),
+                Vector3::new(0.5, -0.25, 0.1 * i as f64),
+            );
+            boid.acceleration = Vector3::new(0.01, 0.0, -0.02);
+            boid.group = (i % 2) as u16;
+            flock.boids.push(boid);
+        }
+        flock.boids[3].target = Some(Vector3::new(-4.0, 0.0, 0.0));
+        flock
+    }
+
+    #[test]
+    fn compact_round_trip_matches_homogeneous_flock() {
+        let flock = flock();
+        let compact = flock.to_compact();
+        assert_eq!(compact.templates.len(), 1);
+
+        // Flocks hold callbacks, so they are compared through their debug form
+        assert_eq!(format!("{:?}", compact.restore()), format!("{:?}", flock));
+    }
+
+    #[test]
+    fn boid_parameters_are_interned() {
+        let mut flock = flock();
+        flock.boids[1].max_speed = 5.0;
+        flock.boids[4].max_speed = 5.0;
+        flock.boids[2].radius = 3.0;
+
+        let compact = flock.to_compact();
+        assert_eq!(compact.templates.len(), 3);
+        assert_eq!(compact.template_indices, vec![0, 1, 2, 0, 1]);
+        assert_eq!(compact.restore().boids, flock.boids);
+    }
+}
@@ -0,0 +1,107 @@
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+use crate::boids::{frame::heading_frame, Boid};
+
+/// Holds a follower boid at a fixed offset from a parent boid
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Constraint<U: BaseNum + Float> {
+    /// Index of the constrained boid
+    pub follower: usize,
+    /// Index of the boid being followed
+    pub parent: usize,
+    /// Desired offset in the parent's frame (`x` forward, `y` to the side, `z` up)
+    pub offset: Vector3<U>,
+    /// How strongly the follower is pulled to its slot each update, from `0` (not at all) to `1` (rigid)
+    pub stiffness: U,
+}
+
+impl<U: BaseNum + Float> Constraint<U> {
+    /// Create a new rigid constraint
+    pub fn new(follower: usize, parent: usize, offset: Vector3<U>) -> Self {
+        Self {
+            follower,
+            parent,
+            offset,
+            stiffness: U::one(),
+        }
+    }
+
+    /// Get the world-space position the follower should occupy
+    pub fn slot<T: Boid<T, U>>(&self, parent: &T) -> Vector3<U> {
+        let (forward, side, up) = heading_frame(parent.velocity());
+        parent.position() + forward * self.offset.x + side * self.offset.y + up * self.offset.z
+    }
+
+    /// Pull the follower towards its slot.
+    ///
+    /// Constraints referring to missing boids, or to the same boid twice, are ignored.
+    pub fn apply<T: Boid<T, U>>(&self, boids: &mut [T]) {
+        if self.follower == self.parent
+            || self.follower >= boids.len()
+            || self.parent >= boids.len()
+        {
+            return;
+        }
+
+        let slot = self.slot(&boids[self.parent]);
+        let parent_velocity = boids[self.parent].velocity();
+        let follower = &mut boids[self.follower];
+
+        // Blend position and velocity towards the slot
+        let position = follower.position();
+        let velocity = follower.velocity();
+        follower.set_position(position + (slot - position) * self.stiffness);
+        follower.set_velocity(velocity + (parent_velocity - velocity) * self.stiffness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{MetricSpace, Vector3};
+
+    use super::Constraint;
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    #[test]
+    fn follower_holds_offset_from_moving_parent() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock.boids.push(Boid3D::new_with_velocity(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.5, 0.0),
+        ));
+        flock.boids.push(Boid3D::new_with_velocity(
+            Vector3::new(20.0, 20.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+        ));
+        flock
+            .boids
+            .push(Boid3D::new_with_angle(Vector3::new(-40.0, 0.0, 0.0), 0.0));
+        flock
+            .constraints
+            .push(Constraint::new(1, 0, Vector3::new(-3.0, 2.0, 0.0)));
+
+        let free_start = flock.boids[2].position;
+        for _ in 0..10 {
+            flock.update();
+            let slot = flock.constraints[0].slot(&flock.boids[0]);
+            assert!(flock.boids[1].position.distance(slot) < 1e-9);
+            assert!(flock.boids[1].velocity.distance(flock.boids[0].velocity) < 1e-9);
+        }
+        assert!(flock.boids[2].position != free_start);
+    }
+
+    #[test]
+    fn soft_constraint_closes_part_of_the_gap() {
+        let mut boids: Vec<Boid3D<f64>> = vec![
+            Boid3D::new_with_angle(Vector3::new(0.0, 0.0, 0.0), 0.0),
+            Boid3D::new_with_angle(Vector3::new(10.0, 0.0, 0.0), 0.0),
+        ];
+        let constraint = Constraint {
+            stiffness: 0.5,
+            ..Constraint::new(1, 0, Vector3::new(-2.0, 0.0, 0.0))
+        };
+        constraint.apply(&mut boids);
+        assert!((boids[1].position.x - 4.0).abs() < 1e-12);
+    }
+}
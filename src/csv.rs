@@ -0,0 +1,126 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+use crate::{
+    boids::{boid3d::Boid3D, Boid},
+    error::{scalar, BoidError},
+    flock::Flock,
+    precision::single_precision,
+};
+
+/// Header row written by `Flock::write_csv`
+const CSV_HEADER: &str = "index,px,py,pz,vx,vy,vz";
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Write the position and velocity of every boid as CSV, one row per boid after a header row of
+    /// `index,px,py,pz,vx,vy,vz`.
+    ///
+    /// Values are written with enough digits to read back exactly.
+    pub fn write_csv(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "{}", CSV_HEADER)?;
+        for (index, boid) in self.boids.iter().enumerate() {
+            let (position, velocity) = (boid.position(), boid.velocity());
+            write!(w, "{}", index)?;
+            for value in [
+                position.x, position.y, position.z, velocity.x, velocity.y, velocity.z,
+            ] {
+                if single_precision::<U>() {
+                    write!(w, ",{}", value.to_f32().unwrap_or(f32::NAN))?;
+                } else {
+                    write!(w, ",{}", value.to_f64().unwrap_or(f64::NAN))?;
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Read a flock back from CSV written by `write_csv`.
+    ///
+    /// Boids are created in row order with default parameters, and the given position and velocity.
+    /// Every other flock setting is left at its default. The header row and blank lines are skipped.
+    pub fn read_csv(r: impl Read) -> Result<Self, BoidError> {
+        let mut flock = Flock::try_default()?;
+        for (number, line) in BufReader::new(r).lines().enumerate() {
+            let line = line.map_err(|error| BoidError::Deserialize {
+                reason: error.to_string(),
+            })?;
+            let line = line.trim();
+            if line.is_empty() || line == CSV_HEADER {
+                continue;
+            }
+
+            let row = number + 1;
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() != 7 {
+                return Err(BoidError::Deserialize {
+                    reason: format!("row {} has {} columns instead of 7", row, fields.len()),
+                });
+            }
+            let mut values = [U::zero(); 6];
+            for (value, field) in values.iter_mut().zip(fields[1..].iter()) {
+                let invalid = |error: std::num::ParseFloatError| BoidError::Deserialize {
+                    reason: format!("row {}: {}", row, error),
+                };
+                *value = if single_precision::<U>() {
+                    scalar(f64::from(field.parse::<f32>().map_err(invalid)?))?
+                } else {
+                    scalar(field.parse::<f64>().map_err(invalid)?)?
+                };
+            }
+
+            let mut boid = Boid3D::try_new_with_angle(
+                Vector3::new(values[0], values[1], values[2]),
+                U::zero(),
+            )?;
+            boid.velocity = Vector3::new(values[3], values[4], values[5]);
+            boid.last_velocity = boid.velocity;
+            flock.boids.push(boid);
+        }
+        flock.refresh_grid();
+        Ok(flock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::{boids::boid3d::Boid3D, error::BoidError, flock::Flock};
+
+    #[test]
+    fn round_trips_positions_and_velocities() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            boids: (0..4)
+                .map(|i| Boid3D::new_with_angle(Vector3::new(i as f64 * 3.7, 0.1, -2.0), 0.3))
+                .collect(),
+            ..Flock::default()
+        };
+        flock.update();
+
+        let mut csv = Vec::new();
+        flock.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().next(), Some("index,px,py,pz,vx,vy,vz"));
+        assert_eq!(csv.lines().count(), 5);
+
+        let read: Flock<Boid3D<f64>, f64> = Flock::read_csv(csv.as_bytes()).unwrap();
+        assert_eq!(read.boids.len(), flock.boids.len());
+        for (read, boid) in read.boids.iter().zip(flock.boids.iter()) {
+            assert_eq!(read.position, boid.position);
+            assert_eq!(read.velocity, boid.velocity);
+        }
+    }
+
+    #[test]
+    fn malformed_rows_are_rejected() {
+        let csv = "index,px,py,pz,vx,vy,vz\n0,1,2,3\n";
+        assert!(matches!(
+            Flock::<Boid3D<f32>, f32>::read_csv(csv.as_bytes()),
+            Err(BoidError::Deserialize { .. })
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+use cgmath::{num_traits::Float, BaseNum, MetricSpace, Vector3};
+
+use crate::{boids::Boid, flock::Flock};
+
+/// The new state of one boid that changed between two frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BoidDelta<U: BaseNum + Float> {
+    /// Index of the changed boid
+    pub index: usize,
+    /// New position of the boid
+    pub position: Vector3<U>,
+    /// New velocity of the boid
+    pub velocity: Vector3<U>,
+}
+
+/// Everything that changed in a flock between two frames, produced by `Flock::diff`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FlockDiff<T, U: BaseNum + Float> {
+    /// Number of boids in the new flock
+    pub len: usize,
+    /// Boids present in both frames whose position or velocity changed
+    pub changed: Vec<BoidDelta<U>>,
+    /// Boids added after the end of the old flock, in index order
+    pub added: Vec<T>,
+}
+
+impl<T, U: BaseNum + Float> FlockDiff<T, U> {
+    /// Check if applying this diff would change nothing
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.added.is_empty()
+    }
+}
+
+impl<T: Boid<T, U> + Clone, U: BaseNum + Float> Flock<T, U> {
+    /// List what changed since `previous`: boids whose position or velocity moved more than
+    /// `threshold`, along with any boids added or removed.
+    ///
+    /// Boids are matched by index, so removals show up as the flock getting shorter. Changed entries hold
+    /// the new state rather than a difference, and added boids are carried whole, so applying the diff to
+    /// `previous` reproduces this flock exactly.
+    pub fn diff(&self, previous: &Flock<T, U>, threshold: U) -> FlockDiff<T, U> {
+        let shared = self.boids.len().min(previous.boids.len());
+        let changed = self.boids[..shared]
+            .iter()
+            .zip(previous.boids.iter())
+            .enumerate()
+            .filter(|(_, (boid, old))| {
+                boid.position().distance(old.position()) > threshold
+                    || boid.velocity().distance(old.velocity()) > threshold
+            })
+            .map(|(index, (boid, _))| BoidDelta {
+                index,
+                position: boid.position(),
+                velocity: boid.velocity(),
+            })
+            .collect();
+
+        FlockDiff {
+            len: self.boids.len(),
+            changed,
+            added: self.boids[shared..].to_vec(),
+        }
+    }
+
+    /// Apply changes produced by `diff`, then refresh the spatial grid.
+    ///
+    /// The flock is cut down to the old length the diff was taken against, changed entries are applied,
+    /// and the added boids are pushed onto the end. Entries for boids that do not exist in this flock are
+    /// ignored.
+    pub fn apply_diff(&mut self, diff: &FlockDiff<T, U>) {
+        self.boids
+            .truncate(diff.len.saturating_sub(diff.added.len()));
+        for delta in diff.changed.iter() {
+            if let Some(boid) = self.boids.get_mut(delta.index) {
+                boid.set_position(delta.position);
+                boid.set_velocity(delta.velocity);
+            }
+        }
+        self.boids.extend(diff.added.iter().cloned());
+        self.refresh_grid();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    type State = Vec<(Vector3<f64>, Vector3<f64>)>;
+
+    fn state(flock: &Flock<Boid3D<f64>, f64>) -> State {
+        flock
+            .boids
+            .iter()
+            .map(|boid| (boid.position, boid.velocity))
+            .collect()
+    }
+
+    fn flock(count: usize) -> Flock<Boid3D<f64>, f64> {
+        Flock {
+            boids: (0..count)
+                .map(|i| Boid3D::new_with_angle(Vector3::new(i as f64 * 10.0, 0.0, 0.0), 0.0))
+                .collect(),
+            ..Flock::default()
+        }
+    }
+
+    #[test]
+    fn unchanged_boids_produce_no_entries() {
+        let previous = flock(4);
+        let mut current = previous.clone();
+        current.boids[2].position.y = 1.0;
+        current.boids[3].position.y = 1e-6;
+
+        let diff = current.diff(&previous, 1e-3);
+        assert_eq!(diff.len, 4);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].index, 2);
+        assert!(diff.added.is_empty());
+        assert!(previous.diff(&previous, 0.0).is_empty());
+    }
+
+    #[test]
+    fn diff_then_apply_reproduces_the_flock() {
+        let previous = flock(5);
+
+        // A step of motion
+        let mut moved = previous.clone();
+        moved.update();
+        let mut replica = previous.clone();
+        replica.apply_diff(&moved.diff(&previous, 0.0));
+        assert_eq!(state(&replica), state(&moved));
+
+        // Boids added
+        let mut grown = moved.clone();
+        grown
+            .boids
+            .push(Boid3D::new_with_angle(Vector3::new(0.0, 50.0, 0.0), 1.0));
+        replica.apply_diff(&grown.diff(&moved, 0.0));
+        assert_eq!(state(&replica), state(&grown));
+        assert_eq!(replica.boids[5], grown.boids[5]);
+
+        // Boids removed
+        let mut shrunk = grown.clone();
+        shrunk.boids.truncate(2);
+        shrunk.boids[1].velocity.y = 0.5;
+        replica.apply_diff(&shrunk.diff(&grown, 0.0));
+        assert_eq!(state(&replica), state(&shrunk));
+    }
+}
@@ -0,0 +1,148 @@
+use cgmath::{num_traits::Float, BaseNum};
+
+use crate::{boids::Boid, clock::Clock, flock::Flock};
+
+/// Advances a flock at a fixed simulation rate, no matter how often it is driven
+#[derive(Debug, Clone)]
+pub struct FlockDriver<T: Boid<T, U>, U: BaseNum + Float> {
+    /// The flock being simulated
+    pub flock: Flock<T, U>,
+    /// Boids as they were before the most recent simulation step
+    pub previous: Vec<T>,
+    /// Length of one simulation step
+    pub sim_dt: U,
+    /// Time accumulated but not yet simulated
+    pub accumulator: U,
+    /// Most simulation steps a single `advance` will run. Any time still owed after that is dropped, so
+    /// one slow frame cannot leave the flock trying to catch up forever.
+    pub max_steps: usize,
+}
+
+impl<T: Boid<T, U> + Clone, U: BaseNum + Float> FlockDriver<T, U>
+where
+    T: std::marker::Send,
+    T: std::marker::Sync,
+    U: std::marker::Sync,
+{
+    /// Wrap a flock to be stepped every `sim_dt` units of time
+    pub fn new(flock: Flock<T, U>, sim_dt: U) -> Self {
+        Self {
+            previous: flock.boids.clone(),
+            flock,
+            sim_dt,
+            accumulator: U::zero(),
+            max_steps: 8,
+        }
+    }
+
+    /// Add `real_dt` of elapsed time and run every whole simulation step that now fits.
+    ///
+    /// Returns the number of steps taken. The leftover time is kept for the next call, unless more than
+    /// `max_steps` steps were owed, in which case it is dropped.
+    pub fn advance(&mut self, real_dt: U) -> usize {
+        #[cfg(feature = "puffin")]
+        puffin::profile_function!();
+
+        if self.sim_dt <= U::zero() {
+            return 0;
+        }
+
+        self.accumulator += real_dt.max(U::zero());
+        let mut steps = 0;
+        while self.accumulator >= self.sim_dt {
+            if steps >= self.max_steps {
+                self.accumulator = U::zero();
+                break;
+            }
+            self.previous = self.flock.boids.clone();
+            self.flock.update();
+            self.accumulator -= self.sim_dt;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Advance by however much time `clock` reports has passed since it was last ticked
+    pub fn advance_with(&mut self, clock: &mut impl Clock<U>) -> usize {
+        let real_dt = clock.tick();
+        self.advance(real_dt)
+    }
+
+    /// Get how far the leftover time is through the next step, from `0` to `1`.
+    ///
+    /// Renderers can use this to blend between `previous` and the current boids.
+    pub fn alpha(&self) -> U {
+        if self.sim_dt <= U::zero() {
+            return U::zero();
+        }
+        (self.accumulator / self.sim_dt).min(U::one())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::FlockDriver;
+    use crate::clock::{Clock, ManualClock};
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    fn driver() -> FlockDriver<Boid3D<f64>, f64> {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock
+            .boids
+            .push(Boid3D::new_with_angle(Vector3::new(0.0, 0.0, 0.0), 0.0));
+        FlockDriver::new(flock, 0.25)
+    }
+
+    #[test]
+    fn irregular_frames_run_whole_steps() {
+        let mut driver = driver();
+        let frames = [0.1, 0.3, 0.05, 0.6, 0.2, 0.0, 0.25, 0.5];
+        let steps: usize = frames.iter().map(|dt| driver.advance(*dt)).sum();
+
+        // 2.0 units of time is exactly 8 steps
+        assert_eq!(steps, 8);
+        assert_eq!(driver.flock.step, 8);
+        assert!(driver.accumulator.abs() < 1e-9);
+
+        assert_eq!(driver.advance(0.125), 0);
+        assert!((driver.alpha() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn long_frames_are_capped() {
+        let mut driver = driver();
+        driver.max_steps = 3;
+        assert_eq!(driver.advance(10.0), 3);
+        assert_eq!(driver.accumulator, 0.0);
+        assert_eq!(driver.advance(0.25), 1);
+    }
+
+    /// A clock that reports a fixed sequence of frame times
+    struct ScriptedClock(std::vec::IntoIter<f64>);
+
+    impl Clock<f64> for ScriptedClock {
+        fn tick(&mut self) -> f64 {
+            self.0.next().unwrap_or(0.0)
+        }
+    }
+
+    #[test]
+    fn clocks_drive_the_driver_deterministically() {
+        let mut driver = driver();
+        let mut clock = ScriptedClock(vec![0.2, 0.2, 0.6, 0.0].into_iter());
+        let steps: Vec<usize> = (0..4).map(|_| driver.advance_with(&mut clock)).collect();
+        assert_eq!(steps, vec![0, 1, 3, 0]);
+        assert_eq!(driver.flock.step, 4);
+
+        // A manual clock only reports the time fed to it, once
+        let mut clock = ManualClock::new();
+        assert_eq!(driver.advance_with(&mut clock), 0);
+        clock.advance(0.125);
+        clock.advance(0.125);
+        assert_eq!(driver.advance_with(&mut clock), 1);
+        assert_eq!(driver.advance_with(&mut clock), 0);
+        assert_eq!(driver.flock.step, 5);
+    }
+}
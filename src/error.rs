@@ -0,0 +1,244 @@
+use std::fmt;
+
+use cgmath::num_traits::NumCast;
+
+/// Everything that can go wrong in the fallible `try_` APIs of this crate
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoidError {
+    /// A constant could not be represented by the scalar type
+    ScalarConversion { value: f64 },
+    /// A parameter was out of its allowed range
+    InvalidParameter { name: &'static str },
+    /// Saved data could not be read back
+    Deserialize { reason: String },
+    /// A buffer did not have the length it needed to have
+    LengthMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for BoidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoidError::ScalarConversion { value } => {
+                write!(f, "the scalar type cannot represent {}", value)
+            }
+            BoidError::InvalidParameter { name } => write!(f, "invalid value for {}", name),
+            BoidError::Deserialize { reason } => write!(f, "could not read saved data: {}", reason),
+            BoidError::LengthMismatch { expected, found } => {
+                write!(f, "expected {} items, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BoidError {}
+
+/// Convert a constant to the scalar type, failing instead of panicking if it cannot be represented
+pub(crate) fn scalar<U: NumCast>(value: f64) -> Result<U, BoidError> {
+    U::from(value).ok_or(BoidError::ScalarConversion { value })
+}
+
+/// Check that a buffer holds exactly `expected` items
+pub(crate) fn check_length(expected: usize, found: usize) -> Result<(), BoidError> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(BoidError::LengthMismatch { expected, found })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::FpCategory,
+        ops::{
+            Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign,
+        },
+    };
+
+    use cgmath::{
+        num_traits::{Float, Num, NumCast, One, ToPrimitive, Zero},
+        Vector3,
+    };
+
+    use super::{scalar, BoidError};
+    use crate::{
+        boids::{boid2d::Boid2D, boid3d::Boid3D, BoidWeights},
+        flock::Flock,
+    };
+
+    /// A float that can only be converted from whole numbers, like a fixed-point type without fractions
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+    struct Coarse(f64);
+
+    macro_rules! binary_ops {
+        ($($trait:ident $method:ident $assign:ident $assign_method:ident),*) => {$(
+            impl $trait for Coarse {
+                type Output = Self;
+                fn $method(self, other: Self) -> Self {
+                    Coarse(self.0.$method(other.0))
+                }
+            }
+            impl $assign for Coarse {
+                fn $assign_method(&mut self, other: Self) {
+                    self.0.$assign_method(other.0)
+                }
+            }
+        )*};
+    }
+    binary_ops!(
+        Add add AddAssign add_assign,
+        Sub sub SubAssign sub_assign,
+        Mul mul MulAssign mul_assign,
+        Div div DivAssign div_assign,
+        Rem rem RemAssign rem_assign
+    );
+
+    impl Neg for Coarse {
+        type Output = Self;
+        fn neg(self) -> Self {
+            Coarse(-self.0)
+        }
+    }
+
+    impl Zero for Coarse {
+        fn zero() -> Self {
+            Coarse(0.0)
+        }
+        fn is_zero(&self) -> bool {
+            self.0 == 0.0
+        }
+    }
+
+    impl One for Coarse {
+        fn one() -> Self {
+            Coarse(1.0)
+        }
+    }
+
+    impl Num for Coarse {
+        type FromStrRadixErr = <f64 as Num>::FromStrRadixErr;
+        fn from_str_radix(text: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr> {
+            f64::from_str_radix(text, radix).map(Coarse)
+        }
+    }
+
+    impl ToPrimitive for Coarse {
+        fn to_i64(&self) -> Option<i64> {
+            self.0.to_i64()
+        }
+        fn to_u64(&self) -> Option<u64> {
+            self.0.to_u64()
+        }
+        fn to_f64(&self) -> Option<f64> {
+            Some(self.0)
+        }
+    }
+
+    impl NumCast for Coarse {
+        fn from<N: ToPrimitive>(n: N) -> Option<Self> {
+            n.to_f64().filter(|value| value.fract() == 0.0).map(Coarse)
+        }
+    }
+
+    macro_rules! forward {
+        ($($name:ident),*) => {$(
+            fn $name(self) -> Self {
+                Coarse(self.0.$name())
+            }
+        )*};
+    }
+
+    macro_rules! forward_binary {
+        ($($name:ident),*) => {$(
+            fn $name(self, other: Self) -> Self {
+                Coarse(self.0.$name(other.0))
+            }
+        )*};
+    }
+
+    macro_rules! constant {
+        ($($name:ident),*) => {$(
+            fn $name() -> Self {
+                Coarse(f64::$name())
+            }
+        )*};
+    }
+
+    #[allow(deprecated)]
+    impl Float for Coarse {
+        constant!(
+            nan,
+            infinity,
+            neg_infinity,
+            neg_zero,
+            min_value,
+            min_positive_value,
+            max_value
+        );
+        forward!(
+            floor, ceil, round, trunc, fract, abs, signum, recip, sqrt, exp, exp2, ln, log2, log10,
+            cbrt, sin, cos, tan, asin, acos, atan, exp_m1, ln_1p, sinh, cosh, tanh, asinh, acosh,
+            atanh
+        );
+        forward_binary!(powf, log, max, min, abs_sub, hypot, atan2);
+
+        fn is_nan(self) -> bool {
+            self.0.is_nan()
+        }
+        fn is_infinite(self) -> bool {
+            self.0.is_infinite()
+        }
+        fn is_finite(self) -> bool {
+            self.0.is_finite()
+        }
+        fn is_normal(self) -> bool {
+            self.0.is_normal()
+        }
+        fn classify(self) -> FpCategory {
+            self.0.classify()
+        }
+        fn is_sign_positive(self) -> bool {
+            self.0.is_sign_positive()
+        }
+        fn is_sign_negative(self) -> bool {
+            self.0.is_sign_negative()
+        }
+        fn mul_add(self, a: Self, b: Self) -> Self {
+            Coarse(self.0.mul_add(a.0, b.0))
+        }
+        fn powi(self, n: i32) -> Self {
+            Coarse(self.0.powi(n))
+        }
+        fn sin_cos(self) -> (Self, Self) {
+            let (sin, cos) = self.0.sin_cos();
+            (Coarse(sin), Coarse(cos))
+        }
+        fn integer_decode(self) -> (u64, i16, i8) {
+            Float::integer_decode(self.0)
+        }
+    }
+
+    #[test]
+    fn unrepresentable_defaults_are_errors_not_panics() {
+        let unrepresentable = BoidError::ScalarConversion { value: 0.03 };
+        assert_eq!(scalar::<Coarse>(0.03), Err(unrepresentable.clone()));
+        assert_eq!(scalar::<Coarse>(2.0), Ok(Coarse(2.0)));
+
+        let origin = Vector3::new(Coarse(0.0), Coarse(0.0), Coarse(0.0));
+        assert_eq!(
+            Boid3D::try_new_with_angle(origin, Coarse(0.0)).unwrap_err(),
+            unrepresentable
+        );
+        assert_eq!(
+            Boid2D::try_new_with_angle(origin.truncate(), Coarse(0.0)).unwrap_err(),
+            unrepresentable
+        );
+        assert!(matches!(
+            BoidWeights::<Coarse>::try_default(),
+            Err(BoidError::ScalarConversion { .. })
+        ));
+
+        // Every flock setting defaults to a whole number, so only the boids are affected
+        assert!(Flock::<Boid3D<Coarse>, Coarse>::try_default().is_ok());
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use cgmath::{num_traits::Float, BaseNum, MetricSpace};
+
+use crate::{boids::Boid, flock::Flock};
+
+/// Something that happened to the flock during an update
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlockEvent {
+    /// A boid moved into or out of the flock's `event_bounds`
+    BoundaryCross { index: usize },
+    /// A boid came within `waypoint_radius` of its target
+    WaypointReached { index: usize },
+    /// Two boids started overlapping, treating each as a sphere of its `radius`. `a` is always the
+    /// lower index.
+    Collision { a: usize, b: usize },
+}
+
+/// A callback run for every event found during `Flock::update`.
+///
+/// The callback is shared rather than owned, so cloning a flock (including the snapshots kept for
+/// `rewind`) keeps reporting to the same callback.
+#[derive(Clone)]
+pub struct EventCallback(Arc<Mutex<dyn FnMut(FlockEvent) + Send>>);
+
+impl EventCallback {
+    /// Wrap a closure as an event callback
+    pub fn new(callback: impl FnMut(FlockEvent) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(callback)))
+    }
+
+    /// Run the callback for a single event
+    pub fn call(&self, event: FlockEvent) {
+        // A callback that panicked before can still be called again
+        let mut callback = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        callback(event);
+    }
+}
+
+impl fmt::Debug for EventCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EventCallback")
+    }
+}
+
+/// A callback given the whole flock at a fixed point of every `Flock::update`.
+///
+/// Like [`EventCallback`], this is shared between clones of the flock. The callback must not update
+/// the flock it is given.
+pub struct StepHook<T: Boid<T, U>, U: BaseNum + Float>(Arc<Mutex<StepFn<T, U>>>);
+
+/// The closure behind a [`StepHook`]
+type StepFn<T, U> = dyn FnMut(&mut Flock<T, U>) + Send;
+
+impl<T: Boid<T, U>, U: BaseNum + Float> StepHook<T, U> {
+    /// Wrap a closure as a step hook
+    pub fn new(hook: impl FnMut(&mut Flock<T, U>) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(hook)))
+    }
+
+    /// Run the hook on `flock`
+    pub fn call(&self, flock: &mut Flock<T, U>) {
+        let mut hook = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        hook(flock);
+    }
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Clone for StepHook<T, U> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> fmt::Debug for StepHook<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StepHook")
+    }
+}
+
+/// The per-boid state events are detected from, taken before and after each update
+pub(crate) struct EventState {
+    inside: Vec<bool>,
+    arrived: Vec<bool>,
+    touching: BTreeSet<(usize, usize)>,
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Install a callback run for every event found during `update`
+    pub fn set_on_event(&mut self, callback: impl FnMut(FlockEvent) + Send + 'static) {
+        self.on_event = Some(EventCallback::new(callback));
+    }
+
+    /// Install a hook run at the start of every `update`, before any steering is worked out
+    pub fn set_pre_step(&mut self, hook: impl FnMut(&mut Flock<T, U>) + Send + 'static) {
+        self.pre_step = Some(StepHook::new(hook));
+    }
+
+    /// Install a hook run at the end of every `update`, once the boids have moved
+    pub fn set_post_step(&mut self, hook: impl FnMut(&mut Flock<T, U>) + Send + 'static) {
+        self.post_step = Some(StepHook::new(hook));
+    }
+
+    /// Record the state events are detected from, or `None` if nobody is listening
+    pub(crate) fn event_state(&self) -> Option<EventState> {
+        self.on_event.as_ref()?;
+
+        let inside = match self.event_bounds {
+            Some(bounds) => self
+                .boids
+                .iter()
+                .map(|boid| bounds.contains(boid.position()))
+                .collect(),
+            None => Vec::new(),
+        };
+        let arrived = match self.waypoint_radius {
+            Some(radius) => self
+                .boids
+                .iter()
+                .map(|boid| {
+                    self.target_of(boid)
+                        .is_some_and(|target| boid.position().distance(target) <= radius)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let max_radius = self
+            .boids
+            .iter()
+            .fold(U::zero(), |max, boid| max.max(boid.radius()));
+        let mut touching = BTreeSet::new();
+        for (a, boid) in self.boids.iter().enumerate() {
+            self.for_each_within(
+                boid.position(),
+                boid.radius() + max_radius,
+                |b, distance| {
+                    if a < b && distance < boid.radius() + self.boids[b].radius() {
+                        touching.insert((a, b));
+                    }
+                },
+            );
+        }
+
+        Some(EventState {
+            inside,
+            arrived,
+            touching,
+        })
+    }
+
+    /// Report every change between two recorded states to the callback, in index order
+    pub(crate) fn emit_events(&self, before: &EventState, after: &EventState) {
+        let callback = match self.on_event.as_ref() {
+            Some(callback) => callback,
+            None => return,
+        };
+
+        for (index, (was, is)) in before.inside.iter().zip(after.inside.iter()).enumerate() {
+            if was != is {
+                callback.call(FlockEvent::BoundaryCross { index });
+            }
+        }
+        for (index, (was, is)) in before.arrived.iter().zip(after.arrived.iter()).enumerate() {
+            if *is && !*was {
+                callback.call(FlockEvent::WaypointReached { index });
+            }
+        }
+        for &(a, b) in after.touching.difference(&before.touching) {
+            callback.call(FlockEvent::Collision { a, b });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use cgmath::Vector3;
+
+    use super::FlockEvent;
+    use crate::{boids::boid3d::Boid3D, bounds::Bounds, flock::Flock};
+
+    #[test]
+    fn crossing_the_bounds_fires_once() {
+        let mut flock = Flock {
+            boids: vec![
+                Boid3D::new_with_velocity(Vector3::new(9.5, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0)),
+                Boid3D::new_with_velocity(
+                    Vector3::new(-80.0, 0.0, 0.0),
+                    Vector3::new(0.0, 0.0, 0.0),
+                ),
+            ],
+            event_bounds: Some(Bounds::new(
+                Vector3::new(-100.0, -100.0, -100.0),
+                Vector3::new(10.0, 100.0, 100.0),
+            )),
+            ..Flock::default()
+        };
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        flock.set_on_event(move |event| sink.lock().unwrap().push(event));
+
+        for _ in 0..5 {
+            flock.update();
+        }
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![FlockEvent::BoundaryCross { index: 0 }]
+        );
+    }
+
+    #[test]
+    fn step_hooks_run_around_the_steering() {
+        let mut flock = Flock {
+            boids: vec![Boid3D::new_with_velocity(
+                Vector3::new(0.0, 0.0, 0.0),
+                Vector3::new(0.0, 0.0, 0.0),
+            )],
+            ..Flock::default()
+        };
+        flock.set_pre_step(|flock| flock.target = Some(Vector3::new(100.0, 0.0, 0.0)));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        flock.set_post_step(move |flock| sink.lock().unwrap().push(flock.boids[0].position));
+
+        // The target set just before steering already pulls the boid this frame
+        flock.update();
+        assert!(flock.boids[0].velocity.x > 0.0);
+        assert_eq!(*seen.lock().unwrap(), vec![flock.boids[0].position]);
+    }
+}
@@ -1,17 +1,465 @@
-use cgmath::{num_traits::Float, BaseNum, Vector3};
+use std::collections::{HashMap, VecDeque};
+
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, MetricSpace, Vector3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
 
-use crate::boids::Boid;
+use crate::{
+    boids::{
+        frame::{heading_frame, reflect_box, reflect_point, snap},
+        limits::{limit_magnitude_v3, steer_v3},
+        Boid, BoidWeights,
+    },
+    bounds::Bounds,
+    constraint::Constraint,
+    error::{check_length, BoidError},
+    events::{EventCallback, StepHook},
+    flow::FlowField,
+    grid::SpatialGrid,
+    obstacle::{detour_direction, Obstacle},
+    octree::Octree,
+    perception::PerceptionConfig,
+    schedule::WeightSchedule,
+    snap::PositionSnap,
+    startle::Startle,
+    statistics::MetricsCollector,
+    sweep::SweepAndPrune,
+};
+
+/// Scales the separation radius depending on where a neighbor is relative to a boid's heading
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SeparationAnisotropy<U: BaseNum + Float> {
+    /// Multiplier for neighbors directly ahead
+    pub front: U,
+    /// Multiplier for neighbors directly to the side
+    pub side: U,
+    /// Multiplier for neighbors directly behind
+    pub back: U,
+}
+
+impl<U: BaseNum + Float> SeparationAnisotropy<U> {
+    /// Get the largest multiplier in any direction
+    pub fn max_scale(&self) -> U {
+        self.front.max(self.side).max(self.back)
+    }
+
+    /// Get the radius multiplier for a neighbor at `offset` from a boid moving along `heading`.
+    ///
+    /// Bearings between the front, side, and back are linearly blended by the cosine of the bearing.
+    pub fn scale(&self, heading: Vector3<U>, offset: Vector3<U>) -> U {
+        let length = heading.magnitude() * offset.magnitude();
+        if length <= U::zero() {
+            return self.side;
+        }
+
+        let cos = heading.dot(offset) / length;
+        if cos >= U::zero() {
+            self.side + (self.front - self.side) * cos
+        } else {
+            self.side - (self.back - self.side) * cos
+        }
+    }
+}
+
+/// Stretches each boid's perception into an ellipsoid along its heading, so it sees farther ahead than
+/// to the sides
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PerceptionEllipsoid<U: BaseNum + Float> {
+    /// Multiplier for the perception radius along the heading, both ahead and behind
+    pub forward: U,
+    /// Multiplier for the perception radius across the heading
+    pub lateral: U,
+}
+
+impl<U: BaseNum + Float> PerceptionEllipsoid<U> {
+    /// Create a perception ellipsoid, naming the first multiplier that is not positive and finite if any
+    pub fn new(forward: U, lateral: U) -> Result<Self, BoidError> {
+        let check = |scale: U, name: &'static str| {
+            if scale > U::zero() && scale.is_finite() {
+                Ok(scale)
+            } else {
+                Err(BoidError::InvalidParameter { name })
+            }
+        };
+        Ok(Self {
+            forward: check(forward, "forward")?,
+            lateral: check(lateral, "lateral")?,
+        })
+    }
+
+    /// Get the largest multiplier in any direction
+    pub fn max_scale(&self) -> U {
+        self.forward.max(self.lateral)
+    }
+
+    /// Check if a neighbor at `offset` from a boid moving along `heading` is strictly inside the
+    /// ellipsoid built from `radius`.
+    ///
+    /// Boids that are not moving have no heading, so they perceive a plain sphere of `radius`.
+    pub fn contains(&self, heading: Vector3<U>, offset: Vector3<U>, radius: U) -> bool {
+        if heading.magnitude2() <= U::zero() {
+            return offset.magnitude2() < radius * radius;
+        }
+
+        // Split the offset into parts along and across the heading, in units of each semi-axis
+        let along = offset.dot(heading.normalize());
+        let across2 = (offset.magnitude2() - along * along).max(U::zero());
+        let forward = radius * self.forward;
+        let lateral = radius * self.lateral;
+        (along * along) / (forward * forward) + across2 / (lateral * lateral) < U::one()
+    }
+}
+
+/// Configuration for V-formation steering, where each boid seeks a slot diagonally behind the nearest
+/// boid ahead of it
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct VFormation<U: BaseNum + Float> {
+    /// Angle between the slot and straight behind the boid ahead, in radians
+    pub slot_angle: U,
+    /// Distance from the boid ahead to the slot
+    pub spacing: U,
+    /// Weight of the formation force
+    pub weight: U,
+}
+
+/// Configuration for obstacle avoidance, where each boid casts a fan of sensor rays ahead of it and
+/// steers towards whichever way is most open
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ObstacleAvoidance<U: BaseNum + Float> {
+    /// Number of sensors spread in a ring around the one pointing straight ahead. With `2`, they point
+    /// left and right of the heading; with `4`, also up and down.
+    pub sensor_count: usize,
+    /// Angle between each ring sensor and straight ahead, in radians
+    pub spread: U,
+    /// Length of every sensor ray. This needs to be longer than a boid travels in one update, or it can
+    /// step right through a thin obstacle.
+    pub range: U,
+    /// Weight of the avoidance force
+    pub weight: U,
+}
+
+impl<U: BaseNum + Float> ObstacleAvoidance<U> {
+    /// Get the unit direction of every sensor for a boid moving along `heading`, starting with the one
+    /// straight ahead
+    pub fn sensor_directions(&self, heading: Vector3<U>) -> Vec<Vector3<U>> {
+        let (forward, side, up) = heading_frame(heading);
+        let (sin, cos) = self.spread.sin_cos();
+        let step = U::from(std::f64::consts::PI * 2.0).unwrap()
+            / U::from(self.sensor_count.max(1)).unwrap();
+
+        let mut directions = vec![forward];
+        for index in 0..self.sensor_count {
+            let around = step * U::from(index).unwrap();
+            directions.push(forward * cos + (side * around.cos() + up * around.sin()) * sin);
+        }
+        directions
+    }
+}
+
+/// Slows boids down as their neighborhood gets more crowded
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DensitySpeed<U: BaseNum + Float> {
+    /// Number of neighbors at which a boid wants to travel at half its maximum speed
+    pub half_density: U,
+    /// Smallest fraction of the maximum speed a boid will slow to
+    pub min_fraction: U,
+}
+
+impl<U: BaseNum + Float> DensitySpeed<U> {
+    /// Get the fraction of the maximum speed for a boid with `neighbors` neighbors.
+    ///
+    /// This follows `1 / (1 + neighbors / half_density)`, floored at `min_fraction`.
+    pub fn scale(&self, neighbors: usize) -> U {
+        if self.half_density <= U::zero() {
+            return U::one();
+        }
+        let density = U::from(neighbors).unwrap() / self.half_density;
+        (U::one() / (U::one() + density))
+            .max(self.min_fraction)
+            .min(U::one())
+    }
+}
+
+/// Switches for the core steering behaviors. Disabled behaviors are skipped entirely, while the boids'
+/// weights for them are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BehaviorFlags {
+    pub separation: bool,
+    pub alignment: bool,
+    pub cohesion: bool,
+    pub targeting: bool,
+}
+
+impl Default for BehaviorFlags {
+    fn default() -> Self {
+        Self {
+            separation: true,
+            alignment: true,
+            cohesion: true,
+            targeting: true,
+        }
+    }
+}
+
+/// How boids choose between several weighted attractors in `Flock::targets`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TargetPolicy {
+    /// Head for the weighted average of every attractor
+    #[default]
+    WeightedAverage,
+    /// Head for the attractor with the highest weight divided by its distance
+    MostAppealing,
+}
+
+/// The point boids steer towards for cohesion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CohesionTarget {
+    /// The mean position of the neighbors
+    #[default]
+    Mean,
+    /// The component-wise median position of the neighbors, which a single far-off neighbor barely moves.
+    ///
+    /// This is always computed exactly, even if `barnes_hut_theta` is set.
+    Median,
+}
+
+/// How the cohesion force depends on the number of neighbors it pulls towards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CohesionNormalization {
+    /// Steer towards the neighbors' center as Reynolds does, limited to `max_force`, so edge boids
+    /// are pulled in just as hard however many neighbors they have
+    #[default]
+    Bounded,
+    /// Sum a pull towards each neighbor, which is the bounded force times the number of neighbors.
+    /// This is not limited to `max_force`, so edge boids of a large, dense flock are pulled in hard
+    /// enough to crush it.
+    Unbounded,
+}
+
+/// How strongly boids separate from each kind of neighbor.
+///
+/// Each neighbor's push is scaled by its priority, so boids give threats such as predators a wider
+/// berth than their peers. The direction is weighted by priority, and the steering force, limited to
+/// `max_force` as usual, is then scaled by the mean priority of the neighbors, so it is limited to
+/// `max_force` times the highest priority among them. Priorities should not be negative.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SeparationPriority<U: BaseNum + Float> {
+    /// Every neighbor has priority `1`
+    #[default]
+    Uniform,
+    /// A neighbor's priority is its radius divided by the boid's own, so bigger boids are avoided more
+    BySize,
+    /// A neighbor's priority is looked up by its group, with `1` for groups that are not listed
+    ByGroup(HashMap<u16, U>),
+}
+
+/// Controls which state each boid sees while the flock updates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UpdateMode {
+    /// Every boid reacts to the state from before the update, as in Reynolds' original model
+    #[default]
+    Synchronous,
+    /// Boids update one at a time in index order, each seeing the already-updated earlier boids.
+    ///
+    /// This always runs on a single thread and checks every boid directly instead of using the spatial
+    /// indexes or Barnes-Hut tree, since those would go stale mid-update.
+    Asynchronous,
+}
+
+/// Controls how the steering forces of an update move each boid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Integration {
+    /// The separation, alignment, and cohesion forces are applied to the velocity in turn, then the sum
+    /// of every other force, and the boid moves after each of these four steps
+    #[default]
+    PerForce,
+    /// Forces accumulate into each boid's `acceleration`, which is applied to the velocity once before
+    /// the boid moves once
+    Accumulated,
+}
+
+/// SplitMix64 finalizer, used to spread nearby seeds across the seed space
+fn mix_seed(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
 
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+// The skipped fields all default without any bounds, which serde would otherwise add
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "T: serde::Deserialize<'de>, U: serde::Deserialize<'de>"))
+)]
 pub struct Flock<T: Boid<T, U>, U: BaseNum + Float> {
     pub boids: Vec<T>,
-    pub goal_separation: U,
-    pub goal_alignment: U,
-    pub goal_cohesion: U,
+    /// Radii within which boids react to their neighbors
+    pub perception: PerceptionConfig<U>,
+    /// How much the perception radii grow with speed, so fast boids see further ahead and react in time.
+    /// Each boid's radii are scaled by `1 + perception_speed_factor * speed / max_speed`, and `0` turns
+    /// this off.
+    ///
+    /// Cohesion through the Barnes-Hut approximation still uses the unscaled radius.
+    pub perception_speed_factor: U,
+    /// Perception volume stretched along each boid's heading, used for every neighbor a behavior sees.
+    /// `None` keeps perception spherical.
+    ///
+    /// Cohesion through the Barnes-Hut approximation still uses spheres.
+    pub perception_ellipsoid: Option<PerceptionEllipsoid<U>>,
+    /// Half-angle, in radians, of the cone straight behind each boid in which it cannot see neighbors.
+    /// `0` hides nothing.
+    ///
+    /// Cohesion through the Barnes-Hut approximation still sees behind.
+    pub blind_angle: U,
     pub target: Option<Vector3<U>>,
+    /// Per-group targets, which take precedence over `target` for boids in that group
+    pub group_targets: HashMap<u16, Vector3<U>>,
+    /// Weighted attractors as `(point, weight)` pairs, which take precedence over `target` when not empty
+    pub targets: Vec<(Vector3<U>, U)>,
+    /// How boids pick a point to head for from `targets`
+    pub target_policy: TargetPolicy,
+    /// Solid shapes in the world
+    pub obstacles: Vec<Obstacle<U>>,
+    /// Whether boids whose target is hidden behind one of the `obstacles` head for the edge of the
+    /// obstacle instead, so they go around it rather than pressing into it
+    pub targeting_avoids_obstacles: bool,
+    /// Sensor-ray steering around the `obstacles`. `None` disables it.
+    pub obstacle_avoidance: Option<ObstacleAvoidance<U>>,
+    /// Fraction of each neighbor's acceleration blended into its velocity when aligning. `None` disables this.
+    pub alignment_acceleration_blend: Option<U>,
+    /// Time constant, in updates, of a low-pass filter on the neighbor heading and center that 3D boids
+    /// align and cohere with. This smooths out the jumps when neighbors cross the perception radius.
+    /// `None` disables this.
+    pub neighbor_smoothing: Option<U>,
+    /// Formation constraints applied after every update, in order
+    pub constraints: Vec<Constraint<U>>,
+    /// Direction-dependent separation radius. `None` keeps separation isotropic.
+    pub separation_anisotropy: Option<SeparationAnisotropy<U>>,
+    /// Fewest neighbors within separation range before separation kicks in
+    pub separation_min_neighbors: usize,
+    /// How strongly each neighbor is separated from
+    pub separation_priority: SeparationPriority<U>,
+    /// Whether boids only separate from neighbors they are closing in on, ignoring ones that are
+    /// already moving apart from them
+    pub separate_only_approaching: bool,
+    /// Active startle response, if any
+    pub startle: Option<Startle<U>>,
+    /// Fraction of velocity lost to drag each update, from `0` (no drag) to `1`
+    pub drag: U,
+    /// Caps each weighted behavior force at this fraction of the boid's `max_force`. `None` disables this.
+    pub behavior_clamp: Option<U>,
+    /// Caps the sum of a boid's weighted behavior forces in each update, by scaling them all down together.
+    /// This is infinite by default, leaving the sum unbounded.
+    pub max_acceleration: U,
+    /// Slows boids in crowded areas. `None` keeps every boid aiming for its `max_speed`.
+    pub density_speed: Option<DensitySpeed<U>>,
+    /// V-formation steering. `None` disables it.
+    pub v_formation: Option<VFormation<U>>,
+    /// Keyframed weights applied to every boid by `advance_schedule`. `None` leaves weights alone.
+    pub weight_schedule: Option<WeightSchedule<U>>,
+    /// Barnes-Hut opening angle for approximating cohesion. `None` computes cohesion exactly.
+    ///
+    /// This only pays off for large flocks (thousands of boids) with a wide cohesion radius. Small
+    /// values such as `0.3` stay very close to the exact result.
+    pub barnes_hut_theta: Option<U>,
+    /// Tree used for the Barnes-Hut approximation. This is rebuilt by `update` and can be left as `None`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub octree: Option<Octree<U>>,
+    /// Positions of an `f32` flock packed together for `simd::distances`. With the `simd` feature, this
+    /// is rebuilt by `update` and can be left as `None`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub packed_positions: Option<Vec<Vector3<f32>>>,
+    /// Whole-flock centroid used for global cohesion. This is recomputed by `update` and can be left as `None`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub global_centroid: Option<Vector3<U>>,
+    /// Spatial grid used to speed up queries and behaviors. Build it with `rebuild_grid` or
+    /// `enable_neighbor_cache`; `update` keeps it current.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub grid: Option<SpatialGrid<U>>,
+    /// Sweep-and-prune index used to speed up queries and behaviors whenever the spatial grid is not
+    /// in use. Build it with `enable_sweep_and_prune`; `update` keeps it current.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub sweep: Option<SweepAndPrune<U>>,
+    /// Smallest flock that uses the spatial grid. Smaller flocks skip it and check every boid directly,
+    /// which gives the same results faster.
+    ///
+    /// Brute force wins up to roughly 500 to 1000 boids at typical densities, so the default is `768`.
+    /// Set this to `0` to always use the grid.
+    pub grid_min_boids: usize,
+    /// Indices of boids marked for removal by `remove_boid`, which are dropped by `compact`
+    pub pending_removals: Vec<usize>,
+    /// Seed used to derive each boid's random number generator
+    pub seed: u64,
+    /// Number of times the flock has been updated
+    pub step: u64,
+    /// Lattice that neighbor distances are measured on and the boids are snapped to after every
+    /// update, to keep flocks on different machines from drifting apart. The steering is still float
+    /// math, so this is not a guarantee of bit-identical results. `None` keeps full float precision.
+    pub position_snap: Option<PositionSnap>,
+    /// Whether boids see each other's old or already-updated state during `update`
+    pub update_mode: UpdateMode,
+    /// How steering forces are integrated into each boid's motion
+    pub integration: Integration,
+    /// Which point of the neighborhood boids steer towards for cohesion
+    pub cohesion_target: CohesionTarget,
+    /// How the cohesion force depends on the number of neighbors
+    pub cohesion_normalization: CohesionNormalization,
+    /// Neighbors closer than this are left out of the cohesion target, so boids already inside a cluster
+    /// stop pulling further into it. This does not apply to the Barnes-Hut approximation.
+    pub cohesion_dead_zone: U,
+    /// Which steering behaviors are enabled
+    pub behaviors: BehaviorFlags,
+    /// Ambient force fields applied to every boid
+    pub flow_fields: Vec<FlowField<U>>,
+    /// Whether forces only turn each boid, with its speed eased multiplicatively towards `max_speed`,
+    /// instead of being added to its velocity. This keeps headings steadier and never reverses a boid in
+    /// one step, but forces can no longer slow boids down.
+    pub energy_conserving: bool,
+    /// Whether boids with no neighbors to align or cohere with, and nothing to target, brake instead of
+    /// coasting. This lets lost boids slow down so they are easier to regroup.
+    pub brake_when_isolated: bool,
+    /// Point that strays are pulled back towards, as hard as each boid's `anchor` weight says. That
+    /// weight is zero by default, so set it as well. `None` disables this.
+    pub anchor: Option<Vector3<U>>,
+    /// Distance from `anchor` within which boids roam freely
+    pub anchor_radius: U,
+    /// Most recent states before each update, oldest first, used by `rewind`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub history: VecDeque<Flock<T, U>>,
+    /// Number of past states kept in `history`. `0` disables it.
+    pub history_capacity: usize,
+    /// Collector that records the flock's statistics after every update. `None` disables this.
+    pub metrics: Option<MetricsCollector<U>>,
+    /// Box whose crossings are reported as `FlockEvent::BoundaryCross`. `None` disables this.
+    pub event_bounds: Option<Bounds<U>>,
+    /// Distance from its target within which a boid is reported as `FlockEvent::WaypointReached`.
+    /// `None` disables this.
+    pub waypoint_radius: Option<U>,
+    /// Callback run for every event found during `update`. `None` skips looking for events entirely.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub on_event: Option<EventCallback>,
+    /// Hook run at the start of every `update`, after the state is saved to `history` but before any
+    /// steering is worked out. This is dropped when converting the flock to another boid or scalar type.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub pre_step: Option<StepHook<T, U>>,
+    /// Hook run at the end of every `update`, once the boids have moved. This is dropped when converting
+    /// the flock to another boid or scalar type.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub post_step: Option<StepHook<T, U>>,
 }
 
 impl<T: Boid<T, U> + Clone, U: BaseNum + Float> Flock<T, U>
@@ -25,20 +473,268 @@ where
         #[cfg(feature = "puffin")]
         puffin::profile_function!();
 
-        // Handle weather we are running parallel or single-thread
+        // Remember the current state so it can be rewound to
+        if self.history_capacity > 0 {
+            let history = std::mem::take(&mut self.history);
+            let snapshot = self.clone();
+            self.history = history;
+            self.history.push_back(snapshot);
+            while self.history.len() > self.history_capacity {
+                self.history.pop_front();
+            }
+        }
+
+        // Let the caller adjust the flock for this frame
+        if let Some(hook) = self.pre_step.clone() {
+            hook.call(self);
+        }
+
+        let events_before = self.event_state();
+        self.advance_boids();
+
+        // Keep the spatial grid in sync with the new positions
+        self.refresh_grid();
+
+        // Report what changed during this step
+        if let (Some(before), Some(after)) = (events_before, self.event_state()) {
+            self.emit_events(&before, &after);
+        }
+
+        self.step += 1;
+
+        // Log order parameters for this step
+        if self.metrics.is_some() {
+            let statistics = self.statistics();
+            if let Some(metrics) = self.metrics.as_mut() {
+                metrics.record(self.step, &statistics);
+            }
+        }
+
+        // Let any startle decay
+        if let Some(startle) = self.startle.as_mut() {
+            startle.elapsed += U::one();
+            if startle.is_finished() {
+                self.startle = None;
+            }
+        }
+
+        if let Some(hook) = self.post_step.clone() {
+            hook.call(self);
+        }
+    }
+
+    /// Work out the next generation of boids from the current state, writing it to `out` and leaving the
+    /// flock itself untouched.
+    ///
+    /// `out` is cleared and refilled, reusing its allocation. Swapping it into `boids` and calling
+    /// `refresh_grid` moves the boids exactly as `update` would, constraints and any `position_snap`
+    /// included. The rest of `update`'s bookkeeping (history, hooks, events, metrics, the step
+    /// counter, and any startle) is left to the caller.
+    pub fn compute_next(&self, out: &mut Vec<T>) {
+        let mut next = self.with_boids(self.boids.clone());
+        next.advance_boids();
+        out.clear();
+        out.append(&mut next.boids);
+    }
+
+    /// Move every boid one step, then hold constrained boids in formation
+    fn advance_boids(&mut self) {
+        self.global_centroid = self.centroid();
+
+        match self.update_mode {
+            UpdateMode::Synchronous => {
+                // Build the cohesion approximation tree if needed
+                self.octree = self
+                    .barnes_hut_theta
+                    .map(|_| Octree::new(self.boids.iter().map(|boid| boid.position())));
+                self.packed_positions = self.pack_positions();
+
+                // Handle weather we are running parallel or single-thread
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "rayon")] {
+                        let boids = self.boids
+                            .par_iter()
+                            .enumerate()
+                            .map(|(index, boid)| boid.update_at(Some(index), self))
+                            .collect();
+                    } else {
+                        let boids = self.boids
+                            .iter()
+                            .enumerate()
+                            .map(|(index, boid)| boid.update_at(Some(index), self))
+                            .collect();
+                    }
+                }
+                self.boids = boids;
+                self.octree = None;
+                self.packed_positions = None;
+            }
+            UpdateMode::Asynchronous => {
+                // The indexes would go stale as boids move, so check every boid directly
+                let grid = self.grid.take();
+                let sweep = self.sweep.take();
+                for index in 0..self.boids.len() {
+                    let boid = self.boids[index].update_at(Some(index), self);
+                    self.boids[index] = boid;
+                }
+                self.grid = grid;
+                self.sweep = sweep;
+            }
+        }
+        self.global_centroid = None;
+
+        // Hold constrained boids in formation
+        for constraint in self.constraints.iter() {
+            constraint.apply(&mut self.boids);
+        }
+        self.apply_position_snap();
+    }
+
+    /// Update only the boids whose index is `phase` modulo `stride`, with every boid seeing the current
+    /// state of the whole flock.
+    ///
+    /// Calling this for each phase from `0` to `stride - 1` in turn updates every boid exactly once,
+    /// spreading the cost of a full update over `stride` frames. Only the boids themselves and the
+    /// spatial indexes are updated: the step counter, constraints, metrics, events, history, and any
+    /// startle are left for `update`. Does nothing if `stride` is zero.
+    pub fn stride_update(&mut self, stride: usize, phase: usize) {
+        if stride == 0 {
+            return;
+        }
+        let phase = phase % stride;
+        let count = (self.boids.len() + stride - 1 - phase) / stride;
+
+        self.global_centroid = self.centroid();
+        self.octree = self
+            .barnes_hut_theta
+            .map(|_| Octree::new(self.boids.iter().map(|boid| boid.position())));
+        self.packed_positions = self.pack_positions();
+
         cfg_if::cfg_if! {
             if #[cfg(feature = "rayon")] {
-                self.boids = self.boids
-                    .par_iter()
-                    .map(|boid| boid.update(&self.clone()))
+                let updated: Vec<T> = (0..count)
+                    .into_par_iter()
+                    .map(|slot| {
+                        let index = phase + slot * stride;
+                        self.boids[index].update_at(Some(index), self)
+                    })
                     .collect();
             } else {
-                self.boids = self.boids
-                    .iter()
-                    .map(|boid| boid.update(&self.clone()))
+                let updated: Vec<T> = (0..count)
+                    .map(|slot| {
+                        let index = phase + slot * stride;
+                        self.boids[index].update_at(Some(index), self)
+                    })
                     .collect();
             }
         }
+        for (slot, boid) in updated.into_iter().enumerate() {
+            self.boids[phase + slot * stride] = boid;
+        }
+
+        self.octree = None;
+        self.packed_positions = None;
+        self.global_centroid = None;
+        self.apply_position_snap();
+        self.refresh_grid();
+    }
+
+    /// Get the state of the flock after one `update`, leaving this flock untouched, and whether any
+    /// boid's position or velocity changed by more than `U::epsilon()`.
+    ///
+    /// A settled flock reports `false`, so renderers and network code can skip redundant work. The
+    /// returned flock has had a full `update`, so its hooks and event callbacks have run on it.
+    pub fn update_checked(&self) -> (Flock<T, U>, bool) {
+        let mut next = self.clone();
+        next.update();
+
+        let tolerance = U::epsilon() * U::epsilon();
+        let changed = next.boids.len() != self.boids.len()
+            || next
+                .boids
+                .iter()
+                .zip(self.boids.iter())
+                .any(|(after, before)| {
+                    after.position().distance2(before.position()) > tolerance
+                        || after.velocity().distance2(before.velocity()) > tolerance
+                });
+        (next, changed)
+    }
+
+    /// Get a copy of the flock mirrored across the plane through `plane_point` with normal `plane_normal`.
+    ///
+    /// Every boid is mirrored with `Boid::mirror`, along with the targets, anchor, obstacles, flow
+    /// fields, event bounds, and any startle source, so the copy stays the mirror image of this flock as
+    /// both are updated. Boxes only mirror exactly across planes whose normal lies along an axis (see
+    /// `Obstacle::reflect`). Formation offsets are kept as they are, since they are measured in each
+    /// parent's own frame.
+    pub fn mirror(&self, plane_normal: Vector3<U>, plane_point: Vector3<U>) -> Self {
+        let mut mirrored = self.clone();
+        for boid in mirrored.boids.iter_mut() {
+            *boid = boid.mirror(plane_normal, plane_point);
+        }
+        mirrored.target = self
+            .target
+            .map(|target| reflect_point(target, plane_normal, plane_point));
+        for target in mirrored.group_targets.values_mut() {
+            *target = reflect_point(*target, plane_normal, plane_point);
+        }
+        for (target, _) in mirrored.targets.iter_mut() {
+            *target = reflect_point(*target, plane_normal, plane_point);
+        }
+        if let Some(startle) = mirrored.startle.as_mut() {
+            startle.source = reflect_point(startle.source, plane_normal, plane_point);
+        }
+        mirrored.anchor = self
+            .anchor
+            .map(|anchor| reflect_point(anchor, plane_normal, plane_point));
+        for obstacle in mirrored.obstacles.iter_mut() {
+            *obstacle = obstacle.reflect(plane_normal, plane_point);
+        }
+        for field in mirrored.flow_fields.iter_mut() {
+            *field = field.reflect(plane_normal, plane_point);
+        }
+        if let Some(bounds) = mirrored.event_bounds.as_mut() {
+            let (min, max) = reflect_box(bounds.min, bounds.max, plane_normal, plane_point);
+            *bounds = Bounds::new(min, max);
+        }
+        mirrored.refresh_grid();
+        mirrored
+    }
+
+    /// Pre-warm the flock by moving it `steps` times, so it has organized itself before it is shown.
+    ///
+    /// Each step moves the boids exactly as `update` would, but nothing else happens: no hooks or event
+    /// callbacks run, no metrics or history are recorded, and the step counter, any startle, and each
+    /// boid's panic level are left as they were, so the warm-up does not register as elapsed time.
+    pub fn settle(&mut self, steps: usize) {
+        let panic: Vec<U> = self.boids.iter().map(|boid| boid.panic()).collect();
+        for _ in 0..steps {
+            self.advance_boids();
+            for (boid, &level) in self.boids.iter_mut().zip(panic.iter()) {
+                boid.set_panic(level);
+            }
+            self.refresh_grid();
+        }
+    }
+
+    /// Restore the state from `frames` updates ago, dropping the newer history.
+    ///
+    /// Returns `false` and leaves the flock untouched if `frames` is zero or more than the history holds.
+    pub fn rewind(&mut self, frames: usize) -> bool {
+        if frames == 0 || frames > self.history.len() {
+            return false;
+        }
+        let mut history = std::mem::take(&mut self.history);
+        history.truncate(history.len() + 1 - frames);
+        *self = history.pop_back().unwrap();
+        self.history = history;
+        true
+    }
+
+    /// Startle the flock, scattering it away from `source` for `duration` updates before it regroups
+    pub fn startle(&mut self, source: Vector3<U>, duration: U) {
+        self.startle = Some(Startle::new(source, duration));
     }
 
     /// Set a target for the flock to follow
@@ -47,14 +743,2615 @@ where
     }
 }
 
-impl<T: Boid<T, U>, U: BaseNum + Float> Default for Flock<T, U> {
-    fn default() -> Self {
-        Self {
-            boids: Vec::new(),
-            goal_separation: U::from(25.0).unwrap(),
-            goal_alignment: U::from(50.0).unwrap(),
-            goal_cohesion: U::from(50.0).unwrap(),
-            target: None
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Get the radius within which boids steer apart
+    pub fn goal_separation(&self) -> U {
+        self.perception.separation()
+    }
+
+    /// Get the radius within which boids match headings
+    pub fn goal_alignment(&self) -> U {
+        self.perception.alignment()
+    }
+
+    /// Get the radius within which boids steer together
+    pub fn goal_cohesion(&self) -> U {
+        self.perception.cohesion()
+    }
+
+    /// Get the factor `boid`'s perception radii are scaled by at its current speed, following
+    /// `perception_speed_factor`
+    pub fn perception_scale(&self, boid: &T) -> U {
+        if self.perception_speed_factor == U::zero() || boid.max_speed() <= U::zero() {
+            return U::one();
+        }
+        let speed = boid.velocity().magnitude() / boid.max_speed();
+        (U::one() + self.perception_speed_factor * speed).max(U::zero())
+    }
+
+    /// Get the perception radii in effect for `boid` at its current speed
+    pub fn perception_for(&self, boid: &T) -> PerceptionConfig<U> {
+        self.perception.scaled(self.perception_scale(boid))
+    }
+
+    /// Get how strongly `boid` should separate from `neighbor`, following `separation_priority`
+    pub fn separation_priority_of(&self, boid: &T, neighbor: &T) -> U {
+        match &self.separation_priority {
+            SeparationPriority::Uniform => U::one(),
+            SeparationPriority::BySize => {
+                if boid.radius() > U::zero() {
+                    neighbor.radius() / boid.radius()
+                } else {
+                    U::one()
+                }
+            }
+            SeparationPriority::ByGroup(priorities) => priorities
+                .get(&neighbor.group())
+                .copied()
+                .unwrap_or_else(U::one),
+        }
+    }
+
+    /// Check if `boid` should separate from `neighbor` at all, which with `separate_only_approaching`
+    /// requires the two to be getting closer.
+    ///
+    /// Co-located boids always separate, since there is no telling which way they are heading apart.
+    pub fn separates_from(&self, boid: &T, neighbor: &T) -> bool {
+        if !self.separate_only_approaching {
+            return true;
+        }
+        let offset = neighbor.position() - boid.position();
+        let closing = (boid.velocity() - neighbor.velocity()).dot(offset);
+        closing > U::zero() || offset.magnitude2() <= U::zero()
+    }
+
+    /// Get the largest separation radius in any direction
+    pub fn max_separation_radius(&self) -> U {
+        match self.separation_anisotropy {
+            Some(anisotropy) => self.goal_separation() * anisotropy.max_scale(),
+            None => self.goal_separation(),
+        }
+    }
+
+    /// Get the separation radius for a neighbor at `offset` from a boid moving along `heading`
+    pub fn separation_radius(&self, heading: Vector3<U>, offset: Vector3<U>) -> U {
+        match self.separation_anisotropy {
+            Some(anisotropy) => self.goal_separation() * anisotropy.scale(heading, offset),
+            None => self.goal_separation(),
+        }
+    }
+
+    /// Add a boid to the flock, returning its index
+    pub fn add_boid(&mut self, boid: T) -> usize {
+        self.boids.push(boid);
+        self.refresh_grid();
+        self.boids.len() - 1
+    }
+
+    /// Mark a boid for removal. Returns `false` if there is no boid at `index`.
+    ///
+    /// The boid stays in place, and keeps being simulated, until `compact` is called. This keeps every
+    /// index stable until the caller is ready to fix up its references.
+    pub fn remove_boid(&mut self, index: usize) -> bool {
+        if index >= self.boids.len() {
+            return false;
+        }
+        if !self.pending_removals.contains(&index) {
+            self.pending_removals.push(index);
+        }
+        true
+    }
+
+    /// Drop every boid marked for removal, keeping the rest in their original order.
+    ///
+    /// Returns a table mapping each old index to its new index, where removed boids map to
+    /// `usize::MAX`. Constraints are remapped (or dropped if they refer to a removed boid) and the
+    /// spatial indexes are rebuilt.
+    pub fn compact(&mut self) -> Vec<usize> {
+        let mut remap = vec![0; self.boids.len()];
+        for index in self.pending_removals.drain(..) {
+            if let Some(entry) = remap.get_mut(index) {
+                *entry = usize::MAX;
+            }
+        }
+
+        // Assign new indices to the surviving boids
+        let mut next = 0;
+        for entry in remap.iter_mut() {
+            if *entry != usize::MAX {
+                *entry = next;
+                next += 1;
+            }
+        }
+        let mut old_index = 0;
+        self.boids.retain(|_| {
+            old_index += 1;
+            remap[old_index - 1] != usize::MAX
+        });
+
+        // Fix up references to the moved boids
+        self.constraints.retain_mut(|constraint| {
+            match (remap.get(constraint.follower), remap.get(constraint.parent)) {
+                (Some(&follower), Some(&parent))
+                    if follower != usize::MAX && parent != usize::MAX =>
+                {
+                    constraint.follower = follower;
+                    constraint.parent = parent;
+                    true
+                }
+                _ => false,
+            }
+        });
+
+        // Indices have moved, so the indexes need a full rebuild
+        if let Some(cell_size) = self.grid.as_ref().map(SpatialGrid::cell_size) {
+            self.rebuild_grid(cell_size);
+        }
+        if self.sweep.is_some() {
+            self.enable_sweep_and_prune();
+        }
+        remap
+    }
+
+    /// Set the seed used for per-boid randomness
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Get a random number generator for the boid at `index` during the current step.
+    ///
+    /// The generator only depends on the seed, the index, and the step count, so per-boid randomness
+    /// is reproducible no matter how the update is scheduled across threads.
+    pub fn rng_for(&self, index: usize) -> StdRng {
+        self.boid_rng(index, self.step)
+    }
+
+    /// Get the random number generator for the boid at `index` in one of its numbered streams. The
+    /// stream `rng_for` uses is the step count.
+    pub(crate) fn boid_rng(&self, index: usize, stream: u64) -> StdRng {
+        StdRng::seed_from_u64(mix_seed(mix_seed(self.seed ^ index as u64) ^ stream))
+    }
+
+    /// Copy every boid's position into `out`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is not the same length as the flock.
+    pub fn positions_into(&self, out: &mut [Vector3<U>]) {
+        self.try_positions_into(out)
+            .expect("position buffer length mismatch");
+    }
+
+    /// Copy every boid's position into `out`, failing if it is not the same length as the flock
+    pub fn try_positions_into(&self, out: &mut [Vector3<U>]) -> Result<(), BoidError> {
+        check_length(self.boids.len(), out.len())?;
+        for (slot, boid) in out.iter_mut().zip(self.boids.iter()) {
+            *slot = boid.position();
+        }
+        Ok(())
+    }
+
+    /// Copy every boid's velocity into `out`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is not the same length as the flock.
+    pub fn velocities_into(&self, out: &mut [Vector3<U>]) {
+        self.try_velocities_into(out)
+            .expect("velocity buffer length mismatch");
+    }
+
+    /// Copy every boid's velocity into `out`, failing if it is not the same length as the flock
+    pub fn try_velocities_into(&self, out: &mut [Vector3<U>]) -> Result<(), BoidError> {
+        check_length(self.boids.len(), out.len())?;
+        for (slot, boid) in out.iter_mut().zip(self.boids.iter()) {
+            *slot = boid.velocity();
+        }
+        Ok(())
+    }
+
+    /// Get every boid's position rounded to the nearest multiple of `cell` on each axis, in index order.
+    ///
+    /// The boids themselves are not moved. Positions are returned exactly if `cell` is not positive.
+    pub fn snapped_positions(&self, cell: U) -> Vec<Vector3<U>> {
+        self.boids
+            .iter()
+            .map(|boid| snap(boid.position(), cell))
+            .collect()
+    }
+
+    /// Set every boid's position from `positions`, then refresh the spatial grid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `positions` is not the same length as the flock.
+    pub fn set_positions(&mut self, positions: &[Vector3<U>]) {
+        self.try_set_positions(positions)
+            .expect("position buffer length mismatch");
+    }
+
+    /// Set every boid's position from `positions`, then refresh the spatial grid.
+    ///
+    /// Fails without changing anything if `positions` is not the same length as the flock.
+    pub fn try_set_positions(&mut self, positions: &[Vector3<U>]) -> Result<(), BoidError> {
+        check_length(self.boids.len(), positions.len())?;
+        for (boid, position) in self.boids.iter_mut().zip(positions.iter()) {
+            boid.set_position(*position);
+        }
+        self.refresh_grid();
+        Ok(())
+    }
+
+    /// Set every boid's velocity from `velocities`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `velocities` is not the same length as the flock.
+    pub fn set_velocities(&mut self, velocities: &[Vector3<U>]) {
+        self.try_set_velocities(velocities)
+            .expect("velocity buffer length mismatch");
+    }
+
+    /// Set every boid's velocity from `velocities`.
+    ///
+    /// Fails without changing anything if `velocities` is not the same length as the flock.
+    pub fn try_set_velocities(&mut self, velocities: &[Vector3<U>]) -> Result<(), BoidError> {
+        check_length(self.boids.len(), velocities.len())?;
+        for (boid, velocity) in self.boids.iter_mut().zip(velocities.iter()) {
+            boid.set_velocity(*velocity);
+        }
+        Ok(())
+    }
+
+    /// Mutate every boid, then refresh the spatial grid so it matches the new positions
+    pub fn apply_to_each(&mut self, mut f: impl FnMut(usize, &mut T)) {
+        for (index, boid) in self.boids.iter_mut().enumerate() {
+            f(index, boid);
+        }
+        self.refresh_grid();
+    }
+
+    /// Bring the spatial grid and sweep-and-prune index, if there are any, up to date with the boids'
+    /// positions
+    pub fn refresh_grid(&mut self) {
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.refresh(self.boids.iter().map(|boid| boid.position()));
+        }
+        if !self.uses_grid() {
+            return;
+        }
+        if let Some(grid) = self.grid.as_mut() {
+            grid.refresh(self.boids.iter().map(|boid| boid.position()));
+        }
+    }
+
+    /// Check if queries currently go through the spatial grid, which needs a grid to be built and the
+    /// flock to have at least `grid_min_boids` boids
+    pub fn uses_grid(&self) -> bool {
+        self.grid.is_some() && self.boids.len() >= self.grid_min_boids
+    }
+
+    /// Build a spatial grid with the given cell size to speed up queries and behaviors.
+    ///
+    /// The grid must be refreshed if boids are moved outside of `update`.
+    pub fn rebuild_grid(&mut self, cell_size: U) {
+        let slack = self.grid.as_ref().map_or(U::zero(), SpatialGrid::slack);
+        self.grid = Some(SpatialGrid::with_slack(
+            self.boids.iter().map(|boid| boid.position()),
+            cell_size,
+            slack,
+        ));
+    }
+
+    /// Build a spatial grid that doubles as a neighbor cache.
+    ///
+    /// Boids are only re-bucketed once they move more than `threshold` from where they were last
+    /// placed, so settled flocks skip most of the grid maintenance. Queries widen their search by
+    /// `threshold` so results stay exact.
+    pub fn enable_neighbor_cache(&mut self, cell_size: U, threshold: U) {
+        self.grid = Some(SpatialGrid::with_slack(
+            self.boids.iter().map(|boid| boid.position()),
+            cell_size,
+            threshold,
+        ));
+    }
+
+    /// Build a sweep-and-prune index to speed up queries and behaviors wherever the grid is not used.
+    ///
+    /// This suits flocks spread out along one direction, where a grid would be mostly empty cells.
+    pub fn enable_sweep_and_prune(&mut self) {
+        self.sweep = Some(SweepAndPrune::new(
+            self.boids.iter().map(|boid| boid.position()),
+        ));
+    }
+
+    /// Call `f` with the index and distance of every other boid strictly within `radius` of `boid`.
+    ///
+    /// `index` is where `boid` is stored in this flock. The boid at `index` is skipped as itself, so
+    /// distinct boids at the same position still see each other. A boid that is not stored in the flock
+    /// passes `None`, and skips any boid exactly at its position instead. With a `perception_ellipsoid`,
+    /// `radius` is stretched into that ellipsoid around the boid's heading. Neighbors in the
+    /// `blind_angle` cone behind the boid are skipped. This is the neighborhood the steering behaviors
+    /// use.
+    pub fn neighbors_excluding_self(
+        &self,
+        boid: &T,
+        index: Option<usize>,
+        radius: U,
+        mut f: impl FnMut(usize, U),
+    ) {
+        let is_self = |other: usize, distance: U| match index {
+            Some(own) => other == own,
+            None => distance <= U::zero(),
+        };
+        let (position, heading) = (boid.position(), boid.velocity());
+        match self.perception_ellipsoid {
+            Some(ellipsoid) => {
+                let reach = radius * ellipsoid.max_scale();
+                self.for_each_within(position, reach, |other, distance| {
+                    let offset = self.boids[other].position() - position;
+                    if !is_self(other, distance)
+                        && ellipsoid.contains(heading, offset, radius)
+                        && !self.in_blind_spot(heading, offset)
+                    {
+                        f(other, distance);
+                    }
+                });
+            }
+            None => self.for_each_within(position, radius, |other, distance| {
+                if !is_self(other, distance)
+                    && distance < radius
+                    && !self.in_blind_spot(heading, self.boids[other].position() - position)
+                {
+                    f(other, distance);
+                }
+            }),
+        }
+    }
+
+    /// Check if `offset` from a boid moving along `heading` falls in the `blind_angle` cone behind it.
+    ///
+    /// Nothing is hidden from a stationary boid, or at the boid's own position.
+    pub fn in_blind_spot(&self, heading: Vector3<U>, offset: Vector3<U>) -> bool {
+        if self.blind_angle <= U::zero() {
+            return false;
         }
+        let scale = (heading.magnitude2() * offset.magnitude2()).sqrt();
+        scale > U::zero() && -heading.dot(offset) > scale * self.blind_angle.cos()
+    }
+
+    /// Call `f` with the index and distance of every boid within `radius` of `position` (inclusive).
+    ///
+    /// Uses the spatial grid if `uses_grid` is true, then the sweep-and-prune index if there is one,
+    /// and otherwise checks every boid. With a `position_snap`, distances are measured in its whole
+    /// steps. While `update` has `packed_positions` built, their distances are worked out in batches
+    /// by `simd::distances`.
+    pub fn for_each_within(&self, position: Vector3<U>, radius: U, mut f: impl FnMut(usize, U)) {
+        if let Some(packed) = self.packed_positions.as_ref() {
+            let usable = packed.len() == self.boids.len() && self.position_snap.is_none();
+            if usable && crate::simd::is_f32::<U>() {
+                return self.for_each_within_packed(packed, position, radius, f);
+            }
+        }
+
+        self.for_each_candidate(position, radius, |index| {
+            if let Some(boid) = self.boids.get(index) {
+                let distance = match self.position_snap {
+                    Some(lattice) => lattice.distance(position, boid.position()),
+                    None => position.distance(boid.position()),
+                };
+                if distance <= radius {
+                    f(index, distance);
+                }
+            }
+        });
+    }
+
+    /// Call `visit` with the index of every boid that may be within `radius` of `position`, which
+    /// includes every boid unless there is an index to narrow them down
+    pub(crate) fn for_each_candidate(
+        &self,
+        position: Vector3<U>,
+        radius: U,
+        visit: impl FnMut(usize),
+    ) {
+        match (self.grid.as_ref().filter(|_| self.uses_grid()), &self.sweep) {
+            (Some(grid), _) => grid.for_each_candidate(position, radius, visit),
+            (None, Some(sweep)) => sweep.for_each_candidate(position, radius, visit),
+            (None, None) => (0..self.boids.len()).for_each(visit),
+        }
+    }
+
+    /// Count the other boids strictly within `radius` of the boid at `index`, including any at the same
+    /// position. Returns zero if there is no boid at `index`.
+    pub fn neighbor_count_of(&self, index: usize, radius: U) -> usize {
+        let position = match self.boids.get(index) {
+            Some(boid) => boid.position(),
+            None => return 0,
+        };
+        let mut count = 0;
+        self.for_each_within(position, radius, |other, distance| {
+            if other != index && distance < radius {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    /// Count the boids within `radius` of `position`, not counting any boid exactly at `position`
+    pub fn neighbor_count(&self, position: Vector3<U>, radius: U) -> usize {
+        let mut count = 0;
+        self.for_each_within(position, radius, |_, distance| {
+            if distance > U::zero() && distance < radius {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    /// Get the indices of every boid within `radius` of `center` (inclusive), sorted by index
+    pub fn find_within_sphere(&self, center: Vector3<U>, radius: U) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.for_each_within(center, radius, |index, _| found.push(index));
+        found.sort_unstable();
+        found
+    }
+
+    /// Get every boid's neighbors: the other boids strictly closer than `radius`, as sorted index lists.
+    ///
+    /// Entry `i` lists the neighbors of boid `i`. Since every boid uses the same radius, the relation is
+    /// symmetric: `j` is in `i`'s list exactly when `i` is in `j`'s. Uses the spatial grid if
+    /// `uses_grid` is true.
+    pub fn neighbor_graph(&self, radius: U) -> Vec<Vec<usize>> {
+        self.boids
+            .iter()
+            .enumerate()
+            .map(|(index, boid)| {
+                let mut neighbors = Vec::new();
+                self.for_each_within(boid.position(), radius, |other, distance| {
+                    if other != index && distance < radius {
+                        neighbors.push(other);
+                    }
+                });
+                neighbors.sort_unstable();
+                neighbors
+            })
+            .collect()
+    }
+
+    /// Get the index and distance of every boid within `radius` of `position` (inclusive), nearest first.
+    ///
+    /// Boids at the same distance are ordered by index, so the result is fully deterministic.
+    pub fn neighbors_sorted(&self, position: Vector3<U>, radius: U) -> Vec<(usize, U)> {
+        let mut found = Vec::new();
+        self.for_each_within(position, radius, |index, distance| {
+            found.push((index, distance))
+        });
+        found.sort_unstable_by(|a, b| {
+            a.1.partial_cmp(&b.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        found
+    }
+
+    /// Get the index of every boid ordered from farthest to nearest to `camera`, for painter's-order
+    /// or alpha-blended rendering.
+    ///
+    /// Boids at the same distance are ordered by index. Reverse the result to draw nearest first.
+    pub fn sorted_by_distance_to(&self, camera: Vector3<U>) -> Vec<usize> {
+        let mut order: Vec<(usize, U)> = self
+            .boids
+            .iter()
+            .enumerate()
+            .map(|(index, boid)| (index, boid.position().distance2(camera)))
+            .collect();
+        order.sort_unstable_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        order.into_iter().map(|(index, _)| index).collect()
+    }
+
+    /// Get the indices of every boid inside the box from `min` to `max` (inclusive), sorted by index
+    pub fn find_within_box(&self, min: Vector3<U>, max: Vector3<U>) -> Vec<usize> {
+        let bounds = Bounds::new(min, max);
+        let mut found = Vec::new();
+        let mut visit = |index: usize| {
+            if self
+                .boids
+                .get(index)
+                .is_some_and(|boid| bounds.contains(boid.position()))
+            {
+                found.push(index);
+            }
+        };
+
+        match (self.grid.as_ref().filter(|_| self.uses_grid()), &self.sweep) {
+            (Some(grid), _) => grid.for_each_candidate_in_box(min, max, visit),
+            (None, Some(sweep)) => sweep.for_each_candidate_in_box(min, max, visit),
+            (None, None) => (0..self.boids.len()).for_each(&mut visit),
+        }
+        found.sort_unstable();
+        found
+    }
+
+    /// Get the average velocity of boids within `radius` of `position`, or zero if there are none.
+    ///
+    /// Each boid is weighted by `1 - distance / radius`, so nearer boids count more. A `radius` that is
+    /// not positive takes in no boids.
+    pub fn velocity_at(&self, position: Vector3<U>, radius: U) -> Vector3<U> {
+        let mut sum = Vector3::new(U::zero(), U::zero(), U::zero());
+        if radius.is_nan() || radius <= U::zero() {
+            return sum;
+        }
+        let mut total = U::zero();
+        self.for_each_within(position, radius, |index, distance| {
+            let weight = U::one() - distance / radius;
+            sum += self.boids[index].velocity() * weight;
+            total += weight;
+        });
+
+        if total > U::zero() {
+            sum / total
+        } else {
+            Vector3::new(U::zero(), U::zero(), U::zero())
+        }
+    }
+
+    /// Sum the positions of boids within the cohesion radius of `position`, returning the sum and count.
+    ///
+    /// Uses the Barnes-Hut tree if one has been built.
+    pub fn cohesion_sum(&self, position: Vector3<U>) -> (Vector3<U>, usize) {
+        if let (Some(octree), Some(theta)) = (self.octree.as_ref(), self.barnes_hut_theta) {
+            return octree.sum_within(position, self.goal_cohesion(), theta);
+        }
+
+        let mut sum = Vector3::new(U::zero(), U::zero(), U::zero());
+        let mut count = 0;
+        self.for_each_within(position, self.goal_cohesion(), |index, distance| {
+            // Only operate on nearby boids
+            if distance > U::zero() && distance < self.goal_cohesion() {
+                sum += self.boids[index].position();
+                count += 1;
+            }
+        });
+        (sum, count)
+    }
+
+    /// Get what `boid`'s cohesion steering is scaled by, following `cohesion_normalization`. `index` is
+    /// where the boid is stored, as for `neighbors_excluding_self`.
+    pub fn cohesion_scale(&self, boid: &T, index: Option<usize>) -> U {
+        match self.cohesion_normalization {
+            CohesionNormalization::Bounded => U::one(),
+            CohesionNormalization::Unbounded => {
+                let radius = self.perception_for(boid).cohesion();
+                let mut count = 0;
+                self.neighbors_excluding_self(boid, index, radius, |_, distance| {
+                    if distance >= self.cohesion_dead_zone {
+                        count += 1;
+                    }
+                });
+                U::from(count).unwrap_or_else(U::one)
+            }
+        }
+    }
+
+    /// Get the point `boid` steers towards for cohesion, or `None` if it has no neighbors within the
+    /// cohesion radius. `index` is where the boid is stored, as for `neighbors_excluding_self`.
+    pub fn cohesion_point(&self, boid: &T, index: Option<usize>) -> Option<Vector3<U>> {
+        let radius = self.perception_for(boid).cohesion();
+        match self.cohesion_target {
+            CohesionTarget::Mean => {
+                // The Barnes-Hut tree can only exclude the boid by position
+                if self.octree.is_some() && self.barnes_hut_theta.is_some() {
+                    let (sum, count) = self.cohesion_sum(boid.position());
+                    return (count > 0).then(|| sum / U::from(count).unwrap());
+                }
+
+                let mut sum = Vector3::new(U::zero(), U::zero(), U::zero());
+                let mut count = 0;
+                self.neighbors_excluding_self(boid, index, radius, |other, distance| {
+                    if distance >= self.cohesion_dead_zone {
+                        sum += self.boids[other].position();
+                        count += 1;
+                    }
+                });
+                (count > 0).then(|| sum / U::from(count).unwrap())
+            }
+            CohesionTarget::Median => {
+                // Skip neighbors with a non-finite coordinate, which would leave the axes unordered. A
+                // `position_snap` measures a NaN coordinate as zero steps, so these can still be found.
+                let mut neighbors = Vec::new();
+                self.neighbors_excluding_self(boid, index, radius, |other, distance| {
+                    let position = self.boids[other].position();
+                    let finite = (0..3).all(|axis| position[axis].is_finite());
+                    if distance >= self.cohesion_dead_zone && finite {
+                        neighbors.push(position);
+                    }
+                });
+                if neighbors.is_empty() {
+                    return None;
+                }
+
+                // Take the middle value on each axis, averaging the middle pair for even counts
+                let mut median = |axis: usize| {
+                    neighbors.sort_by(|a, b| a[axis].partial_cmp(&b[axis]).unwrap());
+                    let middle = neighbors.len() / 2;
+                    if neighbors.len() % 2 == 0 {
+                        (neighbors[middle - 1][axis] + neighbors[middle][axis])
+                            / (U::one() + U::one())
+                    } else {
+                        neighbors[middle][axis]
+                    }
+                };
+                Some(Vector3::new(median(0), median(1), median(2)))
+            }
+        }
+    }
+
+    /// Get the target `boid` heads for.
+    ///
+    /// The boid's own target comes first, then the same order as `target_for`.
+    pub fn target_of(&self, boid: &T) -> Option<Vector3<U>> {
+        boid.target()
+            .or_else(|| self.target_for(boid.group(), boid.position()))
+    }
+
+    /// Get the target for a boid in `group` at `position`.
+    ///
+    /// Group targets come first, then the weighted `targets`, then the flock-wide `target`.
+    pub fn target_for(&self, group: u16, position: Vector3<U>) -> Option<Vector3<U>> {
+        if let Some(target) = self.group_targets.get(&group) {
+            return Some(*target);
+        }
+        if self.targets.is_empty() {
+            return self.target;
+        }
+
+        match self.target_policy {
+            TargetPolicy::WeightedAverage => {
+                let (sum, total) = self.targets.iter().fold(
+                    (Vector3::new(U::zero(), U::zero(), U::zero()), U::zero()),
+                    |(sum, total), (point, weight)| (sum + *point * *weight, total + *weight),
+                );
+                (total > U::zero()).then(|| sum / total)
+            }
+            TargetPolicy::MostAppealing => self
+                .targets
+                .iter()
+                .map(|(point, weight)| {
+                    (
+                        *point,
+                        *weight / position.distance(*point).max(U::epsilon()),
+                    )
+                })
+                .filter(|(_, appeal)| *appeal > U::zero())
+                .fold(
+                    None,
+                    |best: Option<(Vector3<U>, U)>, candidate| match best {
+                        Some(best) if best.1 >= candidate.1 => Some(best),
+                        _ => Some(candidate),
+                    },
+                )
+                .map(|(point, _)| point),
+        }
+    }
+
+    /// Get the targeting force for a boid in `group` at `position`, before weighting.
+    ///
+    /// This is the offset from the boid to its target (see `target_for`), or zero if targeting is
+    /// disabled or there is no target. A boid sitting exactly on its target feels no force, and an
+    /// offset that is not finite is dropped, so reaching the target can never produce `NaN`.
+    pub fn targeting_force(&self, group: u16, position: Vector3<U>) -> Vector3<U> {
+        self.targeting_force_towards(self.target_for(group, position), position)
+    }
+
+    /// Get the raw targeting force at `position` towards `target`, like `targeting_force` but for a
+    /// target that has already been picked
+    pub fn targeting_force_towards(
+        &self,
+        target: Option<Vector3<U>>,
+        position: Vector3<U>,
+    ) -> Vector3<U> {
+        let zero = Vector3::new(U::zero(), U::zero(), U::zero());
+        if !self.behaviors.targeting {
+            return zero;
+        }
+        match target {
+            Some(target) => {
+                let offset = target - position;
+                if !offset.magnitude2().is_finite() {
+                    return zero;
+                }
+                if !self.targeting_avoids_obstacles || self.obstacles.is_empty() {
+                    return offset;
+                }
+
+                // Keep the same pull, but around whatever is in the way
+                detour_direction(&self.obstacles, position, target)
+                    .map(|direction| direction * offset.magnitude())
+                    .unwrap_or(zero)
+            }
+            None => zero,
+        }
+    }
+
+    /// Get the steering force pulling a boid towards the whole-flock centroid, before weighting.
+    ///
+    /// A boid already at the centroid feels nothing.
+    pub fn global_cohesion_force(
+        &self,
+        position: Vector3<U>,
+        velocity: Vector3<U>,
+        speed: U,
+        max_force: U,
+    ) -> Vector3<U> {
+        match self.global_centroid {
+            Some(centroid) if centroid != position => {
+                steer_v3(centroid - position, velocity, speed, max_force)
+            }
+            _ => Vector3::new(U::zero(), U::zero(), U::zero()),
+        }
+    }
+
+    /// Blend a neighbor average into its filtered value from the previous update, following
+    /// `neighbor_smoothing`.
+    ///
+    /// Without a previous value, the filter starts from `own` (the boid's own heading or position), so
+    /// neighbors coming into view are eased in. It resets once there are no neighbors to average.
+    pub fn smooth_neighbor_average(
+        &self,
+        previous: Option<Vector3<U>>,
+        current: Option<Vector3<U>>,
+        own: Vector3<U>,
+    ) -> Option<Vector3<U>> {
+        match (self.neighbor_smoothing, current) {
+            (Some(time_constant), Some(current)) if time_constant > U::zero() => {
+                let previous = previous.unwrap_or(own);
+                let alpha = U::one() - (-time_constant.recip()).exp();
+                Some(previous + (current - previous) * alpha)
+            }
+            _ => current,
+        }
+    }
+
+    /// Get the force an isolated boid uses in place of alignment or cohesion.
+    ///
+    /// With `brake_when_isolated` set and no target, this steers towards a standstill. Otherwise it is zero.
+    ///
+    /// Alignment and cohesion both brake, each scaled by its weight in `weights`, so the brake is shared
+    /// between them. Together they can stop the boid but never push it backwards.
+    pub fn isolated_force(
+        &self,
+        group: u16,
+        position: Vector3<U>,
+        velocity: Vector3<U>,
+        max_force: U,
+        weights: &BoidWeights<U>,
+    ) -> Vector3<U> {
+        let zero = Vector3::new(U::zero(), U::zero(), U::zero());
+        let targeted = self.behaviors.targeting && self.target_for(group, position).is_some();
+        if !self.brake_when_isolated || targeted {
+            return zero;
+        }
+
+        let share = |enabled: bool, weight: U| if enabled { weight.abs() } else { U::zero() };
+        let total = share(self.behaviors.alignment, weights.alignment)
+            + share(self.behaviors.cohesion, weights.cohesion);
+        if total > U::zero() {
+            limit_magnitude_v3(-velocity, max_force) / total
+        } else {
+            zero
+        }
+    }
+
+    /// Get the steering force pulling a boid back towards the anchor.
+    ///
+    /// Boids within `anchor_radius` of the anchor feel nothing, so this only reins in strays.
+    pub fn anchor_force(
+        &self,
+        position: Vector3<U>,
+        velocity: Vector3<U>,
+        speed: U,
+        max_force: U,
+    ) -> Vector3<U> {
+        match self.anchor {
+            Some(anchor) if position.distance(anchor) > self.anchor_radius => {
+                steer_v3(anchor - position, velocity, speed, max_force)
+            }
+            _ => Vector3::new(U::zero(), U::zero(), U::zero()),
+        }
+    }
+
+    /// Get the weighted V-formation steering force for a boid.
+    ///
+    /// The boid seeks a slot behind the nearest boid ahead of it (within the alignment radius), on
+    /// whichever side of that boid it is already on. Boids with nobody ahead lead, and feel no force.
+    pub fn formation_force(
+        &self,
+        position: Vector3<U>,
+        velocity: Vector3<U>,
+        speed: U,
+        max_force: U,
+    ) -> Vector3<U> {
+        let zero = Vector3::new(U::zero(), U::zero(), U::zero());
+        let formation = match self.v_formation {
+            Some(formation) => formation,
+            None => return zero,
+        };
+
+        // Find the nearest boid ahead
+        let mut ahead: Option<(usize, U)> = None;
+        self.for_each_within(position, self.goal_alignment(), |index, distance| {
+            let offset = self.boids[index].position() - position;
+            if distance > U::zero()
+                && offset.dot(velocity) > U::zero()
+                && ahead.is_none_or(|(_, nearest)| distance < nearest)
+            {
+                ahead = Some((index, distance));
+            }
+        });
+        let leader = match ahead {
+            Some((index, _)) => &self.boids[index],
+            None => return zero,
+        };
+
+        // Pick the slot on the same side of the leader as this boid
+        let (forward, side, _) = heading_frame(leader.velocity());
+        let sign = if (position - leader.position()).dot(side) < U::zero() {
+            -U::one()
+        } else {
+            U::one()
+        };
+        let direction =
+            -forward * formation.slot_angle.cos() + side * (formation.slot_angle.sin() * sign);
+        let slot = leader.position() + direction * formation.spacing;
+
+        // Close in on the slot while matching the leader's velocity, slowing the approach in time to
+        // stop there instead of overshooting
+        let gap = slot - position;
+        let distance = gap.magnitude();
+        let closing = if distance > U::zero() {
+            let two = U::one() + U::one();
+            gap * ((two * max_force * distance).sqrt().min(speed) / distance)
+        } else {
+            gap
+        };
+        let desired = limit_magnitude_v3(leader.velocity() + closing, speed);
+        limit_magnitude_v3(desired - velocity, max_force) * formation.weight
+    }
+
+    /// Get the steering force turning a boid away from the `obstacles` ahead of it, following
+    /// `obstacle_avoidance`.
+    ///
+    /// Each sensor counts for how far it reaches before it is blocked, so the boid heads for the most
+    /// open direction, and the force grows as the nearest blocked sensor gets shorter. This is zero when
+    /// every sensor is clear. If the sensors are blocked evenly, as by a wall straight ahead, the boid
+    /// turns towards its side.
+    pub fn avoid_obstacles(
+        &self,
+        position: Vector3<U>,
+        velocity: Vector3<U>,
+        speed: U,
+        max_force: U,
+    ) -> Vector3<U> {
+        let zero = Vector3::new(U::zero(), U::zero(), U::zero());
+        let avoidance = match self.obstacle_avoidance {
+            Some(avoidance) if avoidance.range > U::zero() && !self.obstacles.is_empty() => {
+                avoidance
+            }
+            _ => return zero,
+        };
+
+        // Find how far along each sensor is clear, as a fraction of its range
+        let mut open = zero;
+        let mut nearest = U::one();
+        for direction in avoidance.sensor_directions(velocity) {
+            let end = position + direction * avoidance.range;
+            let clear = self
+                .obstacles
+                .iter()
+                .filter_map(|obstacle| obstacle.segment_hit(position, end))
+                .fold(U::one(), U::min);
+            open += direction * clear;
+            nearest = nearest.min(clear);
+        }
+        if nearest >= U::one() {
+            return zero;
+        }
+
+        let (forward, side, _) = heading_frame(velocity);
+        let lateral = open - forward * open.dot(forward);
+        let desired = if lateral.magnitude2() > U::epsilon() {
+            open
+        } else {
+            side
+        };
+        steer_v3(desired, velocity, speed, max_force) * (avoidance.weight * (U::one() - nearest))
+    }
+
+    /// Limit a weighted behavior force according to `behavior_clamp`
+    pub fn clamp_contribution(&self, force: Vector3<U>, max_force: U) -> Vector3<U> {
+        match self.behavior_clamp {
+            Some(fraction) => limit_magnitude_v3(force, max_force * fraction),
+            None => force,
+        }
+    }
+
+    /// Scale `forces` down together so that their sum is no longer than `max_acceleration`
+    pub fn clamp_net_force(&self, forces: &mut [Vector3<U>]) {
+        let max = self.max_acceleration;
+        if max.is_nan() || max == U::infinity() {
+            return;
+        }
+        let max = max.max(U::zero());
+
+        let net = forces.iter().fold(
+            Vector3::new(U::zero(), U::zero(), U::zero()),
+            |sum, force| sum + *force,
+        );
+        let magnitude = net.magnitude();
+        if magnitude > max {
+            let scale = max / magnitude;
+            for force in forces.iter_mut() {
+                *force *= scale;
+            }
+        }
+    }
+
+    /// Check if nothing can change any boid's velocity, so that `update` would leave every boid coasting
+    /// at its current velocity forever.
+    ///
+    /// This is easy to end up in while tuning, for example by zeroing every weight with no flow field
+    /// or drag to fall back on, so tools can use this to warn about it. Constraints, which move boids
+    /// directly rather than through their velocity, are not counted.
+    pub fn is_inert(&self) -> bool {
+        // Drag and speed easing act even with no force at all
+        let eased = self.energy_conserving
+            && self.boids.iter().any(|boid| {
+                let speed = boid.velocity().magnitude();
+                speed > U::zero() && speed != boid.max_speed()
+            });
+        if self.drag != U::zero() || eased {
+            return false;
+        }
+
+        // Every force is clamped away
+        if self.max_acceleration <= U::zero()
+            || self
+                .behavior_clamp
+                .is_some_and(|fraction| fraction <= U::zero())
+        {
+            return true;
+        }
+
+        if self.startle.is_some()
+            || !self.flow_fields.is_empty()
+            || (!self.obstacles.is_empty()
+                && self
+                    .obstacle_avoidance
+                    .is_some_and(|avoidance| avoidance.weight != U::zero()))
+            || self
+                .v_formation
+                .is_some_and(|formation| formation.weight != U::zero())
+        {
+            return false;
+        }
+
+        let behaviors = self.behaviors;
+        self.boids.iter().all(|boid| {
+            let weights = self.effective_weights(boid.get_weights());
+            let targeted = behaviors.targeting
+                && weights.targeting != U::zero()
+                && self.target_of(boid).is_some();
+            let anchored = weights.anchor != U::zero() && self.anchor.is_some();
+            !(behaviors.separation && weights.separation != U::zero()
+                || behaviors.alignment && weights.alignment != U::zero()
+                || behaviors.cohesion && weights.cohesion != U::zero()
+                || weights.global_cohesion != U::zero()
+                || targeted
+                || anchored)
+        })
+    }
+
+    /// Get the weights a boid should currently use, accounting for any active startle
+    pub fn effective_weights(&self, weights: &BoidWeights<U>) -> BoidWeights<U> {
+        match self.startle {
+            Some(startle) => startle.apply(weights),
+            None => *weights,
+        }
+    }
+
+    /// Get the combined force of every flow field at `position`
+    pub fn flow_force(&self, position: Vector3<U>) -> Vector3<U> {
+        self.flow_fields.iter().fold(
+            Vector3::new(U::zero(), U::zero(), U::zero()),
+            |sum, field| sum + field.force_at(position),
+        )
+    }
+
+    /// Get the startle flee force felt by a boid at `position`.
+    ///
+    /// If the startle has a `spread`, the boid at `index` flees along its own direction within the
+    /// spread cone. This direction is drawn from what `rng_for` gave the boid on the step the startle
+    /// began, so it stays fixed for the whole startle.
+    pub fn startle_force(&self, position: Vector3<U>, index: Option<usize>) -> Vector3<U> {
+        let startle = match self.startle {
+            Some(startle) => startle,
+            None => return Vector3::new(U::zero(), U::zero(), U::zero()),
+        };
+        let force = startle.flee_force(position);
+        let index = match index {
+            Some(index) if startle.spread > U::zero() && force.magnitude2() > U::zero() => index,
+            _ => return force,
+        };
+
+        // Turn the escape direction by a per-boid angle around a per-boid axis
+        let began = self
+            .step
+            .saturating_sub(startle.elapsed.to_u64().unwrap_or(0));
+        let mut rng = self.boid_rng(index, began);
+        let tilt = startle.spread * U::from(rng.gen::<f64>()).unwrap();
+        let roll = U::from(rng.gen::<f64>() * std::f64::consts::PI * 2.0).unwrap();
+        let (forward, side, up) = heading_frame(force);
+        let sideways = side * roll.cos() + up * roll.sin();
+        (forward * tilt.cos() + sideways * tilt.sin()) * force.magnitude()
+    }
+
+    /// Estimate how many boids there are per unit volume around `position`, smoothed over `bandwidth`.
+    ///
+    /// Each boid is spread out with the cubic spline kernel used in SPH fluid simulations, which reaches
+    /// out to `2 * bandwidth` and integrates to exactly `1` over all of space. The estimate therefore
+    /// integrates to the number of boids, and is smooth enough to take gradients of. Only boids within
+    /// reach of `position` are visited. Returns zero if `bandwidth` is not positive.
+    pub fn density_at(&self, position: Vector3<U>, bandwidth: U) -> U {
+        if bandwidth.is_nan() || bandwidth <= U::zero() {
+            return U::zero();
+        }
+
+        let two = U::from(2.0).unwrap();
+        let mut density = U::zero();
+        self.for_each_within(position, bandwidth * two, |_, distance| {
+            let q = distance / bandwidth;
+            density += if q < U::one() {
+                U::one() - U::from(1.5).unwrap() * q * q + U::from(0.75).unwrap() * q * q * q
+            } else {
+                U::from(0.25).unwrap() * (two - q).powi(3)
+            };
+        });
+
+        let normalization = U::one() / (U::from(std::f64::consts::PI).unwrap() * bandwidth.powi(3));
+        density * normalization
+    }
+
+    /// Count the boids in each cell of a grid laid over `bounds`.
+    ///
+    /// Each axis is split into `resolution` cells, except axes where `bounds` has no extent, which get a
+    /// single cell (so a flat 2D flock gives a 2D grid). The result is flattened with X varying fastest,
+    /// then Y, then Z: `index = (z * cells_y + y) * cells_x + x`. Boids outside of `bounds` are ignored.
+    pub fn heatmap(&self, resolution: usize, bounds: Bounds<U>) -> Vec<U> {
+        let resolution = resolution.max(1);
+        let size = bounds.size();
+        let cells = |extent: U| if extent > U::zero() { resolution } else { 1 };
+        let (cells_x, cells_y, cells_z) = (cells(size.x), cells(size.y), cells(size.z));
+        let mut heatmap = vec![U::zero(); cells_x * cells_y * cells_z];
+
+        // Find which cell a coordinate falls into along one axis
+        let cell = |value: U, min: U, extent: U, count: usize| {
+            if count == 1 {
+                return 0;
+            }
+            let index = ((value - min) / extent * U::from(count).unwrap())
+                .to_usize()
+                .unwrap_or(0);
+            index.min(count - 1)
+        };
+
+        for boid in self.boids.iter() {
+            let position = boid.position();
+            if !bounds.contains(position) {
+                continue;
+            }
+
+            let x = cell(position.x, bounds.min.x, size.x, cells_x);
+            let y = cell(position.y, bounds.min.y, size.y, cells_y);
+            let z = cell(position.z, bounds.min.z, size.z, cells_z);
+            heatmap[(z * cells_y + y) * cells_x + x] += U::one();
+        }
+
+        heatmap
+    }
+
+    /// Iterate over every `stride`-th boid, starting with the first. A stride of zero is treated as one.
+    pub fn subsample(&self, stride: usize) -> impl Iterator<Item = &T> {
+        self.boids.iter().step_by(stride.max(1))
+    }
+
+    /// Iterate over a random subset of boids, each kept with probability `fraction` (clamped to `0..=1`)
+    pub fn subsample_random<'a, R: Rng>(
+        &'a self,
+        fraction: f64,
+        rng: &'a mut R,
+    ) -> impl Iterator<Item = &'a T> {
+        let fraction = fraction.clamp(0.0, 1.0);
+        self.boids.iter().filter(move |_| rng.gen_bool(fraction))
+    }
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Create an empty flock with the default settings, failing if the scalar type cannot represent them
+    pub fn try_default() -> Result<Self, BoidError> {
+        Ok(Self {
+            boids: Vec::new(),
+            perception: PerceptionConfig::try_default()?,
+            perception_speed_factor: U::zero(),
+            perception_ellipsoid: None,
+            blind_angle: U::zero(),
+            target: None,
+            group_targets: HashMap::new(),
+            targets: Vec::new(),
+            target_policy: TargetPolicy::WeightedAverage,
+            obstacles: Vec::new(),
+            targeting_avoids_obstacles: false,
+            obstacle_avoidance: None,
+            alignment_acceleration_blend: None,
+            neighbor_smoothing: None,
+            constraints: Vec::new(),
+            separation_anisotropy: None,
+            separation_min_neighbors: 1,
+            separation_priority: SeparationPriority::Uniform,
+            separate_only_approaching: false,
+            startle: None,
+            drag: U::zero(),
+            behavior_clamp: None,
+            max_acceleration: U::infinity(),
+            density_speed: None,
+            v_formation: None,
+            weight_schedule: None,
+            barnes_hut_theta: None,
+            octree: None,
+            packed_positions: None,
+            global_centroid: None,
+            grid: None,
+            sweep: None,
+            grid_min_boids: 768,
+            pending_removals: Vec::new(),
+            seed: 0,
+            step: 0,
+            position_snap: None,
+            metrics: None,
+            update_mode: UpdateMode::Synchronous,
+            integration: Integration::PerForce,
+            cohesion_target: CohesionTarget::Mean,
+            cohesion_normalization: CohesionNormalization::Bounded,
+            cohesion_dead_zone: U::zero(),
+            behaviors: BehaviorFlags::default(),
+            flow_fields: Vec::new(),
+            energy_conserving: false,
+            brake_when_isolated: false,
+            anchor: None,
+            anchor_radius: U::zero(),
+            history: VecDeque::new(),
+            history_capacity: 0,
+            event_bounds: None,
+            waypoint_radius: None,
+            on_event: None,
+            pre_step: None,
+            post_step: None,
+        })
+    }
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Default for Flock<T, U> {
+    fn default() -> Self {
+        Self::try_default().expect("scalar type cannot represent the default flock settings")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::boids::boid3d::Boid3D;
+
+    fn boid(position: [f64; 3], velocity: [f64; 3]) -> Boid3D<f64> {
+        Boid3D::new_with_velocity(position.into(), velocity.into())
+    }
+
+    fn flock_of(boids: Vec<Boid3D<f64>>) -> Flock<Boid3D<f64>, f64> {
+        Flock {
+            boids,
+            ..Flock::default()
+        }
+    }
+
+    fn line(count: usize, spacing: f64) -> Flock<Boid3D<f64>, f64> {
+        flock_of(
+            (0..count)
+                .map(|i| boid([i as f64 * spacing, 0.0, 0.0], [1.0, 0.0, 0.0]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn subsample_takes_every_stride_th_boid() {
+        let flock = line(10, 1.0);
+        let xs: Vec<f64> = flock.subsample(2).map(|boid| boid.position.x).collect();
+        assert_eq!(xs, vec![0.0, 2.0, 4.0, 6.0, 8.0]);
+        assert_eq!(flock.subsample(1).count(), 10);
+        assert_eq!(flock.subsample(0).count(), 10);
+    }
+
+    #[test]
+    fn subsample_random_keeps_the_requested_fraction() {
+        let flock = line(1000, 1.0);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(flock.subsample_random(0.0, &mut rng).count(), 0);
+        assert_eq!(flock.subsample_random(1.0, &mut rng).count(), 1000);
+        let half = flock.subsample_random(0.5, &mut rng).count();
+        assert!((400..600).contains(&half));
+    }
+
+    #[test]
+    fn anisotropic_separation_reaches_further_ahead_than_behind() {
+        let anisotropy = SeparationAnisotropy {
+            front: 2.0,
+            side: 1.0,
+            back: 0.5,
+        };
+        let separation = |offset: f64| {
+            let mut flock = flock_of(vec![
+                boid([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+                boid([offset, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            ]);
+            flock.separation_anisotropy = Some(anisotropy);
+            flock.boids[0].separate(&flock)
+        };
+
+        // 30 units is outside the plain 25 unit radius, but inside 2x ahead
+        assert!(separation(30.0).magnitude() > 0.0);
+        assert_eq!(separation(-30.0).magnitude(), 0.0);
+        // 20 units is inside the plain radius, but outside 0.5x behind
+        assert_eq!(separation(-20.0).magnitude(), 0.0);
+        assert!(anisotropy.scale(Vector3::unit_x(), Vector3::unit_y()) == 1.0);
+    }
+
+    #[test]
+    fn heatmap_counts_boids_in_their_cell() {
+        let mut flock = flock_of(
+            (0..7)
+                .map(|i| boid([6.0 + 0.1 * i as f64, 2.5, 0.0], [1.0, 0.0, 0.0]))
+                .collect(),
+        );
+        // One boid outside the bounds is ignored
+        flock.boids.push(boid([50.0, 0.0, 0.0], [1.0, 0.0, 0.0]));
+
+        let bounds = Bounds::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(10.0, 10.0, 0.0));
+        let heatmap = flock.heatmap(4, bounds);
+        assert_eq!(heatmap.len(), 16);
+        // x = 6.x falls in column 2 and y = 2.5 in row 1, with X varying fastest
+        assert_eq!(heatmap[4 + 2], 7.0);
+        assert_eq!(heatmap.iter().sum::<f64>(), 7.0);
+    }
+
+    #[test]
+    fn velocity_at_samples_the_local_velocity() {
+        let mut flock = flock_of(
+            (0..25)
+                .map(|i| boid([(i % 5) as f64, (i / 5) as f64, 0.0], [1.0, 0.5, 0.0]))
+                .collect(),
+        );
+        flock.rebuild_grid(5.0);
+
+        let inside = flock.velocity_at(Vector3::new(2.0, 2.0, 0.0), 3.0);
+        assert!(inside.distance(Vector3::new(1.0, 0.5, 0.0)) < 1e-12);
+        let far = flock.velocity_at(Vector3::new(100.0, 100.0, 0.0), 3.0);
+        assert_eq!(far, Vector3::new(0.0, 0.0, 0.0));
+
+        // Sampling right on a boid with no radius takes in nothing, rather than dividing by zero
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        assert_eq!(flock.velocity_at(Vector3::new(2.0, 2.0, 0.0), 0.0), zero);
+        assert_eq!(flock.velocity_at(Vector3::new(2.0, 2.0, 0.0), -1.0), zero);
+        assert_eq!(
+            flock.velocity_at(Vector3::new(2.0, 2.0, 0.0), f64::NAN),
+            zero
+        );
+    }
+
+    #[test]
+    fn per_boid_rng_is_reproducible() {
+        let mut flock = line(4, 1.0);
+        flock.set_seed(42);
+        let draw = |flock: &Flock<Boid3D<f64>, f64>, index| flock.rng_for(index).gen::<u64>();
+        assert_eq!(draw(&flock, 2), draw(&flock, 2));
+        assert_ne!(draw(&flock, 2), draw(&flock, 3));
+
+        flock.set_seed(43);
+        let reseeded = draw(&flock, 2);
+        flock.set_seed(42);
+        assert_ne!(reseeded, draw(&flock, 2));
+    }
+
+    #[test]
+    fn startle_spread_follows_the_per_boid_streams() {
+        let mut flock = line(3, 1.0);
+        flock.set_seed(5);
+        flock.startle = Some(Startle {
+            spread: 0.8,
+            ..Startle::new(Vector3::new(-10.0, 0.0, 0.0), 50.0)
+        });
+        let heading = |flock: &Flock<Boid3D<f64>, f64>, index| {
+            flock
+                .startle_force(Vector3::new(0.0, 0.0, 0.0), Some(index))
+                .normalize()
+        };
+        let first = heading(&flock, 1);
+        assert!(first.distance(heading(&flock, 2)) > 1e-6);
+
+        // The direction holds for the rest of the startle
+        flock.step += 3;
+        flock.startle.as_mut().unwrap().elapsed += 3.0;
+        assert!(first.distance(heading(&flock, 1)) < 1e-12);
+
+        // A startle that begins on another step fans out differently
+        flock.startle.as_mut().unwrap().elapsed = 0.0;
+        assert!(first.distance(heading(&flock, 1)) > 1e-6);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn update_matches_a_serial_update() {
+        let mut flock = flock_of(
+            (0..40)
+                .map(|i| {
+                    let angle = i as f64 * 0.7;
+                    boid(
+                        [(i % 8) as f64 * 4.0, (i / 8) as f64 * 4.0, 0.0],
+                        [angle.cos(), angle.sin(), 0.0],
+                    )
+                })
+                .collect(),
+        );
+        flock.set_seed(7);
+        flock.startle = Some(Startle {
+            spread: 0.5,
+            ..Startle::new(Vector3::new(14.0, 10.0, 0.0), 20.0)
+        });
+        for _ in 0..5 {
+            let serial: Vec<Boid3D<f64>> = flock
+                .boids
+                .iter()
+                .enumerate()
+                .map(|(index, boid)| boid.update_at(Some(index), &flock))
+                .collect();
+            flock.update();
+            assert_eq!(flock.boids, serial);
+        }
+    }
+
+    #[test]
+    fn apply_to_each_mutates_boids_and_refreshes_the_grid() {
+        let mut flock = line(5, 100.0);
+        flock.grid_min_boids = 0;
+        flock.rebuild_grid(10.0);
+        flock.apply_to_each(|_, boid| boid.velocity = Vector3::new(0.0, 0.0, 0.0));
+        let before = flock.boids.clone();
+        flock.update();
+        for (boid, before) in flock.boids.iter().zip(before.iter()) {
+            assert_eq!(boid.position, before.position);
+            assert_eq!(boid.velocity.magnitude(), 0.0);
+        }
+
+        // Teleporting a boid is seen by grid queries straight away
+        flock.apply_to_each(|index, boid| {
+            if index == 4 {
+                boid.position = Vector3::new(1.0, 0.0, 0.0);
+            }
+        });
+        assert_eq!(flock.neighbor_count(Vector3::new(0.0, 0.0, 0.0), 5.0), 1);
+    }
+
+    #[test]
+    fn drag_slows_a_free_boid_geometrically() {
+        let mut flock = flock_of(vec![boid([0.0, 0.0, 0.0], [2.0, 0.0, 0.0])]);
+        flock.drag = 0.1;
+        let mut expected = 2.0;
+        for _ in 0..20 {
+            flock.update();
+            expected *= 0.9;
+            assert!((flock.boids[0].velocity.magnitude() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn region_queries_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut flock = flock_of(
+            (0..300)
+                .map(|_| {
+                    boid(
+                        [
+                            rng.gen_range(-40.0..40.0),
+                            rng.gen_range(-40.0..40.0),
+                            rng.gen_range(-40.0..40.0),
+                        ],
+                        [1.0, 0.0, 0.0],
+                    )
+                })
+                .collect(),
+        );
+        let center = Vector3::new(5.0, -3.0, 2.0);
+        let (min, max) = (
+            Vector3::new(-10.0, -20.0, 0.0),
+            Vector3::new(15.0, 5.0, 30.0),
+        );
+        let positions: Vec<Vector3<f64>> = flock.boids.iter().map(|boid| boid.position).collect();
+        let sphere: Vec<usize> = (0..positions.len())
+            .filter(|&i| positions[i].distance(center) <= 20.0)
+            .collect();
+        let boxed: Vec<usize> = (0..positions.len())
+            .filter(|&i| {
+                let p = positions[i];
+                (0..3).all(|axis| p[axis] >= min[axis] && p[axis] <= max[axis])
+            })
+            .collect();
+        assert!(!sphere.is_empty() && !boxed.is_empty());
+
+        assert_eq!(flock.find_within_sphere(center, 20.0), sphere);
+        assert_eq!(flock.find_within_box(min, max), boxed);
+        flock.grid_min_boids = 0;
+        flock.rebuild_grid(7.0);
+        assert_eq!(flock.find_within_sphere(center, 20.0), sphere);
+        assert_eq!(flock.find_within_box(min, max), boxed);
+    }
+
+    #[test]
+    fn behavior_clamp_keeps_weak_behaviors_visible() {
+        let net_force = |alignment: f64, clamp: Option<f64>| {
+            let mut flock = flock_of(vec![
+                boid([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+                boid([-5.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ]);
+            flock.behavior_clamp = clamp;
+            let weights = &mut flock.boids[0].weights;
+            weights.separation = 1e6;
+            weights.alignment = alignment;
+            weights.cohesion = 0.0;
+            flock.boids[0].update(&flock).acceleration
+        };
+        // How much of the net force turning alignment on changes
+        let share = |clamp| {
+            let with = net_force(1.0, clamp);
+            (with - net_force(0.0, clamp)).magnitude() / with.magnitude()
+        };
+
+        assert!(share(None) < 1e-5);
+        assert!(share(Some(1.0)) > 0.1);
+    }
+
+    #[test]
+    fn groups_steer_toward_their_own_targets() {
+        let mut flock = flock_of(vec![
+            boid([0.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            boid([0.0, 0.0, 500.0], [0.0, 0.0, 1.0]),
+            boid([0.0, 0.0, 1000.0], [0.0, 0.0, 1.0]),
+        ]);
+        flock.boids[1].group = 1;
+        flock.boids[2].group = 2;
+        flock.target = Some(Vector3::new(0.0, -1000.0, 0.0));
+        flock
+            .group_targets
+            .insert(0, Vector3::new(1000.0, 0.0, 0.0));
+        flock
+            .group_targets
+            .insert(1, Vector3::new(-1000.0, 0.0, 500.0));
+        flock.update();
+
+        assert!(flock.boids[0].velocity.x > 0.0);
+        assert!(flock.boids[1].velocity.x < 0.0);
+        // Groups without a target of their own fall back to the flock's
+        assert!(flock.boids[2].velocity.y < 0.0);
+        assert!(flock.boids[2].velocity.x.abs() < 1e-12);
+    }
+
+    #[test]
+    fn compact_remaps_tracked_boids() {
+        let mut flock = line(6, 100.0);
+        let tracked = 4;
+        assert!(flock.remove_boid(1));
+        assert!(flock.remove_boid(3));
+        assert!(!flock.remove_boid(6));
+        // Boids stay put until the flock is compacted
+        assert_eq!(flock.boids.len(), 6);
+
+        let remap = flock.compact();
+        assert_eq!(remap, vec![0, usize::MAX, 1, usize::MAX, 2, 3]);
+        assert_eq!(flock.boids.len(), 4);
+        assert_eq!(flock.boids[remap[tracked]].position.x, 400.0);
+    }
+
+    #[test]
+    fn global_cohesion_rejoins_separated_groups() {
+        let gap = |weight: f64| {
+            let mut flock = flock_of(
+                [0.0, 3.0, 300.0, 303.0]
+                    .iter()
+                    .map(|x| boid([*x, 0.0, 0.0], [0.0, 0.0, 0.0]))
+                    .collect(),
+            );
+            flock.apply_to_each(|_, boid| boid.weights.global_cohesion = weight);
+            for _ in 0..200 {
+                flock.update();
+            }
+            let x = |index: usize| flock.boids[index].position.x;
+            (x(2) + x(3) - x(0) - x(1)) / 2.0
+        };
+
+        assert!(gap(0.0) >= 300.0);
+        assert!(gap(1.0) < 200.0);
+    }
+
+    #[test]
+    fn bulk_state_round_trips() {
+        let mut flock = line(5, 3.0);
+        flock.boids[2].velocity = Vector3::new(0.0, 1.5, 0.5);
+        let before = flock.boids.clone();
+
+        let mut positions = vec![Vector3::new(0.0, 0.0, 0.0); 5];
+        let mut velocities = positions.clone();
+        flock.positions_into(&mut positions);
+        flock.velocities_into(&mut velocities);
+        flock.set_positions(&positions);
+        flock.set_velocities(&velocities);
+        assert_eq!(flock.boids, before);
+        assert_eq!(positions[4], Vector3::new(12.0, 0.0, 0.0));
+
+        let mismatch = Err(BoidError::LengthMismatch {
+            expected: 5,
+            found: 4,
+        });
+        assert_eq!(flock.try_positions_into(&mut positions[..4]), mismatch);
+        assert_eq!(flock.try_set_velocities(&velocities[..4]), mismatch);
+        assert_eq!(flock.boids, before);
+    }
+
+    #[test]
+    fn v_formation_staggers_followers_behind_the_boid_ahead() {
+        let formation = VFormation {
+            slot_angle: 0.6,
+            spacing: 8.0,
+            weight: 3.0,
+        };
+        // Mean error in distance to, and angle from straight behind, the nearest boid ahead
+        let slot_error = |flock: &Flock<Boid3D<f64>, f64>| {
+            let (mut distance_error, mut angle_error, mut followers) = (0.0, 0.0, 0.0);
+            for boid in flock.boids.iter() {
+                let ahead = flock
+                    .boids
+                    .iter()
+                    .filter(|other| (other.position - boid.position).dot(boid.velocity) > 0.0)
+                    .min_by(|a, b| {
+                        let distance =
+                            |other: &&Boid3D<f64>| other.position.distance(boid.position);
+                        distance(a).partial_cmp(&distance(b)).unwrap()
+                    });
+                if let Some(leader) = ahead {
+                    let offset = boid.position - leader.position;
+                    let forward = leader.velocity.normalize();
+                    let behind = -offset.dot(forward);
+                    let side = (offset + forward * behind).magnitude();
+                    distance_error += (offset.magnitude() - formation.spacing).abs();
+                    angle_error += (side.atan2(behind) - formation.slot_angle).abs();
+                    followers += 1.0;
+                }
+            }
+            (distance_error / followers, angle_error / followers)
+        };
+
+        for seed in 0..6 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut flock = flock_of(
+                (0..7)
+                    .map(|_| {
+                        let (x, y) = (rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+                        boid([x, y, 0.0], [1.0, 0.0, 0.0])
+                    })
+                    .collect(),
+            );
+            flock.v_formation = Some(formation);
+            flock.apply_to_each(|_, boid| {
+                boid.weights.alignment = 0.0;
+                boid.weights.cohesion = 0.0;
+                boid.weights.separation = 0.3;
+            });
+
+            let (_, angle_before) = slot_error(&flock);
+            for _ in 0..1000 {
+                flock.update();
+            }
+            let (distance_after, angle_after) = slot_error(&flock);
+            assert!(angle_after < angle_before && angle_after < 0.35);
+            assert!(distance_after < formation.spacing / 2.0);
+        }
+    }
+
+    #[test]
+    fn reflecting_twice_restores_the_boid() {
+        let normal = Vector3::new(1.0, 2.0, -0.5);
+        let point = Vector3::new(3.0, -1.0, 2.0);
+        let mut original = boid([4.0, 5.0, -6.0], [1.0, -0.5, 0.25]);
+        original.acceleration = Vector3::new(0.01, 0.02, 0.0);
+        let restored = original.reflect(normal, point).reflect(normal, point);
+        assert!(restored.position.distance(original.position) < 1e-12);
+        assert!(restored.velocity.distance(original.velocity) < 1e-12);
+        assert!(restored.acceleration.distance(original.acceleration) < 1e-12);
+    }
+
+    #[test]
+    fn symmetric_flocks_stay_symmetric() {
+        let (normal, point) = (Vector3::unit_x(), Vector3::new(0.0, 0.0, 0.0));
+        let half = vec![
+            boid([3.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            boid([8.0, 4.0, 1.0], [-1.0, 0.5, 0.0]),
+            boid([5.0, -6.0, 0.0], [0.5, 0.5, 0.5]),
+        ];
+        let mut boids = half.clone();
+        boids.extend(half.iter().map(|boid| boid.reflect(normal, point)));
+        let mut flock = flock_of(boids);
+
+        for _ in 0..20 {
+            flock.update();
+        }
+        for i in 0..half.len() {
+            let mirrored = flock.boids[i].reflect(normal, point);
+            assert!(
+                mirrored
+                    .position
+                    .distance(flock.boids[i + half.len()].position)
+                    < 1e-9
+            );
+        }
+
+        // Mirroring commutes with updating, along with everything the flock steers by
+        flock.anchor = Some(Vector3::new(4.0, 2.0, 0.0));
+        flock.obstacles = vec![
+            Obstacle::Sphere {
+                center: Vector3::new(6.0, 10.0, 0.0),
+                radius: 2.0,
+            },
+            Obstacle::Box {
+                min: Vector3::new(2.0, -12.0, -1.0),
+                max: Vector3::new(5.0, -9.0, 1.0),
+            },
+        ];
+        flock.obstacle_avoidance = Some(ObstacleAvoidance {
+            sensor_count: 0,
+            spread: 0.0,
+            range: 20.0,
+            weight: 2.0,
+        });
+        flock.flow_fields = vec![
+            FlowField::Uniform {
+                force: Vector3::new(0.2, 0.1, 0.0),
+            },
+            FlowField::Vortex {
+                center: Vector3::new(5.0, 0.0, 0.0),
+                axis: Vector3::unit_z(),
+                strength: 0.3,
+            },
+        ];
+        for boid in flock.boids.iter_mut() {
+            boid.weights.anchor = 0.5;
+        }
+        flock.boids[0].target = Some(Vector3::new(10.0, 10.0, 0.0));
+        flock.boids[0].weights.targeting = 1.0;
+
+        let mut mirrored = flock.mirror(normal, point);
+        for _ in 0..10 {
+            mirrored.update();
+            flock.update();
+        }
+        let expected = flock.mirror(normal, point);
+        for (a, b) in mirrored.boids.iter().zip(expected.boids.iter()) {
+            assert!(a.position.distance(b.position) < 1e-9);
+            assert!(a.velocity.distance(b.velocity) < 1e-9);
+        }
+    }
+
+    #[test]
+    fn grid_threshold_does_not_change_results() {
+        for &count in &[20, 300] {
+            // A jittered lattice, since a perfect one cancels separation down to rounding noise
+            let side = (count as f64).sqrt().ceil() as usize;
+            let mut direct = flock_of(
+                (0..count)
+                    .map(|i| {
+                        let angle = i as f64 * 2.3;
+                        let jitter = (i as f64 * 12.9898).sin() * 2.0;
+                        boid(
+                            [
+                                (i % side) as f64 * 6.0 + jitter,
+                                (i / side) as f64 * 6.0 - jitter * 0.5,
+                                0.0,
+                            ],
+                            [angle.cos(), angle.sin(), 0.0],
+                        )
+                    })
+                    .collect(),
+            );
+            direct.rebuild_grid(50.0);
+            direct.grid_min_boids = usize::MAX;
+            let mut gridded = direct.clone();
+            gridded.grid_min_boids = 0;
+
+            for _ in 0..10 {
+                direct.update();
+                gridded.update();
+                assert!(!direct.uses_grid() && gridded.uses_grid());
+                for (a, b) in direct.boids.iter().zip(gridded.boids.iter()) {
+                    assert!((a.position - b.position).magnitude() < 1e-9);
+                    assert!((a.velocity - b.velocity).magnitude() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn asynchronous_updates_see_earlier_boids_move() {
+        let start = flock_of(
+            (0..6)
+                .map(|i| boid([i as f64 * 5.0, (i % 2) as f64 * 3.0, 0.0], [1.0, 0.5, 0.0]))
+                .collect(),
+        );
+        assert_eq!(start.update_mode, UpdateMode::Synchronous);
+
+        let mut synchronous = start.clone();
+        let mut asynchronous = Flock {
+            update_mode: UpdateMode::Asynchronous,
+            ..start.clone()
+        };
+        synchronous.update();
+        asynchronous.update();
+
+        // Synchronous boids all look at the old state
+        let expected: Vec<Boid3D<f64>> =
+            start.boids.iter().map(|boid| boid.update(&start)).collect();
+        assert_eq!(synchronous.boids, expected);
+
+        // The first boid sees the same flock either way, but later ones see it already moved
+        assert_eq!(asynchronous.boids[0], synchronous.boids[0]);
+        assert_ne!(asynchronous.boids[5], synchronous.boids[5]);
+    }
+
+    #[test]
+    fn neighbor_graph_matches_brute_force_and_is_symmetric() {
+        let mut flock = flock_of(
+            (0..60)
+                .map(|i| {
+                    let i = i as f64;
+                    boid(
+                        [(i * 1.7).sin() * 30.0, (i * 0.9).cos() * 30.0, i % 4.0],
+                        [1.0, 0.0, 0.0],
+                    )
+                })
+                .collect(),
+        );
+        let radius = 8.0;
+        let brute: Vec<Vec<usize>> = flock
+            .boids
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                (0..flock.boids.len())
+                    .filter(|&j| j != i && a.position.distance(flock.boids[j].position) < radius)
+                    .collect()
+            })
+            .collect();
+        assert!(brute.iter().any(|neighbors| neighbors.len() > 1));
+
+        assert_eq!(flock.neighbor_graph(radius), brute);
+        flock.grid_min_boids = 0;
+        flock.rebuild_grid(5.0);
+        let graph = flock.neighbor_graph(radius);
+        assert_eq!(graph, brute);
+
+        for (i, neighbors) in graph.iter().enumerate() {
+            for &j in neighbors {
+                assert!(graph[j].contains(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn median_cohesion_ignores_an_outlier() {
+        let clustered = [
+            [5.0, 0.0, 0.0],
+            [6.0, 0.0, 0.0],
+            [7.0, 0.0, 0.0],
+            [5.0, 1.0, 0.0],
+        ];
+        let targets = |cohesion_target: CohesionTarget| {
+            let make = |outlier: bool| {
+                let mut boids = vec![boid([0.0, 0.0, 0.0], [0.0, 0.0, 0.0])];
+                boids.extend(clustered.iter().map(|p| boid(*p, [0.0, 0.0, 0.0])));
+                if outlier {
+                    boids.push(boid([45.0, 0.0, 0.0], [0.0, 0.0, 0.0]));
+                }
+                Flock {
+                    cohesion_target,
+                    ..flock_of(boids)
+                }
+            };
+            let (without, with) = (make(false), make(true));
+            (
+                without.cohesion_point(&without.boids[0], Some(0)).unwrap(),
+                with.cohesion_point(&with.boids[0], Some(0)).unwrap(),
+            )
+        };
+
+        let (without, with) = targets(CohesionTarget::Mean);
+        assert!((with - without).magnitude() > 5.0);
+
+        let (without, with) = targets(CohesionTarget::Median);
+        assert!((with - without).magnitude() < 1.0);
+    }
+
+    #[test]
+    fn median_cohesion_skips_non_finite_neighbors() {
+        let mut boids = vec![
+            boid([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]),
+            boid([4.0, 0.0, 0.0], [0.0, 0.0, 0.0]),
+            boid([6.0, 2.0, 0.0], [0.0, 0.0, 0.0]),
+        ];
+        boids.push(boid([f64::NAN, 1.0, 0.0], [0.0, 0.0, 0.0]));
+        let flock = Flock {
+            cohesion_target: CohesionTarget::Median,
+            position_snap: Some(PositionSnap::new(8)),
+            ..flock_of(boids)
+        };
+        assert_eq!(flock.neighbor_count_of(0, 20.0), 3);
+
+        let center = flock.cohesion_point(&flock.boids[0], Some(0)).unwrap();
+        assert_eq!(center, Vector3::new(5.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn disabled_behaviors_act_like_zero_weights() {
+        let start = flock_of(
+            (0..12)
+                .map(|i| {
+                    let angle = i as f64 * 0.9;
+                    boid(
+                        [(i % 4) as f64 * 6.0, (i / 4) as f64 * 6.0, 0.0],
+                        [angle.cos(), angle.sin(), 0.0],
+                    )
+                })
+                .collect(),
+        );
+        let original = start.boids[0].get_weights().alignment;
+        assert!(original != 0.0);
+
+        let mut disabled = start.clone();
+        disabled.behaviors.alignment = false;
+        let mut zeroed = start.clone();
+        for boid in zeroed.boids.iter_mut() {
+            let weights = BoidWeights {
+                alignment: 0.0,
+                ..*boid.get_weights()
+            };
+            boid.set_weights(weights);
+        }
+        for _ in 0..5 {
+            disabled.update();
+            zeroed.update();
+            for (a, b) in disabled.boids.iter().zip(zeroed.boids.iter()) {
+                assert_eq!((a.position, a.velocity), (b.position, b.velocity));
+            }
+        }
+        assert_eq!(disabled.boids[0].get_weights().alignment, original);
+
+        // Turning alignment back on makes the flock behave as if it had never been off
+        let mut enabled = disabled.clone();
+        enabled.behaviors.alignment = true;
+        let mut fresh = Flock {
+            boids: disabled.boids.clone(),
+            ..start.clone()
+        };
+        enabled.update();
+        fresh.update();
+        disabled.update();
+        assert_eq!(enabled.boids, fresh.boids);
+        assert_ne!(enabled.boids, disabled.boids);
+    }
+
+    #[test]
+    fn rewind_restores_earlier_states() {
+        let mut flock = Flock {
+            history_capacity: 3,
+            ..line(5, 4.0)
+        };
+        let mut states = vec![flock.boids.clone()];
+        for _ in 0..5 {
+            flock.update();
+            states.push(flock.boids.clone());
+        }
+        assert_eq!(flock.history.len(), 3);
+
+        // Too far back, or not at all, changes nothing
+        assert!(!flock.rewind(4));
+        assert!(!flock.rewind(0));
+        assert_eq!(flock.boids, states[5]);
+        assert_eq!(flock.step, 5);
+
+        assert!(flock.rewind(2));
+        assert_eq!(flock.boids, states[3]);
+        assert_eq!(flock.step, 3);
+        assert_eq!(flock.history.len(), 1);
+
+        // Replaying from there takes the same path again
+        flock.update();
+        assert_eq!(flock.boids, states[4]);
+        assert!(flock.rewind(2));
+        assert_eq!(flock.boids, states[2]);
+        assert!(!flock.rewind(1));
+    }
+
+    #[test]
+    fn behaviors_never_count_the_boid_itself() {
+        // Two identical boids at the same spot, and a third nearby
+        let twin = boid([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        let flock = flock_of(vec![
+            twin.clone(),
+            twin,
+            boid([3.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ]);
+
+        for (index, expected) in [(0, vec![1, 2]), (1, vec![0, 2]), (2, vec![0, 1])] {
+            let mut seen = Vec::new();
+            flock.neighbors_excluding_self(&flock.boids[index], Some(index), 10.0, |other, _| {
+                seen.push(other)
+            });
+            seen.sort_unstable();
+            assert_eq!(seen, expected);
+        }
+
+        // The cohesion center is the mean of the other two boids only
+        assert_eq!(
+            flock.cohesion_point(&flock.boids[0], Some(0)),
+            Some(Vector3::new(1.5, 0.0, 0.0))
+        );
+        assert_eq!(
+            flock.cohesion_point(&flock.boids[2], Some(2)),
+            Some(Vector3::new(0.0, 0.0, 0.0))
+        );
+
+        // A lone boid has nothing to align or cohere with, so it gets the isolated force
+        let lone = flock_of(vec![boid([0.0, 0.0, 0.0], [1.0, 0.0, 0.0])]);
+        assert_eq!(lone.cohesion_point(&lone.boids[0], Some(0)), None);
+        assert_eq!(lone.boids[0].align(&lone), Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(lone.boids[0].cohesion(&lone), Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(lone.boids[0].separate(&lone), Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn weighted_targets_pull_towards_their_blend() {
+        let pull = |targets: Vec<(Vector3<f64>, f64)>, target_policy: TargetPolicy| {
+            let flock = Flock {
+                targets,
+                target_policy,
+                ..flock_of(vec![boid([0.0, 10.0, 0.0], [0.0, 0.0, 0.0])])
+            };
+            let boid = &flock.boids[0];
+            flock.targeting_force_towards(flock.target_of(boid), boid.position)
+        };
+        let (left, right) = (Vector3::new(-10.0, 0.0, 0.0), Vector3::new(10.0, 0.0, 0.0));
+
+        // Equal weights pull straight towards the midpoint
+        let force = pull(
+            vec![(left, 1.0), (right, 1.0)],
+            TargetPolicy::WeightedAverage,
+        );
+        assert!(force.y < 0.0 && force.x.abs() < 1e-12);
+
+        // A heavier target drags the blend its way
+        let force = pull(
+            vec![(left, 1.0), (right, 3.0)],
+            TargetPolicy::WeightedAverage,
+        );
+        assert!(force.y < 0.0 && force.x > 0.0);
+        let force = pull(vec![(left, 1.0), (right, 3.0)], TargetPolicy::MostAppealing);
+        assert!(
+            (force.normalize() - (right - Vector3::new(0.0, 10.0, 0.0)).normalize()).magnitude()
+                < 1e-9
+        );
+
+        // A single weighted target acts like the plain `target`
+        let single = pull(vec![(right, 2.0)], TargetPolicy::WeightedAverage);
+        let flock = Flock {
+            target: Some(right),
+            ..flock_of(vec![boid([0.0, 10.0, 0.0], [0.0, 0.0, 0.0])])
+        };
+        let boid = &flock.boids[0];
+        assert_eq!(
+            single,
+            flock.targeting_force_towards(flock.target_of(boid), boid.position)
+        );
+    }
+
+    #[test]
+    fn settling_organizes_the_flock_silently() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let mut flock = flock_of(
+            (0..16)
+                .map(|i| {
+                    let angle = i as f64 * 2.4;
+                    boid(
+                        [(i % 4) as f64 * 5.0, (i / 4) as f64 * 5.0, 0.0],
+                        [angle.cos(), angle.sin(), 0.0],
+                    )
+                })
+                .collect(),
+        );
+        for boid in flock.boids.iter_mut() {
+            boid.weights.alignment = 4.0;
+            boid.max_force = 0.2;
+        }
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (hook_calls, event_calls) = (calls.clone(), calls.clone());
+        flock.pre_step = Some(StepHook::new(move |_| {
+            hook_calls.fetch_add(1, Ordering::SeqCst);
+        }));
+        flock.on_event = Some(EventCallback::new(move |_| {
+            event_calls.fetch_add(1, Ordering::SeqCst);
+        }));
+        flock.event_bounds = Some(Bounds::new(
+            Vector3::new(-1.0, -1.0, -1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+        ));
+        flock.metrics = Some(MetricsCollector::new());
+        flock.history_capacity = 4;
+        flock.startle(Vector3::new(100.0, 100.0, 0.0), 1000.0);
+        flock.boids[3].raise_panic(0.5);
+        let panic: Vec<f64> = flock.boids.iter().map(|boid| boid.panic).collect();
+
+        let before = flock.statistics().polarization;
+        flock.settle(200);
+        let settled: Vec<f64> = flock.boids.iter().map(|boid| boid.panic).collect();
+        assert_eq!(settled, panic);
+        assert!(flock.statistics().polarization > before + 0.2);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(flock.step, 0);
+        assert!(flock.metrics.as_ref().unwrap().is_empty());
+        assert!(flock.history.is_empty());
+        assert_eq!(flock.startle.unwrap().elapsed, 0.0);
+    }
+
+    #[test]
+    fn anchor_only_pulls_strays() {
+        let flock = Flock {
+            anchor: Some(Vector3::new(0.0, 0.0, 0.0)),
+            anchor_radius: 50.0,
+            ..flock_of(vec![
+                boid([200.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+                boid([10.0, 10.0, 0.0], [0.0, 1.0, 0.0]),
+            ])
+        };
+        let force = |index: usize| {
+            let boid = &flock.boids[index];
+            flock.anchor_force(boid.position, boid.velocity, boid.max_speed, boid.max_force)
+        };
+
+        // The stray is turned back towards the anchor
+        let stray = force(0);
+        assert!(stray.x < 0.0);
+        assert!(stray.magnitude() > 0.0);
+
+        assert_eq!(force(1), Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(
+            Flock {
+                anchor: None,
+                ..flock.clone()
+            }
+            .anchor_force(
+                Vector3::new(200.0, 0.0, 0.0),
+                Vector3::new(0.0, 1.0, 0.0),
+                2.0,
+                0.03
+            ),
+            Vector3::new(0.0, 0.0, 0.0)
+        );
+
+        // Over time the stray heads back in
+        let mut flock = Flock {
+            boids: vec![flock.boids[0].clone()],
+            ..flock
+        };
+        flock.boids[0].weights.anchor = 1.0;
+        for _ in 0..2000 {
+            flock.update();
+        }
+        assert!(flock.boids[0].position.magnitude() < 200.0);
+    }
+
+    #[test]
+    fn perception_ellipsoid_sees_further_ahead() {
+        let mut flock = flock_of(vec![
+            boid([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            boid([40.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            boid([0.0, 40.0, 0.0], [1.0, 0.0, 0.0]),
+        ]);
+        flock.perception_ellipsoid = Some(PerceptionEllipsoid::new(2.0, 0.5).unwrap());
+        let seen = |flock: &Flock<Boid3D<f64>, f64>| {
+            let mut seen = Vec::new();
+            flock.neighbors_excluding_self(&flock.boids[0], Some(0), 25.0, |index, _| {
+                seen.push(index)
+            });
+            seen
+        };
+        assert_eq!(seen(&flock), vec![1]);
+
+        // The same neighbors at the same distance are both out of a plain sphere
+        flock.perception_ellipsoid = None;
+        assert!(seen(&flock).is_empty());
+
+        assert!(PerceptionEllipsoid::new(0.0, 1.0).is_err());
+        assert_eq!(
+            PerceptionEllipsoid::new(1.0, f64::NAN),
+            Err(BoidError::InvalidParameter { name: "lateral" })
+        );
+        flock.perception_ellipsoid = Some(PerceptionEllipsoid {
+            forward: 2.0,
+            lateral: 0.0,
+        });
+        assert!(flock.validate().is_err());
+    }
+
+    #[test]
+    fn boids_rest_on_their_target() {
+        let target = Vector3::new(3.0, 4.0, 5.0);
+        for avoiding in [false, true].iter().copied() {
+            let mut flock = Flock {
+                target: Some(target),
+                targeting_avoids_obstacles: avoiding,
+                obstacles: vec![Obstacle::Sphere {
+                    center: Vector3::new(50.0, 0.0, 0.0),
+                    radius: 5.0,
+                }],
+                ..flock_of(vec![boid([3.0, 4.0, 5.0], [0.0, 0.0, 0.0])])
+            };
+            assert_eq!(
+                flock.targeting_force(0, target),
+                Vector3::new(0.0, 0.0, 0.0)
+            );
+
+            for _ in 0..20 {
+                flock.update();
+                assert_eq!(flock.boids[0].position, target);
+                assert_eq!(flock.boids[0].velocity, Vector3::new(0.0, 0.0, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_come_nearest_first_with_ties_by_index() {
+        let mut flock = flock_of(
+            [5.0, -2.0, 2.0, 9.0, 100.0, 10.0, -9.0]
+                .iter()
+                .map(|x| boid([*x, 0.0, 0.0], [1.0, 0.0, 0.0]))
+                .collect(),
+        );
+        let expected = vec![(1, 2.0), (2, 2.0), (0, 5.0), (3, 9.0), (6, 9.0), (5, 10.0)];
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+
+        for &min_boids in &[usize::MAX, 0] {
+            flock.rebuild_grid(4.0);
+            flock.grid_min_boids = min_boids;
+            assert_eq!(flock.uses_grid(), min_boids == 0);
+            assert_eq!(flock.neighbors_sorted(origin, 10.0), expected);
+            assert_eq!(flock.neighbors_sorted(origin, 10.0), expected);
+        }
+    }
+
+    #[test]
+    fn energy_conserving_steering_turns_more_smoothly() {
+        let mean_turn = |energy_conserving: bool| {
+            let mut rng = StdRng::seed_from_u64(21);
+            let mut flock = Flock {
+                energy_conserving,
+                ..flock_of(
+                    (0..60)
+                        .map(|_| {
+                            let angle: f64 = rng.gen_range(0.0..std::f64::consts::TAU);
+                            boid(
+                                [rng.gen_range(0.0..60.0), rng.gen_range(0.0..60.0), 0.0],
+                                [angle.cos() * 1.5, angle.sin() * 1.5, 0.0],
+                            )
+                        })
+                        .collect(),
+                )
+            };
+            let mut total = 0.0;
+            let mut turns = 0;
+            for _ in 0..40 {
+                flock.update();
+                for boid in flock.boids.iter() {
+                    if boid.velocity.magnitude2() > 0.0 && boid.last_velocity.magnitude2() > 0.0 {
+                        total += boid.velocity.angle(boid.last_velocity).0;
+                        turns += 1;
+                    }
+                }
+            }
+            total / turns as f64
+        };
+
+        assert!(mean_turn(true) < mean_turn(false));
+    }
+
+    #[test]
+    fn dead_zone_stops_clusters_collapsing() {
+        let ring = |spacing: f64| {
+            let mut flock = flock_of(
+                (0..6)
+                    .map(|i| {
+                        let angle = i as f64 * std::f64::consts::TAU / 6.0;
+                        boid(
+                            [angle.cos() * spacing, angle.sin() * spacing, 0.0],
+                            [0.0, 0.0, 1.0],
+                        )
+                    })
+                    .collect(),
+            );
+            flock.cohesion_dead_zone = 10.0;
+            flock
+        };
+
+        // Packed inside the dead zone, nothing pulls the boids further in
+        let dense = ring(3.0);
+        for boid in dense.boids.iter() {
+            assert_eq!(boid.cohesion(&dense), Vector3::new(0.0, 0.0, 0.0));
+        }
+
+        // Spread out, every boid still pulls towards the middle
+        let spread = ring(20.0);
+        for boid in spread.boids.iter() {
+            assert!(boid.cohesion(&spread).dot(-boid.position) > 0.0);
+        }
+    }
+
+    #[test]
+    fn density_peaks_in_a_cluster_and_integrates_to_the_count() {
+        // One boid's kernel integrates to one
+        let single = flock_of(vec![boid([0.0, 0.0, 0.0], [0.0, 0.0, 0.0])]);
+        let step = 0.1;
+        let cells = (-20..20).map(|i| (i as f64 + 0.5) * step);
+        let mut total = 0.0;
+        for x in cells.clone() {
+            for y in cells.clone() {
+                for z in cells.clone() {
+                    total += single.density_at(Vector3::new(x, y, z), 1.0);
+                }
+            }
+        }
+        assert!((total * step.powi(3) - 1.0).abs() < 1e-2);
+
+        // A cluster reads densest at its center, falling off smoothly to nothing
+        let cluster = flock_of(
+            (0..7)
+                .map(|i| {
+                    let angle = i as f64;
+                    boid([angle.cos(), angle.sin(), 0.0], [0.0, 0.0, 0.0])
+                })
+                .collect(),
+        );
+        let samples: Vec<f64> = (0..60)
+            .map(|i| cluster.density_at(Vector3::new(0.0, 0.0, i as f64 * 0.1), 2.0))
+            .collect();
+        assert!(samples.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(samples
+            .windows(2)
+            .all(|pair| pair[0] - pair[1] < samples[0] * 0.1));
+        assert_eq!(samples[59], 0.0);
+        assert_eq!(single.density_at(Vector3::new(0.0, 0.0, 0.0), 0.0), 0.0);
+    }
+
+    #[test]
+    fn max_acceleration_caps_the_net_force() {
+        // Four saturated behaviors pushing the same way
+        let push = Vector3::new(0.03, 0.0, 0.0);
+        let mut forces = [push; 4];
+        let mut flock = flock_of(vec![boid([0.0, 0.0, 0.0], [0.0, 1.0, 0.0])]);
+        flock.clamp_net_force(&mut forces);
+        assert_eq!(forces, [push; 4]);
+
+        flock.max_acceleration = 0.05;
+        flock.clamp_net_force(&mut forces);
+        let net = forces
+            .iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |sum, force| sum + *force);
+        assert!((net - Vector3::new(0.05, 0.0, 0.0)).magnitude() < 1e-12);
+
+        // The same cap holds for a whole update
+        flock.target = Some(Vector3::new(1000.0, 0.0, 0.0));
+        flock.flow_fields = vec![FlowField::Uniform {
+            force: Vector3::new(0.0, 0.5, 0.0),
+        }];
+        flock.integration = Integration::Accumulated;
+        flock.update();
+        assert!((flock.boids[0].acceleration.magnitude() - 0.05).abs() < 1e-12);
+    }
+
+    #[test]
+    fn boids_sort_farthest_first_with_ties_by_index() {
+        let xs = [3.0, -8.0, 1.0, 8.0, 0.0, -3.0];
+        let flock = flock_of(
+            xs.iter()
+                .map(|x| boid([*x, 0.0, 0.0], [0.0, 0.0, 0.0]))
+                .collect(),
+        );
+        let camera = Vector3::new(0.0, 4.0, 0.0);
+
+        let mut expected: Vec<usize> = (0..xs.len()).collect();
+        expected.sort_by(|a, b| {
+            let distance = |i: usize| Vector3::new(xs[i], 0.0, 0.0).distance(camera);
+            distance(*b)
+                .partial_cmp(&distance(*a))
+                .unwrap()
+                .then(a.cmp(b))
+        });
+        assert_eq!(expected, vec![1, 3, 0, 5, 2, 4]);
+        assert_eq!(flock.sorted_by_distance_to(camera), expected);
+    }
+
+    #[test]
+    fn targeting_steers_around_blocking_obstacles() {
+        let sphere = Obstacle::Sphere {
+            center: Vector3::new(50.0, 0.0, 0.0),
+            radius: 10.0,
+        };
+        let mut flock = Flock {
+            target: Some(Vector3::new(100.0, 0.0, 0.0)),
+            obstacles: vec![sphere],
+            ..flock_of(vec![boid([0.0, 0.0, 0.0], [0.0, 0.0, 0.0])])
+        };
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+
+        // Straight through the sphere unless asked otherwise
+        let direct = flock.targeting_force(0, origin);
+        assert_eq!(direct, Vector3::new(100.0, 0.0, 0.0));
+
+        flock.targeting_avoids_obstacles = true;
+        let detour = flock.targeting_force(0, origin);
+        assert!((detour.magnitude() - 100.0).abs() < 1e-9);
+        let clearance = (10.0f64 / 50.0).asin();
+        assert!(detour.angle(direct).0 >= clearance - 1e-9);
+
+        // Following it, the boid gets past the sphere without entering it
+        let mut furthest: f64 = 0.0;
+        for _ in 0..60 {
+            flock.update();
+            let position = flock.boids[0].position;
+            assert!(position.distance(Vector3::new(50.0, 0.0, 0.0)) >= 10.0);
+            furthest = furthest.max(position.x);
+        }
+        assert!(furthest > 60.0);
+    }
+
+    #[test]
+    fn update_checked_reports_motion() {
+        let mut moving = flock_of(vec![
+            boid([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            boid([10.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ]);
+        let before = moving.boids.clone();
+        let (next, changed) = moving.update_checked();
+        assert!(changed);
+        assert_eq!(moving.boids, before);
+        moving.update();
+        assert_eq!(next.boids, moving.boids);
+        assert_eq!(next.step, moving.step);
+
+        // Out of each other's reach and standing still, nothing can move
+        let mut settled = flock_of(vec![
+            boid([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]),
+            boid([100.0, 0.0, 0.0], [0.0, 0.0, 0.0]),
+        ]);
+        for _ in 0..5 {
+            let (next, changed) = settled.update_checked();
+            assert!(!changed);
+            settled = next;
+        }
+        assert_eq!(settled.step, 5);
+    }
+
+    #[test]
+    fn stride_updates_cover_every_boid_once() {
+        let mut flock = flock_of(
+            (0..10)
+                .map(|i| {
+                    let angle = i as f64 * 0.7;
+                    boid(
+                        [i as f64 * 8.0, (i % 2) as f64 * 5.0, 0.0],
+                        [angle.cos(), angle.sin(), 0.0],
+                    )
+                })
+                .collect(),
+        );
+        flock.refresh_grid();
+
+        let mut updates = vec![0; 10];
+        for phase in 0..3 {
+            let before = flock.clone();
+            flock.stride_update(3, phase);
+            for (i, (old, new)) in before.boids.iter().zip(flock.boids.iter()).enumerate() {
+                if i % 3 == phase {
+                    // Advanced with the whole flock as it was before the call
+                    assert_eq!(*new, old.update(&before));
+                    assert_ne!(new.position, old.position);
+                    updates[i] += 1;
+                } else {
+                    assert_eq!(new, old);
+                }
+            }
+        }
+        assert_eq!(updates, vec![1; 10]);
+    }
+
+    #[test]
+    fn blind_spot_hides_only_what_is_right_behind() {
+        let side_rear = Vector3::new(-(0.7f64.cos()), 0.7f64.sin(), 0.0) * 10.0;
+        let mut flock = flock_of(vec![
+            boid([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            boid([-10.0, 0.0, 0.0], [1.0, 0.0, 0.0]),
+            boid(side_rear.into(), [1.0, 0.0, 0.0]),
+        ]);
+        let seen = |flock: &Flock<Boid3D<f64>, f64>| {
+            let mut seen = Vec::new();
+            flock.neighbors_excluding_self(&flock.boids[0], Some(0), 20.0, |index, _| {
+                seen.push(index)
+            });
+            seen.sort_unstable();
+            seen
+        };
+        assert_eq!(seen(&flock), vec![1, 2]);
+
+        flock.blind_angle = 0.5;
+        assert_eq!(seen(&flock), vec![2]);
+
+        // With only the hidden neighbor left, there is nothing to cohere with
+        flock.boids.truncate(2);
+        assert_eq!(flock.boids[0].cohesion(&flock), Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn priority_neighbors_are_avoided_more_within_max_force() {
+        // A lone neighbor at the same distance, either a peer or a predator ten times the size
+        let lone = |radius: f64| {
+            let neighbor = Boid3D {
+                radius,
+                ..boid([5.0, 0.0, 0.0], [0.0, 0.0, 0.0])
+            };
+            Flock {
+                separation_priority: SeparationPriority::BySize,
+                ..flock_of(vec![boid([0.0, 0.0, 0.0], [0.0, 0.0, 0.0]), neighbor])
+            }
+        };
+        let (peer, predator) = (lone(1.0), lone(10.0));
+        let max_force = peer.boids[0].max_force;
+
+        let from_peer = peer.boids[0].separate(&peer);
+        let from_predator = predator.boids[0].separate(&predator);
+        assert!(from_peer.x < 0.0 && from_predator.x < 0.0);
+        assert!(from_predator.magnitude() > from_peer.magnitude() * 5.0);
+        assert!(from_predator.magnitude() <= max_force * 10.0 + 1e-12);
+
+        // The same holds for 2D boids
+        let (peer, predator) = (peer.to_2d(), predator.to_2d());
+        let from_peer = peer.boids[0].separate(&peer);
+        let from_predator = predator.boids[0].separate(&predator);
+        assert!(from_peer.x < 0.0 && from_predator.x < 0.0);
+        assert!(from_predator.magnitude() > from_peer.magnitude() * 5.0);
+        assert!(from_predator.magnitude() <= max_force * 10.0 + 1e-12);
+    }
+
+    #[test]
+    fn compute_next_matches_update_without_touching_the_flock() {
+        let mut flock = flock_of(
+            (0..10)
+                .map(|i| {
+                    let angle = i as f64 * 0.9;
+                    boid(
+                        [(i % 4) as f64 * 1.5, (i / 4) as f64 * 1.5, 0.0],
+                        [angle.cos(), angle.sin(), 0.0],
+                    )
+                })
+                .collect(),
+        );
+        flock.rebuild_grid(2.0);
+        let before = flock.clone();
+
+        let mut out = vec![boid([0.0, 0.0, 0.0], [1.0, 0.0, 0.0]); 20];
+        flock.compute_next(&mut out);
+        assert_eq!(flock.boids, before.boids);
+        let mut again = Vec::new();
+        flock.compute_next(&mut again);
+        assert_eq!(again, out);
+
+        let mut swapped = flock.clone();
+        std::mem::swap(&mut swapped.boids, &mut out);
+        swapped.refresh_grid();
+        flock.update();
+        assert_eq!(swapped.boids, flock.boids);
+    }
+
+    #[test]
+    fn inert_flocks_are_reported() {
+        let mut flock = line(3, 1.0);
+        for boid in flock.boids.iter_mut() {
+            boid.weights = boid.weights.map(|_| 0.0);
+        }
+        assert!(flock.is_inert());
+
+        // Any one behavior that can act is enough
+        let mut separating = flock.clone();
+        separating.boids[1].weights.separation = 1.0;
+        assert!(!separating.is_inert());
+
+        let mut targeting = flock.clone();
+        targeting.boids[0].weights.targeting = 1.0;
+        assert!(targeting.is_inert());
+        targeting.target = Some(Vector3::new(10.0, 0.0, 0.0));
+        assert!(!targeting.is_inert());
+
+        let dragged = Flock {
+            drag: 0.1,
+            ..flock.clone()
+        };
+        assert!(!dragged.is_inert());
+
+        // Weights do nothing for behaviors that are switched off
+        let mut disabled = separating;
+        disabled.behaviors.separation = false;
+        assert!(disabled.is_inert());
+    }
+
+    #[test]
+    fn side_sensors_catch_walls_the_center_ray_misses() {
+        // A wall off to the left, which the boid is drifting towards
+        let mut flock = flock_of(vec![boid([0.0, 0.0, 0.0], [1.0, 0.2, 0.0])]);
+        flock.obstacles.push(Obstacle::Box {
+            min: Vector3::new(0.0, 2.0, -5.0),
+            max: Vector3::new(100.0, 3.0, 5.0),
+        });
+        let avoidance = ObstacleAvoidance {
+            sensor_count: 2,
+            spread: 0.5,
+            range: 10.0,
+            weight: 1.0,
+        };
+
+        let (position, velocity) = (flock.boids[0].position, flock.boids[0].velocity);
+        let steer =
+            |flock: &Flock<Boid3D<f64>, f64>| flock.avoid_obstacles(position, velocity, 2.0, 0.03);
+        let forward_only = ObstacleAvoidance {
+            sensor_count: 0,
+            ..avoidance
+        };
+        assert!(!flock.obstacles[0].blocks(position, position + velocity.normalize() * 10.0));
+
+        flock.obstacle_avoidance = Some(forward_only);
+        assert_eq!(steer(&flock), Vector3::new(0.0, 0.0, 0.0));
+        flock.obstacle_avoidance = Some(avoidance);
+        let away = steer(&flock);
+        assert!(away.y < 0.0);
+        assert!(away.magnitude() <= 0.03 + 1e-12);
     }
 }
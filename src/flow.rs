@@ -0,0 +1,123 @@
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, Vector3};
+
+use crate::boids::frame::{reflect_point, reflect_vector};
+
+/// An ambient force field that pushes boids around independently of their neighbors
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FlowField<U: BaseNum + Float> {
+    /// The same force everywhere, like a steady wind
+    Uniform { force: Vector3<U> },
+    /// A swirl around the line through `center` along `axis`.
+    ///
+    /// The force is tangential to circles around the axis, with a magnitude of `strength`, so boids
+    /// orbit it. Looking down `axis`, positive strengths swirl counter-clockwise.
+    Vortex {
+        center: Vector3<U>,
+        axis: Vector3<U>,
+        strength: U,
+    },
+}
+
+impl<U: BaseNum + Float> FlowField<U> {
+    /// Mirror this field across the plane through `plane_point` with normal `plane_normal`.
+    ///
+    /// A mirrored vortex swirls the other way, so its axis is flipped as well as reflected.
+    pub fn reflect(&self, plane_normal: Vector3<U>, plane_point: Vector3<U>) -> Self {
+        match *self {
+            FlowField::Uniform { force } => FlowField::Uniform {
+                force: reflect_vector(force, plane_normal),
+            },
+            FlowField::Vortex {
+                center,
+                axis,
+                strength,
+            } => FlowField::Vortex {
+                center: reflect_point(center, plane_normal, plane_point),
+                axis: -reflect_vector(axis, plane_normal),
+                strength,
+            },
+        }
+    }
+
+    /// Get the force this field applies at `position`
+    pub fn force_at(&self, position: Vector3<U>) -> Vector3<U> {
+        let zero = Vector3::new(U::zero(), U::zero(), U::zero());
+        match *self {
+            FlowField::Uniform { force } => force,
+            FlowField::Vortex {
+                center,
+                axis,
+                strength,
+            } => {
+                if axis.magnitude2() <= U::zero() {
+                    return zero;
+                }
+                let axis = axis.normalize();
+
+                // Only the offset perpendicular to the axis matters
+                let offset = position - center;
+                let radial = offset - axis * offset.dot(axis);
+                let tangent = axis.cross(radial);
+                if tangent.magnitude2() <= U::zero() {
+                    return zero;
+                }
+                tangent.normalize() * strength
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::FlowField;
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    #[test]
+    fn vortex_boids_orbit_the_center() {
+        let vortex = FlowField::Vortex {
+            center: Vector3::new(0.0, 0.0, 0.0),
+            axis: Vector3::new(0.0, 0.0, 2.0),
+            strength: 0.05,
+        };
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            boids: vec![Boid3D::new_with_velocity(
+                Vector3::new(20.0, 0.0, 0.0),
+                Vector3::new(0.0, 0.0, 0.0),
+            )],
+            flow_fields: vec![vortex],
+            ..Flock::default()
+        };
+
+        // The push is sideways, counter-clockwise around +Z
+        let radial = Vector3::new(1.0, 0.0, 0.0);
+        let force = vortex.force_at(radial * 20.0);
+        assert!(force.dot(radial).abs() < 1e-12);
+        assert!(force.y > 0.0);
+
+        // Starting from rest, the boid sets off around the center rather than away from it
+        flock.update();
+        let velocity = flock.boids[0].velocity;
+        assert!(velocity.dot(radial).abs() < 1e-9 && velocity.y > 0.0);
+
+        // It keeps sweeping counter-clockwise around the axis
+        let mut angle: f64 = 0.0;
+        let mut swept = 0.0;
+        for _ in 0..200 {
+            flock.update();
+            let position = flock.boids[0].position;
+            assert!(position.z.abs() < 1e-9);
+            let next = position.y.atan2(position.x);
+            let mut turn = next - angle;
+            if turn < -std::f64::consts::PI {
+                turn += std::f64::consts::TAU;
+            }
+            assert!(turn > 0.0);
+            swept += turn;
+            angle = next;
+        }
+        assert!(swept > std::f64::consts::PI);
+    }
+}
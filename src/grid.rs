@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, Vector3};
+
+/// A uniform grid of boid indices, used to speed up spatial queries.
+///
+/// The grid stores the boid indices found in each cell at the time it was built, so it must be
+/// refreshed whenever boids move. `Flock::update` does this automatically for the flock's grid.
+///
+/// A grid with some `slack` acts as a neighbor cache: boids are only moved between cells once they
+/// drift more than `slack` from where they were last placed, and queries are widened by `slack` to
+/// stay exact.
+#[derive(Debug, Clone)]
+pub struct SpatialGrid<U: BaseNum + Float> {
+    cell_size: U,
+    slack: U,
+    cells: BTreeMap<(i64, i64, i64), Vec<usize>>,
+    anchors: Vec<Vector3<U>>,
+    reused: usize,
+    refreshed: usize,
+}
+
+impl<U: BaseNum + Float> SpatialGrid<U> {
+    /// Build a grid from boid positions, indexed in iteration order
+    pub fn new(positions: impl IntoIterator<Item = Vector3<U>>, cell_size: U) -> Self {
+        Self::with_slack(positions, cell_size, U::zero())
+    }
+
+    /// Build a grid that only re-buckets boids once they move more than `slack`
+    pub fn with_slack(
+        positions: impl IntoIterator<Item = Vector3<U>>,
+        cell_size: U,
+        slack: U,
+    ) -> Self {
+        let mut grid = Self {
+            cell_size,
+            slack: slack.max(U::zero()),
+            cells: BTreeMap::new(),
+            anchors: positions.into_iter().collect(),
+            reused: 0,
+            refreshed: 0,
+        };
+        for (index, position) in grid.anchors.iter().enumerate() {
+            let key = grid.key(*position);
+            grid.cells.entry(key).or_default().push(index);
+        }
+        grid.refreshed = grid.anchors.len();
+        grid
+    }
+
+    /// Bring the grid up to date with new boid positions.
+    ///
+    /// Only boids that moved more than `slack` since they were last placed are re-bucketed. If the
+    /// number of boids changed, the grid is rebuilt from scratch.
+    pub fn refresh(&mut self, positions: impl IntoIterator<Item = Vector3<U>>) {
+        let positions: Vec<Vector3<U>> = positions.into_iter().collect();
+        if positions.len() != self.anchors.len() {
+            *self = Self::with_slack(positions, self.cell_size, self.slack);
+            return;
+        }
+
+        self.reused = 0;
+        self.refreshed = 0;
+        for (index, position) in positions.into_iter().enumerate() {
+            let anchor = self.anchors[index];
+            if (position - anchor).magnitude() <= self.slack {
+                self.reused += 1;
+                continue;
+            }
+
+            // Move the boid to its new cell
+            let (old, new) = (self.key(anchor), self.key(position));
+            if old != new {
+                if let Some(cell) = self.cells.get_mut(&old) {
+                    cell.retain(|other| *other != index);
+                    if cell.is_empty() {
+                        self.cells.remove(&old);
+                    }
+                }
+                self.cells.entry(new).or_default().push(index);
+            }
+            self.anchors[index] = position;
+            self.refreshed += 1;
+        }
+    }
+
+    /// Get the distance boids may drift before being re-bucketed
+    pub fn slack(&self) -> U {
+        self.slack
+    }
+
+    /// Get how many boids kept their cached placement during the last refresh
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    /// Get how many boids were re-bucketed during the last refresh (or placed, after a rebuild)
+    pub fn refreshed(&self) -> usize {
+        self.refreshed
+    }
+
+    /// Get the edge length of each cell
+    pub fn cell_size(&self) -> U {
+        self.cell_size
+    }
+
+    /// Get the cell coordinates containing a position
+    pub fn key(&self, position: Vector3<U>) -> (i64, i64, i64) {
+        let axis = |value: U| (value / self.cell_size).floor().to_i64().unwrap_or(0);
+        (axis(position.x), axis(position.y), axis(position.z))
+    }
+
+    /// Get the indices of every boid in a cell
+    pub fn cell(&self, key: (i64, i64, i64)) -> &[usize] {
+        self.cells.get(&key).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Call `f` with the index of every boid in the cells overlapping the box around a sphere.
+    ///
+    /// This is a broad phase: callers still need to check the exact distance.
+    pub fn for_each_candidate(&self, position: Vector3<U>, radius: U, f: impl FnMut(usize)) {
+        let offset = Vector3::new(radius, radius, radius);
+        self.for_each_candidate_in_box(position - offset, position + offset, f);
+    }
+
+    /// Call `f` with the index of every boid in the cells overlapping a box, widened by `slack`.
+    ///
+    /// This is a broad phase: callers still need to check the exact position.
+    pub fn for_each_candidate_in_box(
+        &self,
+        min: Vector3<U>,
+        max: Vector3<U>,
+        mut f: impl FnMut(usize),
+    ) {
+        let slack = Vector3::new(self.slack, self.slack, self.slack);
+        let min = self.key(min - slack);
+        let max = self.key(max + slack);
+
+        // Visiting every occupied cell is cheaper than scanning a huge mostly-empty range
+        let span = |min: i64, max: i64| (max - min + 1).max(0) as u128;
+        let range = span(min.0, max.0) * span(min.1, max.1) * span(min.2, max.2);
+        if range > self.cells.len() as u128 {
+            for (key, indices) in self.cells.iter() {
+                if (min.0..=max.0).contains(&key.0)
+                    && (min.1..=max.1).contains(&key.1)
+                    && (min.2..=max.2).contains(&key.2)
+                {
+                    indices.iter().for_each(|index| f(*index));
+                }
+            }
+            return;
+        }
+
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for z in min.2..=max.2 {
+                    self.cell((x, y, z)).iter().for_each(|index| f(*index));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::SpatialGrid;
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    fn candidates(grid: &SpatialGrid<f64>, position: Vector3<f64>, radius: f64) -> Vec<usize> {
+        let mut found = Vec::new();
+        grid.for_each_candidate(position, radius, |index| found.push(index));
+        found.sort_unstable();
+        found
+    }
+
+    #[test]
+    fn slack_widens_queries_once() {
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        let mut grid = SpatialGrid::with_slack(
+            vec![Vector3::new(0.9, 0.0, 0.0), Vector3::new(2.9, 0.0, 0.0)],
+            1.0,
+            0.5,
+        );
+        // Radius plus slack reaches cell 1, but not cell 2
+        assert_eq!(candidates(&grid, origin, 1.0), vec![0]);
+
+        // A boid that drifted within the slack is still found from its old cell
+        grid.refresh(vec![
+            Vector3::new(1.3, 0.0, 0.0),
+            Vector3::new(2.9, 0.0, 0.0),
+        ]);
+        assert_eq!(grid.reused(), 2);
+        assert_eq!(candidates(&grid, Vector3::new(1.3, 0.0, 0.0), 0.1), vec![0]);
+    }
+
+    #[test]
+    fn settled_flocks_reuse_cached_neighbors() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        for i in 0..10 {
+            let mut boid = Boid3D::new_with_angle(Vector3::new(i as f64 * 100.0, 0.0, 0.0), 0.0);
+            boid.velocity = Vector3::new(0.0, 0.0, 0.0);
+            flock.boids.push(boid);
+        }
+        flock.grid_min_boids = 0;
+        flock.enable_neighbor_cache(10.0, 0.5);
+        flock.update();
+        let grid = flock.grid.as_ref().unwrap();
+        assert_eq!((grid.reused(), grid.refreshed()), (10, 0));
+
+        // Boids moving at full speed outrun the slack every update
+        flock.apply_to_each(|_, boid| boid.velocity = Vector3::new(2.0, 0.0, 0.0));
+        flock.update();
+        let grid = flock.grid.as_ref().unwrap();
+        assert_eq!((grid.reused(), grid.refreshed()), (0, 10));
+    }
+}
@@ -0,0 +1,84 @@
+use cgmath::{num_traits::Float, BaseNum};
+
+use crate::{
+    boids::Boid,
+    error::{check_length, BoidError},
+    flock::Flock,
+};
+
+impl<T: Boid<T, U> + Clone, U: BaseNum + Float> Flock<T, U> {
+    /// Blend between two recorded states of a flock, for smooth slow-motion or retimed playback.
+    ///
+    /// Boids are matched by index, and their positions and velocities are linearly interpolated, where
+    /// `t = 0` gives `a` and `t = 1` gives `b`. Everything else is copied from `a`, apart from its
+    /// history. The result is only meant to be displayed, not simulated further.
+    ///
+    /// Returns `BoidError::LengthMismatch` if the flocks have different numbers of boids.
+    pub fn interpolate(a: &Flock<T, U>, b: &Flock<T, U>, t: U) -> Result<Flock<T, U>, BoidError> {
+        check_length(a.boids.len(), b.boids.len())?;
+
+        // Weighted this way, both ends reproduce their keyframe exactly
+        let s = U::one() - t;
+        let mut frame = a.clone();
+        frame.history.clear();
+        for (boid, end) in frame.boids.iter_mut().zip(b.boids.iter()) {
+            boid.set_position(boid.position() * s + end.position() * t);
+            boid.set_velocity(boid.velocity() * s + end.velocity() * t);
+        }
+        frame.refresh_grid();
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::{boids::boid3d::Boid3D, error::BoidError, flock::Flock};
+
+    fn flock(offset: f64, heading: f64) -> Flock<Boid3D<f64>, f64> {
+        Flock {
+            boids: (0..4)
+                .map(|i| {
+                    Boid3D::new_with_angle(
+                        Vector3::new(i as f64 * 10.0 + offset, offset, 0.0),
+                        heading,
+                    )
+                })
+                .collect(),
+            ..Flock::default()
+        }
+    }
+
+    #[test]
+    fn keyframes_and_midpoints() {
+        let (a, b) = (flock(0.0, 0.0), flock(4.0, 1.0));
+
+        let start = Flock::interpolate(&a, &b, 0.0).unwrap();
+        let end = Flock::interpolate(&a, &b, 1.0).unwrap();
+        let middle = Flock::interpolate(&a, &b, 0.5).unwrap();
+        for i in 0..4 {
+            assert_eq!(start.boids[i].position, a.boids[i].position);
+            assert_eq!(start.boids[i].velocity, a.boids[i].velocity);
+            assert_eq!(end.boids[i].position, b.boids[i].position);
+            assert_eq!(end.boids[i].velocity, b.boids[i].velocity);
+            assert_eq!(
+                middle.boids[i].position,
+                (a.boids[i].position + b.boids[i].position) / 2.0
+            );
+        }
+    }
+
+    #[test]
+    fn flocks_must_match_in_size() {
+        let mut b = flock(4.0, 1.0);
+        b.boids.pop();
+        assert_eq!(
+            Flock::interpolate(&flock(0.0, 0.0), &b, 0.5).unwrap_err(),
+            BoidError::LengthMismatch {
+                expected: 4,
+                found: 3
+            }
+        );
+    }
+}
@@ -4,7 +4,61 @@
 #[macro_use]
 extern crate serde;
 
+pub mod error;
+pub use error::BoidError;
 pub mod boids;
 pub use boids::boid2d::Boid2D;
+pub use boids::boid3d::Boid3D;
 pub mod flock;
 pub use flock::Flock;
+pub mod perception;
+pub use perception::PerceptionConfig;
+pub mod bounds;
+pub use bounds::Bounds;
+pub mod constraint;
+pub use constraint::Constraint;
+pub mod startle;
+pub use startle::Startle;
+pub mod events;
+pub use events::FlockEvent;
+pub mod statistics;
+pub use statistics::{FlockStatistics, MetricsCollector};
+pub mod grid;
+pub mod octree;
+pub mod sweep;
+pub mod snap;
+pub use snap::PositionSnap;
+pub mod driver;
+pub use driver::FlockDriver;
+pub mod clock;
+pub use clock::{Clock, ManualClock};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub use clock::SystemClock;
+pub mod obstacle;
+pub use obstacle::Obstacle;
+pub mod diff;
+pub use diff::{BoidDelta, FlockDiff};
+pub mod spawn;
+pub mod validate;
+pub use validate::FlockIssue;
+pub mod schedule;
+pub use schedule::WeightSchedule;
+pub mod raycast;
+pub mod shared;
+pub use shared::SharedFlock;
+pub mod flow;
+pub use flow::FlowField;
+pub mod precision;
+pub mod projection;
+pub mod interpolate;
+pub mod thin;
+pub mod compact;
+pub mod csv;
+pub mod pack;
+pub mod soa;
+pub mod simd;
+pub mod render;
+pub use render::Renderer;
+#[cfg(feature = "ascii")]
+pub use render::AsciiRenderer;
+pub use compact::CompactFlock;
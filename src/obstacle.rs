@@ -0,0 +1,262 @@
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, MetricSpace, Vector3};
+
+use crate::boids::frame::{heading_frame, reflect_box, reflect_point};
+
+/// A solid shape that blocks boids' line of sight
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Obstacle<U: BaseNum + Float> {
+    /// A ball around a point
+    Sphere { center: Vector3<U>, radius: U },
+    /// An axis-aligned box
+    Box { min: Vector3<U>, max: Vector3<U> },
+    /// A cylinder with rounded ends, running from `start` to `end`
+    Capsule {
+        start: Vector3<U>,
+        end: Vector3<U>,
+        radius: U,
+    },
+}
+
+/// Find where a segment first enters a sphere, as a fraction of the segment
+pub(crate) fn sphere_hit<U: BaseNum + Float>(
+    from: Vector3<U>,
+    to: Vector3<U>,
+    center: Vector3<U>,
+    radius: U,
+) -> Option<U> {
+    let offset = from - center;
+    if offset.magnitude2() <= radius * radius {
+        return Some(U::zero());
+    }
+
+    // Solve |offset + t * direction| = radius
+    let direction = to - from;
+    let a = direction.magnitude2();
+    if a <= U::zero() {
+        return None;
+    }
+    let b = (U::one() + U::one()) * offset.dot(direction);
+    let c = offset.magnitude2() - radius * radius;
+    let discriminant = b * b - U::from(4.0).unwrap() * a * c;
+    if discriminant < U::zero() {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / ((U::one() + U::one()) * a);
+    if t >= U::zero() && t <= U::one() {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Find where a segment first enters an axis-aligned box, as a fraction of the segment
+fn box_hit<U: BaseNum + Float>(
+    from: Vector3<U>,
+    to: Vector3<U>,
+    min: Vector3<U>,
+    max: Vector3<U>,
+) -> Option<U> {
+    let direction = to - from;
+    let mut enter = U::zero();
+    let mut exit = U::one();
+
+    // Clip the segment against each pair of slabs
+    for axis in 0..3 {
+        let (origin, delta, low, high) = (from[axis], direction[axis], min[axis], max[axis]);
+        if delta.abs() <= U::epsilon() {
+            if origin < low || origin > high {
+                return None;
+            }
+            continue;
+        }
+        let (mut near, mut far) = ((low - origin) / delta, (high - origin) / delta);
+        if near > far {
+            std::mem::swap(&mut near, &mut far);
+        }
+        enter = enter.max(near);
+        exit = exit.min(far);
+        if enter > exit {
+            return None;
+        }
+    }
+    Some(enter)
+}
+
+/// Find where a segment first enters a capsule, as a fraction of the segment
+fn capsule_hit<U: BaseNum + Float>(
+    from: Vector3<U>,
+    to: Vector3<U>,
+    start: Vector3<U>,
+    end: Vector3<U>,
+    radius: U,
+) -> Option<U> {
+    let axis = end - start;
+    let length = axis.magnitude();
+    if length <= U::epsilon() {
+        return sphere_hit(from, to, start, radius);
+    }
+    let axis = axis / length;
+
+    // Starting inside the capsule counts as an immediate hit
+    let along = (from - start).dot(axis).max(U::zero()).min(length);
+    if from.distance(start + axis * along) <= radius {
+        return Some(U::zero());
+    }
+
+    // Hits on the rounded ends
+    let mut best = [
+        sphere_hit(from, to, start, radius),
+        sphere_hit(from, to, end, radius),
+    ]
+    .iter()
+    .flatten()
+    .fold(None, |best: Option<U>, t| {
+        Some(best.map_or(*t, |best| best.min(*t)))
+    });
+
+    // Hit on the side of the cylinder, found by solving in the plane perpendicular to the axis
+    let direction = to - from;
+    let offset = from - start;
+    let direction_perp = direction - axis * direction.dot(axis);
+    let offset_perp = offset - axis * offset.dot(axis);
+    let a = direction_perp.magnitude2();
+    if a > U::epsilon() {
+        let b = (U::one() + U::one()) * offset_perp.dot(direction_perp);
+        let c = offset_perp.magnitude2() - radius * radius;
+        let discriminant = b * b - U::from(4.0).unwrap() * a * c;
+        if discriminant >= U::zero() {
+            let t = (-b - discriminant.sqrt()) / ((U::one() + U::one()) * a);
+            let along = (offset + direction * t).dot(axis);
+            if t >= U::zero() && t <= U::one() && along >= U::zero() && along <= length {
+                best = Some(best.map_or(t, |best| best.min(t)));
+            }
+        }
+    }
+    best
+}
+
+impl<U: BaseNum + Float> Obstacle<U> {
+    /// Mirror this obstacle across the plane through `plane_point` with normal `plane_normal`.
+    ///
+    /// A box stays axis-aligned, so it is only mirrored exactly across planes whose normal lies along an
+    /// axis, and otherwise becomes the smallest box around its reflection.
+    pub fn reflect(&self, plane_normal: Vector3<U>, plane_point: Vector3<U>) -> Self {
+        let point = |point| reflect_point(point, plane_normal, plane_point);
+        match *self {
+            Obstacle::Sphere { center, radius } => Obstacle::Sphere {
+                center: point(center),
+                radius,
+            },
+            Obstacle::Box { min, max } => {
+                let (min, max) = reflect_box(min, max, plane_normal, plane_point);
+                Obstacle::Box { min, max }
+            }
+            Obstacle::Capsule { start, end, radius } => Obstacle::Capsule {
+                start: point(start),
+                end: point(end),
+                radius,
+            },
+        }
+    }
+
+    /// Find where the segment from `from` to `to` first touches this obstacle.
+    ///
+    /// Returns the fraction of the way along the segment, from `0` to `1`, or `None` if the segment
+    /// misses. A segment starting inside the obstacle hits at `0`.
+    pub fn segment_hit(&self, from: Vector3<U>, to: Vector3<U>) -> Option<U> {
+        match *self {
+            Obstacle::Sphere { center, radius } => sphere_hit(from, to, center, radius),
+            Obstacle::Box { min, max } => box_hit(from, to, min, max),
+            Obstacle::Capsule { start, end, radius } => capsule_hit(from, to, start, end, radius),
+        }
+    }
+
+    /// Check if the segment from `from` to `to` touches this obstacle
+    pub fn blocks(&self, from: Vector3<U>, to: Vector3<U>) -> bool {
+        self.segment_hit(from, to).is_some()
+    }
+
+    /// Get a sphere enclosing this obstacle, as `(center, radius)`.
+    ///
+    /// For a capsule, this is only the part of the capsule around the point of its axis closest to
+    /// `near`, which keeps the sphere tight around long capsules.
+    pub fn bounding_sphere(&self, near: Vector3<U>) -> (Vector3<U>, U) {
+        let two = U::one() + U::one();
+        match *self {
+            Obstacle::Sphere { center, radius } => (center, radius),
+            Obstacle::Box { min, max } => ((min + max) / two, (max - min).magnitude() / two),
+            Obstacle::Capsule { start, end, radius } => {
+                let axis = end - start;
+                let length2 = axis.magnitude2();
+                let along = if length2 > U::zero() {
+                    ((near - start).dot(axis) / length2)
+                        .max(U::zero())
+                        .min(U::one())
+                } else {
+                    U::zero()
+                };
+                (start + axis * along, radius)
+            }
+        }
+    }
+}
+
+/// Get the direction to head in from `from` towards `to` without running into the obstacles.
+///
+/// If the straight line is clear, this points straight at `to`. Otherwise it points along the tangent
+/// to a sphere around the first obstacle in the way, on the side `to` lies towards. If `from` is
+/// inside that sphere there is no tangent, so this points straight at `to` too. Returns `None` if
+/// `from` and `to` are the same point.
+pub fn detour_direction<U: BaseNum + Float>(
+    obstacles: &[Obstacle<U>],
+    from: Vector3<U>,
+    to: Vector3<U>,
+) -> Option<Vector3<U>> {
+    let offset = to - from;
+    if offset.magnitude2() <= U::zero() {
+        return None;
+    }
+    let direct = offset.normalize();
+
+    // Find the first obstacle along the way
+    let blocking = obstacles
+        .iter()
+        .filter_map(|obstacle| {
+            obstacle
+                .segment_hit(from, to)
+                .map(|fraction| (obstacle, fraction))
+        })
+        .fold(
+            None,
+            |best: Option<(&Obstacle<U>, U)>, candidate| match best {
+                Some(best) if best.1 <= candidate.1 => Some(best),
+                _ => Some(candidate),
+            },
+        );
+    let (obstacle, fraction) = match blocking {
+        Some(blocking) => blocking,
+        None => return Some(direct),
+    };
+
+    let (center, radius) = obstacle.bounding_sphere(from + offset * fraction);
+    let to_center = center - from;
+    let distance = to_center.magnitude();
+    if distance <= radius {
+        return Some(direct);
+    }
+    let forward = to_center / distance;
+
+    // Go around on the side of the target, or any side if it is dead behind the obstacle
+    let lateral = offset - forward * offset.dot(forward);
+    let side = if lateral.magnitude2() > U::epsilon() * offset.magnitude2() {
+        lateral.normalize()
+    } else {
+        heading_frame(forward).1
+    };
+
+    // The tangent makes an angle of asin(radius / distance) with the line to the center
+    let sin = radius / distance;
+    let cos = (U::one() - sin * sin).sqrt();
+    Some(forward * cos + side * sin)
+}
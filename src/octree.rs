@@ -0,0 +1,277 @@
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, MetricSpace, Vector3};
+
+/// Maximum number of points stored in a leaf before it is split
+const LEAF_CAPACITY: usize = 8;
+
+/// Maximum depth of the tree, which stops co-located points from splitting forever
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone)]
+struct OctreeNode<U: BaseNum + Float> {
+    /// Minimum corner of the node's cube
+    min: Vector3<U>,
+    /// Edge length of the node's cube
+    size: U,
+    /// Number of points under this node
+    count: usize,
+    /// Sum of all point positions under this node
+    sum: Vector3<U>,
+    /// Indices of the eight child nodes, if this node has been split
+    children: Option<[usize; 8]>,
+    /// Points stored directly in this node, if it is a leaf
+    points: Vec<Vector3<U>>,
+}
+
+impl<U: BaseNum + Float> OctreeNode<U> {
+    fn new(min: Vector3<U>, size: U) -> Self {
+        Self {
+            min,
+            size,
+            count: 0,
+            sum: Vector3::new(U::zero(), U::zero(), U::zero()),
+            children: None,
+            points: Vec::new(),
+        }
+    }
+
+    /// Get the distance from a point to the closest point of this node's cube
+    fn distance_to(&self, point: Vector3<U>) -> U {
+        let axis = |value: U, min: U| {
+            if value < min {
+                min - value
+            } else if value > min + self.size {
+                value - (min + self.size)
+            } else {
+                U::zero()
+            }
+        };
+        Vector3::new(
+            axis(point.x, self.min.x),
+            axis(point.y, self.min.y),
+            axis(point.z, self.min.z),
+        )
+        .magnitude()
+    }
+
+    /// Find which child octant a point belongs in
+    fn octant(&self, point: Vector3<U>) -> usize {
+        let half = self.size / (U::one() + U::one());
+        let mut octant = 0;
+        if point.x >= self.min.x + half {
+            octant |= 1;
+        }
+        if point.y >= self.min.y + half {
+            octant |= 2;
+        }
+        if point.z >= self.min.z + half {
+            octant |= 4;
+        }
+        octant
+    }
+}
+
+/// An octree of boid positions used for the Barnes-Hut cohesion approximation.
+///
+/// Each node stores the number of points below it and the sum of their positions, so a distant node
+/// can stand in for all of its boids at once.
+#[derive(Debug, Clone)]
+pub struct Octree<U: BaseNum + Float> {
+    nodes: Vec<OctreeNode<U>>,
+}
+
+impl<U: BaseNum + Float> Octree<U> {
+    /// Build a tree containing every given position
+    pub fn new(positions: impl IntoIterator<Item = Vector3<U>>) -> Self {
+        let positions: Vec<Vector3<U>> = positions.into_iter().collect();
+
+        // Find a cube enclosing every point
+        let mut min = Vector3::new(U::infinity(), U::infinity(), U::infinity());
+        let mut max = Vector3::new(U::neg_infinity(), U::neg_infinity(), U::neg_infinity());
+        for position in positions.iter() {
+            min = Vector3::new(
+                min.x.min(position.x),
+                min.y.min(position.y),
+                min.z.min(position.z),
+            );
+            max = Vector3::new(
+                max.x.max(position.x),
+                max.y.max(position.y),
+                max.z.max(position.z),
+            );
+        }
+        let size = (max.x - min.x).max(max.y - min.y).max(max.z - min.z);
+        let root = if positions.is_empty() || !size.is_finite() {
+            OctreeNode::new(Vector3::new(U::zero(), U::zero(), U::zero()), U::one())
+        } else {
+            OctreeNode::new(min, size.max(U::epsilon()))
+        };
+
+        let mut tree = Self { nodes: vec![root] };
+        for position in positions {
+            tree.insert(position);
+        }
+        tree
+    }
+
+    fn insert(&mut self, point: Vector3<U>) {
+        let mut node = 0;
+        let mut depth = 0;
+        loop {
+            self.nodes[node].count += 1;
+            self.nodes[node].sum += point;
+
+            match self.nodes[node].children {
+                Some(children) => {
+                    node = children[self.nodes[node].octant(point)];
+                    depth += 1;
+                }
+                None => {
+                    self.nodes[node].points.push(point);
+                    if self.nodes[node].points.len() > LEAF_CAPACITY && depth < MAX_DEPTH {
+                        self.split(node);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    fn split(&mut self, node: usize) {
+        let half = self.nodes[node].size / (U::one() + U::one());
+        let min = self.nodes[node].min;
+
+        // Create the eight children
+        let mut children = [0; 8];
+        for (octant, child) in children.iter_mut().enumerate() {
+            let offset = Vector3::new(
+                if octant & 1 != 0 { half } else { U::zero() },
+                if octant & 2 != 0 { half } else { U::zero() },
+                if octant & 4 != 0 { half } else { U::zero() },
+            );
+            *child = self.nodes.len();
+            self.nodes.push(OctreeNode::new(min + offset, half));
+        }
+        self.nodes[node].children = Some(children);
+
+        // Push this node's points down into the children
+        let points = std::mem::take(&mut self.nodes[node].points);
+        for point in points {
+            let child = children[self.nodes[node].octant(point)];
+            self.nodes[child].count += 1;
+            self.nodes[child].sum += point;
+            self.nodes[child].points.push(point);
+        }
+    }
+
+    /// Sum the positions of every point within `radius` of `position`, returning the sum and the count.
+    ///
+    /// Points at exactly `position` are skipped, matching the exact cohesion behavior. A node whose edge
+    /// length divided by the distance to its center of mass is below `theta` is treated as a single
+    /// aggregate, so `theta = 0` gives an exact answer and larger values trade accuracy for speed.
+    pub fn sum_within(&self, position: Vector3<U>, radius: U, theta: U) -> (Vector3<U>, usize) {
+        let mut sum = Vector3::new(U::zero(), U::zero(), U::zero());
+        let mut count = 0;
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.count == 0 || node.distance_to(position) >= radius {
+                continue;
+            }
+
+            match node.children {
+                Some(children) => {
+                    // Approximate distant nodes that do not contain the boid itself
+                    let center = node.sum / U::from(node.count).unwrap();
+                    let distance = position.distance(center);
+                    if node.distance_to(position) > U::zero()
+                        && distance < radius
+                        && node.size < theta * distance
+                    {
+                        sum += node.sum;
+                        count += node.count;
+                    } else {
+                        stack.extend_from_slice(&children);
+                    }
+                }
+                None => {
+                    for point in node.points.iter() {
+                        let distance = position.distance(*point);
+                        if distance > U::zero() && distance < radius {
+                            sum += *point;
+                            count += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        (sum, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{MetricSpace, Vector3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Octree;
+
+    fn points(count: usize) -> Vec<Vector3<f64>> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count)
+            .map(|_| {
+                Vector3::new(
+                    rng.gen_range(-50.0..50.0),
+                    rng.gen_range(-50.0..50.0),
+                    rng.gen_range(-50.0..50.0),
+                )
+            })
+            .collect()
+    }
+
+    fn exact(
+        points: &[Vector3<f64>],
+        position: Vector3<f64>,
+        radius: f64,
+    ) -> (Vector3<f64>, usize) {
+        points
+            .iter()
+            .filter(|point| {
+                let distance = position.distance(**point);
+                distance > 0.0 && distance < radius
+            })
+            .fold((Vector3::new(0.0, 0.0, 0.0), 0), |(sum, count), point| {
+                (sum + *point, count + 1)
+            })
+    }
+
+    #[test]
+    fn zero_theta_is_exact() {
+        let points = points(2000);
+        let tree = Octree::new(points.iter().copied());
+        for position in points.iter().take(50) {
+            let (sum, count) = tree.sum_within(*position, 30.0, 0.0);
+            let (expected_sum, expected_count) = exact(&points, *position, 30.0);
+            assert_eq!(count, expected_count);
+            assert!(sum.distance(expected_sum) < 1e-6);
+        }
+    }
+
+    // The approximation only pays off once the cohesion radius covers many leaves, which is the regime of
+    // large flocks with long-range cohesion. A small `theta` keeps the centers of mass close to exact.
+    #[test]
+    fn small_theta_matches_exact_cohesion() {
+        let points = points(5000);
+        let tree = Octree::new(points.iter().copied());
+        let radius = 60.0;
+        for position in points.iter().take(50) {
+            let (sum, count) = tree.sum_within(*position, radius, 0.2);
+            let (expected_sum, expected_count) = exact(&points, *position, radius);
+            assert!(count > 0 && expected_count > 0);
+            let center = sum / count as f64;
+            let expected_center = expected_sum / expected_count as f64;
+            assert!(center.distance(expected_center) < 1.0);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::convert::{TryFrom, TryInto};
+
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+use crate::{
+    boids::{boid3d::Boid3D, Boid},
+    error::{check_length, scalar, BoidError},
+    flock::Flock,
+    precision::single_precision,
+};
+
+/// Bytes at the start of every packed flock
+const PACK_MAGIC: &[u8; 4] = b"BOID";
+
+/// Version of the packed layout written by `pack_into`
+const PACK_VERSION: u16 = 1;
+
+/// Size of the header: magic, version, scalar width, and boid count
+const PACK_HEADER: usize = 16;
+
+/// Number of scalars stored per boid
+const PACK_VALUES: usize = 6;
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Append the position and velocity of every boid to `buf` as a flat binary blob, for handing the
+    /// flock across an FFI boundary.
+    ///
+    /// The layout is little-endian throughout:
+    ///
+    /// | Offset | Size | Contents                                            |
+    /// |--------|------|-----------------------------------------------------|
+    /// | 0      | 4    | The magic bytes `BOID`                              |
+    /// | 4      | 2    | Layout version, currently `1`                       |
+    /// | 6      | 2    | Scalar width in bytes: `4` for `f32`, `8` for `f64` |
+    /// | 8      | 8    | Boid count as a `u64`                               |
+    /// | 16     | ...  | Per boid: `px, py, pz, vx, vy, vz` as scalars       |
+    ///
+    /// Scalars are written as `f32` if the flock's scalar type is no more precise than that, and as
+    /// `f64` otherwise.
+    pub fn pack_into(&self, buf: &mut Vec<u8>) {
+        let single = single_precision::<U>();
+        let width: u16 = if single { 4 } else { 8 };
+        buf.reserve(PACK_HEADER + self.boids.len() * PACK_VALUES * width as usize);
+
+        buf.extend_from_slice(PACK_MAGIC);
+        buf.extend_from_slice(&PACK_VERSION.to_le_bytes());
+        buf.extend_from_slice(&width.to_le_bytes());
+        buf.extend_from_slice(&(self.boids.len() as u64).to_le_bytes());
+        for boid in self.boids.iter() {
+            let (position, velocity) = (boid.position(), boid.velocity());
+            for value in [
+                position.x, position.y, position.z, velocity.x, velocity.y, velocity.z,
+            ] {
+                if single {
+                    buf.extend_from_slice(&value.to_f32().unwrap_or(f32::NAN).to_le_bytes());
+                } else {
+                    buf.extend_from_slice(&value.to_f64().unwrap_or(f64::NAN).to_le_bytes());
+                }
+            }
+        }
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Read a flock back from a blob written by `pack_into`.
+    ///
+    /// Boids are created in order with default parameters, and the stored position and velocity.
+    /// Every other flock setting is left at its default. Blobs of either scalar width can be read into
+    /// a flock of any scalar type.
+    ///
+    /// Fails with `LengthMismatch` if `buf` is not exactly as long as its header says, and with
+    /// `Deserialize` if the header is not one this version understands.
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, BoidError> {
+        if buf.len() < PACK_HEADER {
+            return Err(BoidError::LengthMismatch {
+                expected: PACK_HEADER,
+                found: buf.len(),
+            });
+        }
+        if &buf[0..4] != PACK_MAGIC {
+            return Err(BoidError::Deserialize {
+                reason: "not a packed flock".to_string(),
+            });
+        }
+        let version = u16::from_le_bytes(buf[4..6].try_into().unwrap());
+        if version != PACK_VERSION {
+            return Err(BoidError::Deserialize {
+                reason: format!("unsupported packed flock version {}", version),
+            });
+        }
+        let width = u16::from_le_bytes(buf[6..8].try_into().unwrap()) as usize;
+        if width != 4 && width != 8 {
+            return Err(BoidError::Deserialize {
+                reason: format!("unsupported scalar width {}", width),
+            });
+        }
+        let count = u64::from_le_bytes(buf[8..16].try_into().unwrap());
+
+        // A count too large to address can never match the buffer
+        let expected = usize::try_from(count)
+            .ok()
+            .and_then(|count| count.checked_mul(PACK_VALUES * width))
+            .and_then(|body| body.checked_add(PACK_HEADER))
+            .unwrap_or(usize::MAX);
+        check_length(expected, buf.len())?;
+
+        let mut flock = Flock::try_default()?;
+        for record in buf[PACK_HEADER..].chunks_exact(PACK_VALUES * width) {
+            let mut values = [U::zero(); PACK_VALUES];
+            for (value, bytes) in values.iter_mut().zip(record.chunks_exact(width)) {
+                let raw = if width == 4 {
+                    f64::from(f32::from_le_bytes(bytes.try_into().unwrap()))
+                } else {
+                    f64::from_le_bytes(bytes.try_into().unwrap())
+                };
+                *value = scalar(raw)?;
+            }
+
+            let mut boid = Boid3D::try_new_with_angle(
+                Vector3::new(values[0], values[1], values[2]),
+                U::zero(),
+            )?;
+            boid.velocity = Vector3::new(values[3], values[4], values[5]);
+            boid.last_velocity = boid.velocity;
+            flock.boids.push(boid);
+        }
+        flock.refresh_grid();
+        Ok(flock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::{boids::boid3d::Boid3D, error::BoidError, flock::Flock};
+
+    fn flock() -> Flock<Boid3D<f64>, f64> {
+        let mut flock = Flock {
+            boids: (0..3)
+                .map(|i| Boid3D::new_with_angle(Vector3::new(i as f64 * 1.5, -0.7, 2.0), 0.4))
+                .collect(),
+            ..Flock::default()
+        };
+        flock.update();
+        flock
+    }
+
+    #[test]
+    fn round_trips_positions_and_velocities() {
+        let flock = flock();
+        let mut buf = vec![0xff];
+        flock.pack_into(&mut buf);
+        assert_eq!(buf.len(), 1 + 16 + 3 * 6 * 8);
+
+        let unpacked: Flock<Boid3D<f64>, f64> = Flock::unpack_from(&buf[1..]).unwrap();
+        assert_eq!(unpacked.boids.len(), 3);
+        for (unpacked, boid) in unpacked.boids.iter().zip(flock.boids.iter()) {
+            assert_eq!(unpacked.position, boid.position);
+            assert_eq!(unpacked.velocity, boid.velocity);
+        }
+    }
+
+    #[test]
+    fn truncated_buffers_are_length_mismatches() {
+        let mut buf = Vec::new();
+        flock().pack_into(&mut buf);
+
+        assert_eq!(
+            Flock::<Boid3D<f64>, f64>::unpack_from(&buf[..buf.len() - 1]).unwrap_err(),
+            BoidError::LengthMismatch {
+                expected: buf.len(),
+                found: buf.len() - 1,
+            }
+        );
+        assert_eq!(
+            Flock::<Boid3D<f64>, f64>::unpack_from(&buf[..10]).unwrap_err(),
+            BoidError::LengthMismatch {
+                expected: 16,
+                found: 10,
+            }
+        );
+    }
+}
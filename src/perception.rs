@@ -0,0 +1,110 @@
+use cgmath::{num_traits::Float, BaseNum};
+
+use crate::error::{scalar, BoidError};
+
+/// The radii within which boids react to their neighbors
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PerceptionConfig<U: BaseNum + Float> {
+    separation: U,
+    alignment: U,
+    cohesion: U,
+}
+
+impl<U: BaseNum + Float> PerceptionConfig<U> {
+    /// Create a new perception config, naming the first radius that is not positive and finite if any
+    pub fn new(separation: U, alignment: U, cohesion: U) -> Result<Self, BoidError> {
+        let check = |radius: U, name: &'static str| {
+            if radius > U::zero() && radius.is_finite() {
+                Ok(radius)
+            } else {
+                Err(BoidError::InvalidParameter { name })
+            }
+        };
+        Ok(Self {
+            separation: check(separation, "separation")?,
+            alignment: check(alignment, "alignment")?,
+            cohesion: check(cohesion, "cohesion")?,
+        })
+    }
+
+    /// Get the default perception config, failing if the scalar type cannot represent it
+    pub fn try_default() -> Result<Self, BoidError> {
+        Ok(Self {
+            separation: scalar(25.0)?,
+            alignment: scalar(50.0)?,
+            cohesion: scalar(50.0)?,
+        })
+    }
+
+    /// Get the radius within which boids steer apart
+    pub fn separation(&self) -> U {
+        self.separation
+    }
+
+    /// Get the radius within which boids match headings
+    pub fn alignment(&self) -> U {
+        self.alignment
+    }
+
+    /// Get the radius within which boids steer together
+    pub fn cohesion(&self) -> U {
+        self.cohesion
+    }
+
+    /// Get a copy with every radius multiplied by `factor`, or an unchanged copy if that would leave any
+    /// radius not positive and finite
+    pub fn scaled(&self, factor: U) -> Self {
+        Self::new(
+            self.separation * factor,
+            self.alignment * factor,
+            self.cohesion * factor,
+        )
+        .unwrap_or(*self)
+    }
+}
+
+impl<U: BaseNum + Float> Default for PerceptionConfig<U> {
+    fn default() -> Self {
+        Self::try_default().expect("scalar type cannot represent the default perception radii")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PerceptionConfig;
+    use crate::error::BoidError;
+
+    #[test]
+    fn rejects_radii_that_are_not_positive_and_finite() {
+        let invalid = |name| Err(BoidError::InvalidParameter { name });
+        assert_eq!(
+            PerceptionConfig::new(0.0, 50.0, 50.0),
+            invalid("separation")
+        );
+        assert_eq!(
+            PerceptionConfig::new(25.0, -1.0, 50.0),
+            invalid("alignment")
+        );
+        assert_eq!(
+            PerceptionConfig::new(25.0, 50.0, f64::INFINITY),
+            invalid("cohesion")
+        );
+        assert_eq!(
+            PerceptionConfig::new(f64::NAN, 50.0, 50.0),
+            invalid("separation")
+        );
+    }
+
+    #[test]
+    fn accessors_return_the_configured_radii() {
+        let perception = PerceptionConfig::new(10.0, 20.0, 30.0).unwrap();
+        assert_eq!(perception.separation(), 10.0);
+        assert_eq!(perception.alignment(), 20.0);
+        assert_eq!(perception.cohesion(), 30.0);
+
+        let doubled = perception.scaled(2.0);
+        assert_eq!(doubled.cohesion(), 60.0);
+        assert_eq!(perception.scaled(0.0), perception);
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::VecDeque;
+
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+use crate::{
+    boids::{boid3d::Boid3D, Boid},
+    bounds::Bounds,
+    constraint::Constraint,
+    flock::{
+        DensitySpeed, Flock, ObstacleAvoidance, PerceptionEllipsoid, SeparationAnisotropy,
+        SeparationPriority, VFormation,
+    },
+    flow::FlowField,
+    grid::SpatialGrid,
+    obstacle::Obstacle,
+    perception::PerceptionConfig,
+    schedule::WeightSchedule,
+    startle::Startle,
+    sweep::SweepAndPrune,
+};
+
+/// Check if the scalar type is no more precise than `f32`, so values can be stored as `f32` without
+/// losing anything
+pub(crate) fn single_precision<U: Float>() -> bool {
+    U::epsilon().to_f64().unwrap_or(0.0) >= f64::from(f32::EPSILON)
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Build a flock of `boids` with every other setting copied from this one, passing each scalar
+    /// through `cast`.
+    ///
+    /// This is the one place every field is carried across, so boid type and precision conversions
+    /// stay in step. Cached state and history are dropped, and the spatial indexes (if any) are rebuilt
+    /// from the new boids. The `on_event` callback carries over, since it works on any flock, but the
+    /// `pre_step` and `post_step` hooks take the old flock type and are dropped.
+    pub(crate) fn convert<V: Boid<V, W>, W: BaseNum + Float>(
+        &self,
+        boids: Vec<V>,
+        cast: impl Fn(U) -> W,
+    ) -> Flock<V, W> {
+        let vector = |vector: Vector3<U>| vector.map(&cast);
+
+        // Radii beyond the range of the new type saturate instead of becoming infinite, so they stay valid
+        let radius = |radius: U| {
+            cast(radius)
+                .min(W::max_value())
+                .max(W::min_positive_value())
+        };
+        let perception = PerceptionConfig::new(
+            radius(self.perception.separation()),
+            radius(self.perception.alignment()),
+            radius(self.perception.cohesion()),
+        )
+        .expect("saturated radii are positive and finite");
+
+        let weight_schedule = self.weight_schedule.as_ref().and_then(|schedule| {
+            let keyframes = schedule
+                .keyframes()
+                .iter()
+                .map(|(time, keyframe)| (cast(*time), keyframe.map(&cast)))
+                .collect();
+            WeightSchedule::new(keyframes).map(|mut converted| {
+                converted.time = cast(schedule.time);
+                converted
+            })
+        });
+
+        let grid = self.grid.as_ref().map(|grid| {
+            SpatialGrid::with_slack(
+                boids.iter().map(|boid| boid.position()),
+                cast(grid.cell_size()),
+                cast(grid.slack()),
+            )
+        });
+        let sweep = self
+            .sweep
+            .as_ref()
+            .map(|_| SweepAndPrune::new(boids.iter().map(|boid| boid.position())));
+
+        Flock {
+            boids,
+            perception,
+            perception_speed_factor: cast(self.perception_speed_factor),
+            blind_angle: cast(self.blind_angle),
+            perception_ellipsoid: self
+                .perception_ellipsoid
+                .map(|ellipsoid| PerceptionEllipsoid {
+                    forward: cast(ellipsoid.forward),
+                    lateral: cast(ellipsoid.lateral),
+                }),
+            target: self.target.map(vector),
+            group_targets: self
+                .group_targets
+                .iter()
+                .map(|(group, target)| (*group, vector(*target)))
+                .collect(),
+            targets: self
+                .targets
+                .iter()
+                .map(|(point, weight)| (vector(*point), cast(*weight)))
+                .collect(),
+            target_policy: self.target_policy,
+            obstacles: self
+                .obstacles
+                .iter()
+                .map(|obstacle| match *obstacle {
+                    Obstacle::Sphere { center, radius } => Obstacle::Sphere {
+                        center: vector(center),
+                        radius: cast(radius),
+                    },
+                    Obstacle::Box { min, max } => Obstacle::Box {
+                        min: vector(min),
+                        max: vector(max),
+                    },
+                    Obstacle::Capsule { start, end, radius } => Obstacle::Capsule {
+                        start: vector(start),
+                        end: vector(end),
+                        radius: cast(radius),
+                    },
+                })
+                .collect(),
+            targeting_avoids_obstacles: self.targeting_avoids_obstacles,
+            obstacle_avoidance: self.obstacle_avoidance.map(|avoidance| ObstacleAvoidance {
+                sensor_count: avoidance.sensor_count,
+                spread: cast(avoidance.spread),
+                range: cast(avoidance.range),
+                weight: cast(avoidance.weight),
+            }),
+            alignment_acceleration_blend: self.alignment_acceleration_blend.map(&cast),
+            neighbor_smoothing: self.neighbor_smoothing.map(&cast),
+            constraints: self
+                .constraints
+                .iter()
+                .map(|constraint| Constraint {
+                    follower: constraint.follower,
+                    parent: constraint.parent,
+                    offset: vector(constraint.offset),
+                    stiffness: cast(constraint.stiffness),
+                })
+                .collect(),
+            separation_anisotropy: self.separation_anisotropy.map(|anisotropy| {
+                SeparationAnisotropy {
+                    front: cast(anisotropy.front),
+                    side: cast(anisotropy.side),
+                    back: cast(anisotropy.back),
+                }
+            }),
+            separation_min_neighbors: self.separation_min_neighbors,
+            separate_only_approaching: self.separate_only_approaching,
+            separation_priority: match &self.separation_priority {
+                SeparationPriority::Uniform => SeparationPriority::Uniform,
+                SeparationPriority::BySize => SeparationPriority::BySize,
+                SeparationPriority::ByGroup(priorities) => SeparationPriority::ByGroup(
+                    priorities
+                        .iter()
+                        .map(|(group, priority)| (*group, cast(*priority)))
+                        .collect(),
+                ),
+            },
+            startle: self.startle.map(|startle| Startle {
+                source: vector(startle.source),
+                duration: cast(startle.duration),
+                elapsed: cast(startle.elapsed),
+                separation_boost: cast(startle.separation_boost),
+                flee: cast(startle.flee),
+                spread: cast(startle.spread),
+            }),
+            drag: cast(self.drag),
+            behavior_clamp: self.behavior_clamp.map(&cast),
+            max_acceleration: cast(self.max_acceleration),
+            density_speed: self.density_speed.map(|curve| DensitySpeed {
+                half_density: cast(curve.half_density),
+                min_fraction: cast(curve.min_fraction),
+            }),
+            v_formation: self.v_formation.map(|formation| VFormation {
+                slot_angle: cast(formation.slot_angle),
+                spacing: cast(formation.spacing),
+                weight: cast(formation.weight),
+            }),
+            weight_schedule,
+            barnes_hut_theta: self.barnes_hut_theta.map(&cast),
+            octree: None,
+            packed_positions: None,
+            global_centroid: None,
+            grid,
+            sweep,
+            grid_min_boids: self.grid_min_boids,
+            pending_removals: self.pending_removals.clone(),
+            seed: self.seed,
+            step: self.step,
+            position_snap: self.position_snap,
+            update_mode: self.update_mode,
+            integration: self.integration,
+            cohesion_target: self.cohesion_target,
+            cohesion_normalization: self.cohesion_normalization,
+            cohesion_dead_zone: cast(self.cohesion_dead_zone),
+            behaviors: self.behaviors,
+            flow_fields: self
+                .flow_fields
+                .iter()
+                .map(|field| match *field {
+                    FlowField::Uniform { force } => FlowField::Uniform {
+                        force: vector(force),
+                    },
+                    FlowField::Vortex {
+                        center,
+                        axis,
+                        strength,
+                    } => FlowField::Vortex {
+                        center: vector(center),
+                        axis: vector(axis),
+                        strength: cast(strength),
+                    },
+                })
+                .collect(),
+            energy_conserving: self.energy_conserving,
+            brake_when_isolated: self.brake_when_isolated,
+            anchor: self.anchor.map(vector),
+            anchor_radius: cast(self.anchor_radius),
+            metrics: self.metrics.as_ref().map(|metrics| metrics.map(&cast)),
+            history: VecDeque::new(),
+            history_capacity: self.history_capacity,
+            event_bounds: self
+                .event_bounds
+                .map(|bounds| Bounds::new(vector(bounds.min), vector(bounds.max))),
+            waypoint_radius: self.waypoint_radius.map(&cast),
+            on_event: self.on_event.clone(),
+            pre_step: None,
+            post_step: None,
+        }
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Rebuild the flock with every scalar, in the boids and the settings, passed through `cast`
+    fn cast<V: BaseNum + Float>(&self, cast: impl Fn(U) -> V) -> Flock<Boid3D<V>, V> {
+        let vector = |vector: Vector3<U>| vector.map(&cast);
+        let boids = self
+            .boids
+            .iter()
+            .map(|boid| Boid3D {
+                position: vector(boid.position),
+                velocity: vector(boid.velocity),
+                acceleration: vector(boid.acceleration),
+                last_velocity: vector(boid.last_velocity),
+                max_speed: cast(boid.max_speed),
+                min_speed: cast(boid.min_speed),
+                max_force: cast(boid.max_force),
+                r: cast(boid.r),
+                weights: boid.weights.map(&cast),
+                group: boid.group,
+                panic: cast(boid.panic),
+                panic_decay: cast(boid.panic_decay),
+                radius: cast(boid.radius),
+                smoothed_heading: boid.smoothed_heading.map(vector),
+                smoothed_center: boid.smoothed_center.map(vector),
+                target: boid.target.map(vector),
+            })
+            .collect();
+        self.convert(boids, cast)
+    }
+}
+
+impl Flock<Boid3D<f64>, f64> {
+    /// Convert the flock to single precision.
+    ///
+    /// Every value is rounded to the nearest `f32`, so positions lose precision far from the origin
+    /// (an `f32` only has about 7 significant digits), and values beyond the `f32` range become
+    /// infinite.
+    ///
+    /// Every setting carries over, including the `on_event` callback and recorded metrics. The
+    /// `pre_step` and `post_step` hooks are dropped, since they only accept the old flock type.
+    pub fn to_f32(&self) -> Flock<Boid3D<f32>, f32> {
+        self.cast(|value| value as f32)
+    }
+}
+
+impl Flock<Boid3D<f32>, f32> {
+    /// Convert the flock to double precision. Every `f32` value is represented exactly.
+    ///
+    /// As with `to_f32`, the `pre_step` and `post_step` hooks are dropped.
+    pub fn to_f64(&self) -> Flock<Boid3D<f64>, f64> {
+        self.cast(f64::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use crate::{
+        boids::boid3d::Boid3D,
+        events::{EventCallback, StepHook},
+        flock::Flock,
+        obstacle::Obstacle,
+        perception::PerceptionConfig,
+        statistics::MetricsCollector,
+    };
+
+    #[test]
+    fn round_trips_within_single_precision() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            boids: (0..30)
+                .map(|i| {
+                    let i = i as f64;
+                    Boid3D::new_with_angle(
+                        Vector3::new(i * 3.7, (i * 0.3).sin() * 20.0, i / 7.0),
+                        i,
+                    )
+                })
+                .collect(),
+            target: Some(Vector3::new(100.0 / 3.0, -2.5, 0.1)),
+            obstacles: vec![Obstacle::Sphere {
+                center: Vector3::new(50.0, 0.0, 0.0),
+                radius: 4.2,
+            }],
+            drag: 0.01,
+            metrics: Some(MetricsCollector::new()),
+            on_event: Some(EventCallback::new(|_| {})),
+            pre_step: Some(StepHook::new(|_| {})),
+            ..Flock::default()
+        };
+        flock.rebuild_grid(12.5);
+        for _ in 0..3 {
+            flock.update();
+        }
+
+        let single = flock.to_f32();
+        assert!(single.on_event.is_some() && single.pre_step.is_none());
+        assert_eq!(single.metrics.as_ref().unwrap().len(), 3);
+        assert_eq!(single.grid.as_ref().unwrap().cell_size(), 12.5);
+
+        let back = single.to_f64();
+        let close =
+            |a: Vector3<f64>, b: Vector3<f64>| (a - b).magnitude() <= 1e-6 * a.magnitude().max(1.0);
+        for (original, converted) in flock.boids.iter().zip(back.boids.iter()) {
+            assert!(close(original.position, converted.position));
+            assert!(close(original.velocity, converted.velocity));
+            assert!((original.max_speed - converted.max_speed).abs() < 1e-6);
+            assert!((original.weights.targeting - converted.weights.targeting).abs() < 1e-9);
+        }
+        assert!(close(flock.target.unwrap(), back.target.unwrap()));
+        assert!((flock.drag - back.drag).abs() < 1e-9);
+        assert_eq!(back.step, flock.step);
+        let (original, converted) = (flock.metrics.unwrap(), back.metrics.unwrap());
+        assert_eq!(original.steps(), converted.steps());
+        for (a, b) in original.centroids().iter().zip(converted.centroids()) {
+            assert!(close(*a, *b));
+        }
+    }
+
+    #[test]
+    fn out_of_range_radii_saturate() {
+        let flock: Flock<Boid3D<f64>, f64> = Flock {
+            perception: PerceptionConfig::new(1e-300, 50.0, 1e300).unwrap(),
+            ..Flock::default()
+        };
+        let perception = flock.to_f32().perception;
+        assert_eq!(perception.separation(), f32::MIN_POSITIVE);
+        assert_eq!(perception.alignment(), 50.0);
+        assert_eq!(perception.cohesion(), f32::MAX);
+    }
+}
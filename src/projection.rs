@@ -0,0 +1,139 @@
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+use crate::{
+    boids::{boid2d::Boid2D, boid3d::Boid3D, Boid},
+    flock::Flock,
+};
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Build a flock of different boids with a copy of every other setting from this one.
+    ///
+    /// This follows `convert`: cached state, history, and the step hooks are dropped, and the spatial
+    /// indexes (if any) are rebuilt.
+    pub(crate) fn with_boids<V: Boid<V, U>>(&self, boids: Vec<V>) -> Flock<V, U> {
+        self.convert(boids, |value| value)
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid2D<U>, U> {
+    /// Lift the flock into 3D, placing every boid at height `z` with no vertical velocity.
+    ///
+    /// Shared boid parameters carry over, and 3D-only ones such as the panic decay take their defaults.
+    /// Every flock setting carries over too, including the `on_event` callback, except the `pre_step`
+    /// and `post_step` hooks, which only accept the old flock type.
+    pub fn to_3d(&self, z: U) -> Flock<Boid3D<U>, U> {
+        let boids = self
+            .boids
+            .iter()
+            .map(|boid| {
+                let velocity = boid.velocity.extend(U::zero());
+                Boid3D {
+                    position: boid.position.extend(z),
+                    velocity,
+                    acceleration: boid.acceleration.extend(U::zero()),
+                    last_velocity: velocity,
+                    max_speed: boid.max_speed,
+                    min_speed: boid.min_speed,
+                    max_force: boid.max_force,
+                    weights: boid.weights,
+                    group: boid.group,
+                    radius: boid.radius,
+                    ..Boid3D::new_with_angle(
+                        Vector3::new(U::zero(), U::zero(), U::zero()),
+                        U::zero(),
+                    )
+                }
+            })
+            .collect();
+        self.with_boids(boids)
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Flatten the flock into 2D by dropping the Z component of every boid.
+    ///
+    /// Shared boid parameters carry over, and 3D-only ones such as panic levels are dropped. As with
+    /// `to_3d`, the `pre_step` and `post_step` hooks are dropped.
+    pub fn to_2d(&self) -> Flock<Boid2D<U>, U> {
+        let boids = self
+            .boids
+            .iter()
+            .map(|boid| Boid2D {
+                position: boid.position.truncate(),
+                velocity: boid.velocity.truncate(),
+                acceleration: boid.acceleration.truncate(),
+                max_speed: boid.max_speed,
+                min_speed: boid.min_speed,
+                max_force: boid.max_force,
+                weights: boid.weights,
+                group: boid.group,
+                radius: boid.radius,
+            })
+            .collect();
+        self.with_boids(boids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector2, Vector3};
+
+    use crate::{
+        boids::{boid2d::Boid2D, boid3d::Boid3D},
+        flock::Flock,
+    };
+
+    #[test]
+    fn projections_round_trip_the_plane() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            boids: (0..10)
+                .map(|i| {
+                    let i = i as f64;
+                    Boid3D::new_with_velocity(
+                        Vector3::new(i * 2.0, -i, 7.5),
+                        Vector3::new(0.5, 0.25 * i.sin(), 0.0),
+                    )
+                })
+                .collect(),
+            drag: 0.05,
+            ..Flock::default()
+        };
+        flock.boids[3].max_speed = 4.0;
+        flock.rebuild_grid(6.0);
+
+        let flat = flock.to_2d();
+        assert_eq!(flat.drag, flock.drag);
+        assert!(flat.grid.is_some());
+        for (boid, projected) in flock.boids.iter().zip(flat.boids.iter()) {
+            assert_eq!(
+                projected.position,
+                Vector2::new(boid.position.x, boid.position.y)
+            );
+            assert_eq!(
+                projected.velocity,
+                Vector2::new(boid.velocity.x, boid.velocity.y)
+            );
+            assert_eq!(projected.max_speed, boid.max_speed);
+        }
+
+        let lifted = flat.to_3d(7.5);
+        for (boid, restored) in flock.boids.iter().zip(lifted.boids.iter()) {
+            assert_eq!(restored.position, boid.position);
+            assert_eq!(restored.velocity, boid.velocity);
+            assert_eq!(restored.max_speed, boid.max_speed);
+            assert_eq!(restored.group, boid.group);
+        }
+    }
+
+    #[test]
+    fn lifting_places_boids_at_the_given_height() {
+        let flock: Flock<Boid2D<f64>, f64> = Flock {
+            boids: vec![Boid2D::new_with_angle(Vector2::new(1.0, 2.0), 0.5)],
+            ..Flock::default()
+        };
+        let lifted = flock.to_3d(-3.0);
+        assert_eq!(lifted.boids[0].position, Vector3::new(1.0, 2.0, -3.0));
+        assert_eq!(lifted.boids[0].velocity.z, 0.0);
+        assert_eq!(lifted.boids[0].acceleration.z, 0.0);
+    }
+}
@@ -0,0 +1,265 @@
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, Vector3};
+
+use crate::{boids::Boid, flock::Flock, grid::SpatialGrid, obstacle::sphere_hit};
+
+/// Find where a ray first enters a sphere, as a distance along the ray (up to `max_dist`)
+fn ray_sphere<U: BaseNum + Float>(
+    origin: Vector3<U>,
+    dir: Vector3<U>,
+    max_dist: U,
+    center: Vector3<U>,
+    radius: U,
+) -> Option<U> {
+    sphere_hit(origin, origin + dir * max_dist, center, radius).map(|fraction| fraction * max_dist)
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Find the first boid hit by a ray, treating each boid as a sphere of its `radius`.
+    ///
+    /// Returns the boid's index and the distance along the ray to the hit, or `None` if nothing is hit
+    /// within `max_dist`. A ray starting inside a boid hits it at distance `0`. When the flock uses its
+    /// spatial grid, only the cells along the ray are checked.
+    pub fn raycast(&self, origin: Vector3<U>, dir: Vector3<U>, max_dist: U) -> Option<(usize, U)> {
+        if dir.magnitude2() <= U::zero() || max_dist.is_nan() || max_dist < U::zero() {
+            return None;
+        }
+        let dir = dir.normalize();
+
+        // Only walk the part of the ray inside the flock, so an unbounded ray still ends
+        let max_radius = self
+            .boids
+            .iter()
+            .fold(U::zero(), |max, boid| max.max(boid.radius()));
+        let bounds = self.bounding_box()?;
+        let margin = Vector3::new(max_radius, max_radius, max_radius);
+        let (enter, exit) = ray_span(origin, dir, bounds.min - margin, bounds.max + margin)?;
+        let max_dist = max_dist.min(exit);
+        if enter > max_dist {
+            return None;
+        }
+
+        let hit = |index: usize| {
+            let boid = &self.boids[index];
+            ray_sphere(origin, dir, max_dist, boid.position(), boid.radius())
+                .map(|distance| (index, distance))
+        };
+        let mut best: Option<(usize, U)> = None;
+        let mut keep_closest = |candidate: Option<(usize, U)>| {
+            // Ties go to the lowest index, so the answer does not depend on the visiting order
+            if let Some((index, distance)) = candidate {
+                if best.is_none_or(|(best_index, best_distance)| {
+                    distance < best_distance || (distance == best_distance && index < best_index)
+                }) {
+                    best = Some((index, distance));
+                }
+            }
+            best
+        };
+
+        match self.grid.as_ref().filter(|_| self.uses_grid()) {
+            Some(grid) => {
+                walk_cells(grid, origin, dir, enter, max_dist, |min, max, enter| {
+                    let mut closest = keep_closest(None);
+                    if closest.is_some_and(|(_, distance)| distance < enter) {
+                        return false;
+                    }
+                    grid.for_each_candidate_in_box(min - margin, max + margin, |index| {
+                        closest = keep_closest(hit(index));
+                    });
+                    true
+                });
+            }
+            None => (0..self.boids.len()).for_each(|index| {
+                keep_closest(hit(index));
+            }),
+        }
+        keep_closest(None)
+    }
+}
+
+/// Get the distances at which a ray enters and leaves a box, or `None` if the ray misses the box.
+///
+/// The entry distance is zero if the ray starts inside the box.
+fn ray_span<U: BaseNum + Float>(
+    origin: Vector3<U>,
+    dir: Vector3<U>,
+    min: Vector3<U>,
+    max: Vector3<U>,
+) -> Option<(U, U)> {
+    let mut enter = U::zero();
+    let mut exit = U::infinity();
+    for axis in 0..3 {
+        if dir[axis].abs() <= U::epsilon() {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let (mut near, mut far) = (
+            (min[axis] - origin[axis]) / dir[axis],
+            (max[axis] - origin[axis]) / dir[axis],
+        );
+        if near > far {
+            std::mem::swap(&mut near, &mut far);
+        }
+        enter = enter.max(near);
+        exit = exit.min(far);
+        if enter > exit {
+            return None;
+        }
+    }
+    Some((enter, exit))
+}
+
+/// Visit the grid cells a ray passes through between `min_dist` and `max_dist`, in order, until `visit`
+/// returns `false`.
+///
+/// `visit` is given each cell's corners and the distance at which the ray enters it.
+fn walk_cells<U: BaseNum + Float>(
+    grid: &SpatialGrid<U>,
+    origin: Vector3<U>,
+    dir: Vector3<U>,
+    min_dist: U,
+    max_dist: U,
+    mut visit: impl FnMut(Vector3<U>, Vector3<U>, U) -> bool,
+) {
+    let size = grid.cell_size();
+    let start = grid.key(origin + dir * min_dist);
+    let mut cell = [start.0, start.1, start.2];
+
+    // Amanatides & Woo: track the distance to the next cell boundary along each axis
+    let mut step = [0i64; 3];
+    let mut next = [U::infinity(); 3];
+    let mut delta = [U::infinity(); 3];
+    for axis in 0..3 {
+        if dir[axis] > U::zero() {
+            step[axis] = 1;
+            let boundary = U::from(cell[axis] + 1).unwrap() * size;
+            next[axis] = (boundary - origin[axis]) / dir[axis];
+            delta[axis] = size / dir[axis];
+        } else if dir[axis] < U::zero() {
+            step[axis] = -1;
+            let boundary = U::from(cell[axis]).unwrap() * size;
+            next[axis] = (boundary - origin[axis]) / dir[axis];
+            delta[axis] = -size / dir[axis];
+        }
+    }
+
+    let mut enter = min_dist;
+    while enter <= max_dist {
+        let min = Vector3::new(
+            U::from(cell[0]).unwrap() * size,
+            U::from(cell[1]).unwrap() * size,
+            U::from(cell[2]).unwrap() * size,
+        );
+        let max = min + Vector3::new(size, size, size);
+        if !visit(min, max, enter) {
+            return;
+        }
+
+        // Step into whichever neighboring cell the ray reaches first
+        let axis = (0..3)
+            .min_by(|a, b| next[*a].partial_cmp(&next[*b]).unwrap())
+            .unwrap();
+        if !next[axis].is_finite() {
+            return;
+        }
+        enter = next[axis];
+        cell[axis] += step[axis];
+        next[axis] += delta[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    /// Two boids of radius 1 along the X axis, optionally searched through the spatial grid
+    fn pair(gridded: bool) -> Flock<Boid3D<f64>, f64> {
+        let mut flock = Flock {
+            boids: [10.0, 20.0]
+                .iter()
+                .map(|x| Boid3D {
+                    radius: 1.0,
+                    ..Boid3D::new_with_angle(Vector3::new(*x, 0.0, 0.0), 0.0)
+                })
+                .collect(),
+            grid_min_boids: 0,
+            ..Flock::default()
+        };
+        if gridded {
+            flock.rebuild_grid(3.0);
+        }
+        flock
+    }
+
+    #[test]
+    fn rays_hit_the_nearest_boid() {
+        for gridded in [false, true] {
+            let flock = pair(gridded);
+            assert_eq!(flock.uses_grid(), gridded);
+
+            // Straight down the line, the nearer boid is hit on its surface
+            let (index, distance) = flock
+                .raycast(
+                    Vector3::new(0.0, 0.0, 0.0),
+                    Vector3::new(2.0, 0.0, 0.0),
+                    100.0,
+                )
+                .unwrap();
+            assert_eq!(index, 0);
+            assert!((distance - 9.0).abs() < 1e-9);
+
+            // Coming from the other side, the other boid is nearer
+            let (index, distance) = flock
+                .raycast(
+                    Vector3::new(30.0, 0.0, 0.0),
+                    Vector3::new(-1.0, 0.0, 0.0),
+                    100.0,
+                )
+                .unwrap();
+            assert_eq!(index, 1);
+            assert!((distance - 9.0).abs() < 1e-9);
+
+            // Straight at the far boid, passing beside the near one
+            let (index, _) = flock
+                .raycast(
+                    Vector3::new(20.0, 10.0, 0.0),
+                    Vector3::new(0.0, -1.0, 0.0),
+                    100.0,
+                )
+                .unwrap();
+            assert_eq!(index, 1);
+        }
+    }
+
+    #[test]
+    fn rays_can_miss() {
+        for gridded in [false, true] {
+            let flock = pair(gridded);
+            let origin = Vector3::new(0.0, 0.0, 0.0);
+
+            // Passing above both boids
+            assert_eq!(
+                flock.raycast(
+                    Vector3::new(0.0, 1.5, 0.0),
+                    Vector3::new(1.0, 0.0, 0.0),
+                    100.0
+                ),
+                None
+            );
+            // Pointing away
+            assert_eq!(
+                flock.raycast(origin, Vector3::new(-1.0, 0.0, 0.0), 100.0),
+                None
+            );
+            // Falling short
+            assert_eq!(
+                flock.raycast(origin, Vector3::new(1.0, 0.0, 0.0), 8.5),
+                None
+            );
+        }
+    }
+}
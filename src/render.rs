@@ -0,0 +1,169 @@
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+#[cfg(feature = "ascii")]
+use crate::bounds::Bounds;
+use crate::{boids::Boid, flock::Flock};
+
+/// Something that can draw a flock, one boid at a time
+pub trait Renderer<U: BaseNum + Float> {
+    /// Draw a single boid
+    fn draw_boid(&mut self, position: Vector3<U>, velocity: Vector3<U>);
+
+    /// Finish the frame once every boid has been drawn
+    fn present(&mut self);
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Draw every boid to `renderer` in index order, then present the frame
+    pub fn render(&self, renderer: &mut impl Renderer<U>) {
+        for boid in self.boids.iter() {
+            renderer.draw_boid(boid.position(), boid.velocity());
+        }
+        renderer.present();
+    }
+}
+
+/// A renderer that draws the flock as text, looking down the Z axis, for quick debugging.
+///
+/// Each boid is drawn as an arrow (`>`, `^`, `<`, or `v`) pointing closest to its heading in the XY
+/// plane, or `o` if it is not moving in that plane. A cell holding more than one boid is drawn as `*`.
+/// Boids outside `bounds` are left out.
+#[cfg(feature = "ascii")]
+#[derive(Debug, Clone)]
+pub struct AsciiRenderer<U: BaseNum + Float> {
+    /// Region of the XY plane that fills the frame, with `max.y` at the top
+    pub bounds: Bounds<U>,
+    width: usize,
+    height: usize,
+    cells: Vec<char>,
+    frame: String,
+}
+
+#[cfg(feature = "ascii")]
+impl<U: BaseNum + Float> AsciiRenderer<U> {
+    /// Create a renderer that draws `bounds` as `width` by `height` characters
+    pub fn new(bounds: Bounds<U>, width: usize, height: usize) -> Self {
+        Self {
+            bounds,
+            width,
+            height,
+            cells: vec![' '; width * height],
+            frame: String::new(),
+        }
+    }
+
+    /// Get the number of characters in each row
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Get the number of rows
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get the most recently presented frame, one line per row with each ending in a newline
+    pub fn frame(&self) -> &str {
+        &self.frame
+    }
+
+    /// Get the cell a value falls in along one axis, or `None` if it is outside the bounds
+    fn cell(value: U, min: U, max: U, cells: usize) -> Option<usize> {
+        if cells == 0 || value.is_nan() || value < min || value > max {
+            return None;
+        }
+        if max <= min {
+            return Some(0);
+        }
+        let fraction = (value - min) / (max - min);
+        let cell = (fraction * U::from(cells).unwrap()).to_usize().unwrap_or(0);
+        Some(cell.min(cells - 1))
+    }
+}
+
+#[cfg(feature = "ascii")]
+impl<U: BaseNum + Float> Renderer<U> for AsciiRenderer<U> {
+    fn draw_boid(&mut self, position: Vector3<U>, velocity: Vector3<U>) {
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        let column = Self::cell(position.x, min.x, max.x, self.width);
+        let row = Self::cell(position.y, min.y, max.y, self.height);
+        let (column, row) = match (column, row) {
+            (Some(column), Some(row)) => (column, self.height - 1 - row),
+            _ => return,
+        };
+
+        let marker = if velocity.x == U::zero() && velocity.y == U::zero() {
+            'o'
+        } else if velocity.x.abs() >= velocity.y.abs() {
+            if velocity.x > U::zero() {
+                '>'
+            } else {
+                '<'
+            }
+        } else if velocity.y > U::zero() {
+            '^'
+        } else {
+            'v'
+        };
+
+        let cell = &mut self.cells[row * self.width + column];
+        *cell = if *cell == ' ' { marker } else { '*' };
+    }
+
+    fn present(&mut self) {
+        self.frame.clear();
+        for row in self.cells.chunks(self.width.max(1)) {
+            self.frame.extend(row.iter());
+            self.frame.push('\n');
+        }
+        self.cells.iter_mut().for_each(|cell| *cell = ' ');
+    }
+}
+
+#[cfg(all(test, feature = "ascii"))]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::AsciiRenderer;
+    use crate::{boids::boid3d::Boid3D, bounds::Bounds, flock::Flock};
+
+    #[test]
+    fn ascii_frames_draw_each_boid() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        for (position, velocity) in [
+            ((1.0, 1.0), (1.0, 0.0)),
+            ((5.0, 5.0), (0.0, 1.0)),
+            ((9.0, 9.0), (-1.0, 0.2)),
+            ((3.0, 7.0), (0.0, 0.0)),
+            ((50.0, 5.0), (1.0, 0.0)),
+        ]
+        .iter()
+        .copied()
+        {
+            flock.boids.push(Boid3D::new_with_velocity(
+                Vector3::new(position.0, position.1, 0.0),
+                Vector3::new(velocity.0, velocity.1, 0.0),
+            ));
+        }
+
+        let bounds = Bounds::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(10.0, 10.0, 0.0));
+        let mut renderer = AsciiRenderer::new(bounds, 10, 5);
+        assert_eq!((renderer.width(), renderer.height()), (10, 5));
+        flock.render(&mut renderer);
+
+        let frame = renderer.frame().to_string();
+        assert_eq!(frame.lines().count(), 5);
+        assert!(frame.lines().all(|line| line.chars().count() == 10));
+        let markers = frame.chars().filter(|c| !c.is_whitespace()).count();
+        assert_eq!(markers, 4);
+        assert_eq!(frame.lines().last().unwrap().trim(), ">");
+        assert_eq!(frame.lines().next().unwrap().trim(), "<");
+        for marker in ['^', 'o'].iter() {
+            assert!(frame.contains(*marker));
+        }
+
+        // Drawing the frame again starts from a blank slate
+        flock.render(&mut renderer);
+        assert_eq!(renderer.frame(), frame);
+    }
+}
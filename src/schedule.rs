@@ -0,0 +1,168 @@
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+
+use cgmath::{num_traits::Float, BaseNum};
+
+#[cfg(feature = "serde")]
+use crate::error::BoidError;
+use crate::{boids::Boid, boids::BoidWeights, flock::Flock};
+
+/// A keyframed timeline of boid weights.
+///
+/// Deserializing checks the keyframes the same way `new` does.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "WeightScheduleData<U>"))]
+pub struct WeightSchedule<U: BaseNum + Float> {
+    /// Keyframes as `(time, weights)` pairs, sorted by time
+    keyframes: Vec<(U, BoidWeights<U>)>,
+    /// Time the schedule has been advanced to
+    pub time: U,
+}
+
+/// A weight schedule as saved, before its keyframes are checked
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct WeightScheduleData<U: BaseNum + Float> {
+    keyframes: Vec<(U, BoidWeights<U>)>,
+    time: U,
+}
+
+#[cfg(feature = "serde")]
+impl<U: BaseNum + Float> TryFrom<WeightScheduleData<U>> for WeightSchedule<U> {
+    type Error = BoidError;
+
+    fn try_from(data: WeightScheduleData<U>) -> Result<Self, Self::Error> {
+        let mut schedule = Self::new(data.keyframes).ok_or_else(|| BoidError::Deserialize {
+            reason: "a weight schedule needs at least one keyframe, all at finite times"
+                .to_string(),
+        })?;
+        schedule.time = data.time;
+        Ok(schedule)
+    }
+}
+
+impl<U: BaseNum + Float> WeightSchedule<U> {
+    /// Create a schedule from keyframes, which are sorted by time.
+    ///
+    /// Returns `None` if there are no keyframes or any time is not finite.
+    pub fn new(mut keyframes: Vec<(U, BoidWeights<U>)>) -> Option<Self> {
+        if keyframes.is_empty() || keyframes.iter().any(|(time, _)| !time.is_finite()) {
+            return None;
+        }
+        keyframes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Some(Self {
+            keyframes,
+            time: U::zero(),
+        })
+    }
+
+    /// Get the keyframes, sorted by time
+    pub fn keyframes(&self) -> &[(U, BoidWeights<U>)] {
+        &self.keyframes
+    }
+
+    /// Get the weights at `time`, interpolating linearly between keyframes.
+    ///
+    /// Times before the first keyframe or after the last are clamped to that keyframe, and a NaN time
+    /// gives the first keyframe.
+    pub fn sample(&self, time: U) -> BoidWeights<U> {
+        let first = self.keyframes[0];
+        let last = self.keyframes[self.keyframes.len() - 1];
+        if time.is_nan() || time <= first.0 {
+            return first.1;
+        }
+        if time >= last.0 {
+            return last.1;
+        }
+
+        // Find the pair of keyframes surrounding the time
+        let next = self.keyframes.partition_point(|(key, _)| *key <= time);
+        let (start, end) = (self.keyframes[next - 1], self.keyframes[next]);
+        let span = end.0 - start.0;
+        if span <= U::zero() {
+            return end.1;
+        }
+        start.1.lerp(&end.1, (time - start.0) / span)
+    }
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Move the weight schedule forward by `dt` and give every boid the weights at the new time.
+    ///
+    /// Does nothing if the flock has no schedule.
+    pub fn advance_schedule(&mut self, dt: U) {
+        let weights = match self.weight_schedule.as_mut() {
+            Some(schedule) => {
+                schedule.time += dt;
+                schedule.sample(schedule.time)
+            }
+            None => return,
+        };
+        for boid in self.boids.iter_mut() {
+            boid.set_weights(weights);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeightSchedule;
+    use crate::boids::BoidWeights;
+
+    fn weights(cohesion: f64) -> BoidWeights<f64> {
+        BoidWeights {
+            cohesion,
+            ..BoidWeights::default()
+        }
+    }
+
+    fn schedule() -> WeightSchedule<f64> {
+        WeightSchedule::new(vec![(5.0, weights(3.0)), (0.0, weights(1.0))]).unwrap()
+    }
+
+    #[test]
+    fn samples_interpolate_between_keyframes() {
+        let schedule = schedule();
+        assert_eq!(schedule.keyframes()[0].0, 0.0);
+        assert!((schedule.sample(2.5).cohesion - 2.0).abs() < 1e-12);
+        assert!((schedule.sample(1.0).cohesion - 1.4).abs() < 1e-12);
+    }
+
+    #[test]
+    fn samples_outside_the_keyframes_clamp() {
+        let schedule = schedule();
+        assert_eq!(schedule.sample(-1.0), weights(1.0));
+        assert_eq!(schedule.sample(10.0), weights(3.0));
+        assert_eq!(schedule.sample(f64::NAN), weights(1.0));
+        assert_eq!(schedule.sample(f64::INFINITY), weights(3.0));
+    }
+
+    #[test]
+    fn rejects_empty_or_non_finite_keyframes() {
+        assert_eq!(WeightSchedule::<f64>::new(vec![]), None);
+        assert_eq!(WeightSchedule::new(vec![(f64::NAN, weights(1.0))]), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserializing_checks_the_keyframes() {
+        use std::convert::TryFrom;
+
+        use super::WeightScheduleData;
+
+        let empty = WeightScheduleData::<f64> {
+            keyframes: vec![],
+            time: 0.0,
+        };
+        assert!(WeightSchedule::try_from(empty).is_err());
+
+        let saved = WeightScheduleData {
+            keyframes: vec![(1.0, weights(2.0))],
+            time: 4.0,
+        };
+        let schedule = WeightSchedule::try_from(saved).unwrap();
+        assert_eq!(schedule.time, 4.0);
+        assert_eq!(schedule.sample(0.0), weights(2.0));
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+use cgmath::{num_traits::Float, BaseNum};
+
+use crate::{boids::boid2d::Boid2D, boids::boid3d::Boid3D, boids::Boid, flock::Flock};
+
+// Flocks hold only plain data, so they can be shared between threads
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Flock<Boid3D<f32>, f32>>();
+    assert_send_sync::<Flock<Boid2D<f64>, f64>>();
+};
+
+/// A flock that can be shared between threads, for example a simulation thread and a render thread.
+///
+/// Cloning a `SharedFlock` gives another handle to the same flock. Any number of readers may sample the
+/// flock at once, while stepping it waits for exclusive access. A panic while holding the lock does not
+/// poison it for other threads.
+#[derive(Debug)]
+pub struct SharedFlock<T: Boid<T, U>, U: BaseNum + Float> {
+    inner: Arc<RwLock<Flock<T, U>>>,
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Clone for SharedFlock<T, U> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> SharedFlock<T, U> {
+    /// Wrap a flock for sharing
+    pub fn new(flock: Flock<T, U>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(flock)),
+        }
+    }
+
+    /// Run `f` with shared read access to the flock
+    pub fn read<R>(&self, f: impl FnOnce(&Flock<T, U>) -> R) -> R {
+        f(&self.inner.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Run `f` with exclusive access to the flock
+    pub fn write<R>(&self, f: impl FnOnce(&mut Flock<T, U>) -> R) -> R {
+        f(&mut self.inner.write().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Take the flock back out, or return `self` if other handles still exist
+    pub fn try_unwrap(self) -> Result<Flock<T, U>, Self> {
+        Arc::try_unwrap(self.inner)
+            .map(|lock| lock.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .map_err(|inner| Self { inner })
+    }
+}
+
+impl<T: Boid<T, U> + Clone, U: BaseNum + Float> SharedFlock<T, U>
+where
+    T: std::marker::Send,
+    T: std::marker::Sync,
+    U: std::marker::Sync,
+{
+    /// Update the flock once, waiting for any readers to finish first
+    pub fn step(&self) {
+        self.write(Flock::update);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::SharedFlock;
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    #[test]
+    fn readers_sample_while_the_owner_steps() {
+        let flock = Flock {
+            boids: (0..20)
+                .map(|i| Boid3D::new_with_angle(Vector3::new(i as f64 * 3.0, 0.0, 0.0), i as f64))
+                .collect(),
+            ..Flock::default()
+        };
+        let shared = SharedFlock::new(flock);
+
+        let reader = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let mut steps = Vec::new();
+                for _ in 0..50 {
+                    let (step, statistics) = shared.read(|flock| (flock.step, flock.statistics()));
+                    assert_eq!(statistics.count, 20);
+                    assert!(statistics.mean_speed.is_finite());
+                    steps.push(step);
+                }
+                steps
+            })
+        };
+        for _ in 0..50 {
+            shared.step();
+        }
+
+        // Every sample saw a whole step, in order
+        let steps = reader.join().unwrap();
+        assert!(steps.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(steps.iter().all(|step| *step <= 50));
+
+        let flock = shared.try_unwrap().unwrap();
+        assert_eq!(flock.step, 50);
+    }
+}
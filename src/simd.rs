@@ -0,0 +1,311 @@
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+use crate::{
+    boids::{boid3d::Boid3D, Boid},
+    flock::Flock,
+};
+
+/// Compute the distance from `origin` to each of `points`, writing them to the same index of `out`.
+///
+/// With the `simd` feature on x86 targets with SSE, four distances are computed at a time. Everywhere
+/// else this falls back to a scalar loop. Both paths round the same way, so they give identical results.
+///
+/// With the `simd` feature, `Flock::update` packs the positions of an `f32` flock together so its
+/// neighbor searches go through this, which speeds up stepping (see `benches/neighbors.rs`). `f64`
+/// flocks, and searches made outside an update, check one boid at a time. Bulk queries such as
+/// `Flock::distances_from` use it directly.
+///
+/// # Panics
+///
+/// Panics if `out` is not the same length as `points`.
+pub fn distances(origin: Vector3<f32>, points: &[Vector3<f32>], out: &mut [f32]) {
+    assert_eq!(
+        points.len(),
+        out.len(),
+        "output must hold one distance per point"
+    );
+
+    cfg_if::cfg_if! {
+        if #[cfg(all(
+            feature = "simd",
+            any(target_arch = "x86", target_arch = "x86_64"),
+            target_feature = "sse"
+        ))] {
+            distances_sse(origin, points, out);
+        } else {
+            distances_scalar(origin, points, out);
+        }
+    }
+}
+
+/// Scalar version of `distances`, also used for the points left over after the SIMD batches
+fn distances_scalar(origin: Vector3<f32>, points: &[Vector3<f32>], out: &mut [f32]) {
+    for (point, distance) in points.iter().zip(out.iter_mut()) {
+        let (dx, dy, dz) = (point.x - origin.x, point.y - origin.y, point.z - origin.z);
+        *distance = (dx * dx + dy * dy + dz * dz).sqrt();
+    }
+}
+
+#[cfg(all(
+    feature = "simd",
+    any(target_arch = "x86", target_arch = "x86_64"),
+    target_feature = "sse"
+))]
+fn distances_sse(origin: Vector3<f32>, points: &[Vector3<f32>], out: &mut [f32]) {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    /// Shuffle mask picking lanes `a` and `b` of the first input then `c` and `d` of the second
+    const fn lanes(d: i32, c: i32, b: i32, a: i32) -> i32 {
+        d << 6 | c << 4 | b << 2 | a
+    }
+
+    let batches = points.len() / 4 * 4;
+
+    // SAFETY: SSE is statically enabled for this target. `Vector3` is `repr(C)`, so each chunk of four
+    // points is twelve contiguous floats, read as three unaligned loads, and every store writes exactly
+    // four floats to a chunk of `out` that is four floats long
+    unsafe {
+        let (ox, oy, oz) = (
+            _mm_set1_ps(origin.x),
+            _mm_set1_ps(origin.y),
+            _mm_set1_ps(origin.z),
+        );
+        for (chunk, distances) in points[..batches]
+            .chunks_exact(4)
+            .zip(out[..batches].chunks_exact_mut(4))
+        {
+            // Transpose x0 y0 z0 x1 | y1 z1 x2 y2 | z2 x3 y3 z3 into one register per axis
+            let floats = chunk.as_ptr() as *const f32;
+            let (a, b, c) = (
+                _mm_loadu_ps(floats),
+                _mm_loadu_ps(floats.add(4)),
+                _mm_loadu_ps(floats.add(8)),
+            );
+            let x2y2x3y3 = _mm_shuffle_ps::<{ lanes(2, 1, 3, 2) }>(b, c);
+            let y0z0y1z1 = _mm_shuffle_ps::<{ lanes(1, 0, 2, 1) }>(a, b);
+            let x = _mm_shuffle_ps::<{ lanes(2, 0, 3, 0) }>(a, x2y2x3y3);
+            let y = _mm_shuffle_ps::<{ lanes(3, 1, 2, 0) }>(y0z0y1z1, x2y2x3y3);
+            let z = _mm_shuffle_ps::<{ lanes(3, 0, 3, 1) }>(y0z0y1z1, c);
+
+            let (dx, dy, dz) = (_mm_sub_ps(x, ox), _mm_sub_ps(y, oy), _mm_sub_ps(z, oz));
+            let squared = _mm_add_ps(
+                _mm_add_ps(_mm_mul_ps(dx, dx), _mm_mul_ps(dy, dy)),
+                _mm_mul_ps(dz, dz),
+            );
+            _mm_storeu_ps(distances.as_mut_ptr(), _mm_sqrt_ps(squared));
+        }
+    }
+
+    distances_scalar(origin, &points[batches..], &mut out[batches..]);
+}
+
+/// Check whether the scalar type `U` is `f32`. Scalars have no `'static` bound to compare type ids
+/// with, so this goes by size and precision instead.
+pub(crate) fn is_f32<U: Float>() -> bool {
+    std::mem::size_of::<U>() == std::mem::size_of::<f32>()
+        && U::epsilon().to_f32() == Some(f32::EPSILON)
+        && U::max_value().to_f32() == Some(f32::MAX)
+}
+
+/// Number of distances worked out on the stack at a time
+const BATCH: usize = 64;
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Pack the boid positions of an `f32` flock for `for_each_within_packed`. Returns `None` without
+    /// the `simd` feature, or for other scalar types, which are searched one boid at a time.
+    pub(crate) fn pack_positions(&self) -> Option<Vec<Vector3<f32>>> {
+        if !cfg!(feature = "simd") || !is_f32::<U>() || self.position_snap.is_some() {
+            return None;
+        }
+        let to_f32 = |value: U| value.to_f32().unwrap_or(f32::NAN);
+        Some(
+            self.boids
+                .iter()
+                .map(|boid| boid.position().map(to_f32))
+                .collect(),
+        )
+    }
+
+    /// `for_each_within` over the positions from `pack_positions`, working out their distances in
+    /// batches. These round exactly as `MetricSpace::distance` does, so the same boids are found at the
+    /// same distances as when checking them one at a time.
+    pub(crate) fn for_each_within_packed(
+        &self,
+        packed: &[Vector3<f32>],
+        position: Vector3<U>,
+        radius: U,
+        mut f: impl FnMut(usize, U),
+    ) {
+        let to_f32 = |value: U| value.to_f32().unwrap_or(f32::NAN);
+        let (origin, limit) = (position.map(to_f32), to_f32(radius));
+
+        // Only the boids that are found have their distance converted back
+        let mut out = [0.0; BATCH];
+        let mut visit_batch = |points: &[Vector3<f32>], index_of: &dyn Fn(usize) -> usize| {
+            let out = &mut out[..points.len()];
+            distances(origin, points, out);
+            for (offset, &distance) in out.iter().enumerate() {
+                if distance <= limit {
+                    f(index_of(offset), U::from(distance).unwrap_or_else(U::nan));
+                }
+            }
+        };
+
+        if !self.uses_grid() && self.sweep.is_none() {
+            for (batch, points) in packed.chunks(BATCH).enumerate() {
+                visit_batch(points, &|offset| batch * BATCH + offset);
+            }
+            return;
+        }
+
+        let mut indices = [0; BATCH];
+        let mut points = [Vector3::new(0.0, 0.0, 0.0); BATCH];
+        let mut len = 0;
+        self.for_each_candidate(position, radius, |index| {
+            if let Some(&point) = packed.get(index) {
+                indices[len] = index;
+                points[len] = point;
+                len += 1;
+                if len == BATCH {
+                    visit_batch(&points, &|offset| indices[offset]);
+                    len = 0;
+                }
+            }
+        });
+        visit_batch(&points[..len], &|offset| indices[offset]);
+    }
+}
+
+impl Flock<Boid3D<f32>, f32> {
+    /// Get the distance from `position` to every boid, in index order, using `distances`
+    pub fn distances_from(&self, position: Vector3<f32>) -> Vec<f32> {
+        let positions: Vec<Vector3<f32>> = self.boids.iter().map(|boid| boid.position).collect();
+        let mut out = vec![0.0; positions.len()];
+        distances(position, &positions, &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{MetricSpace, Vector3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{distances, distances_scalar};
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    #[test]
+    fn batches_match_the_scalar_path() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut point = || {
+            Vector3::new(
+                rng.gen_range(-500.0..500.0),
+                rng.gen_range(-500.0..500.0),
+                rng.gen_range(-500.0..500.0),
+            )
+        };
+
+        // Lengths that do and do not fill whole batches of four
+        for count in [0, 1, 4, 7, 64, 101].iter().copied() {
+            let origin = point();
+            let points: Vec<Vector3<f32>> = (0..count).map(|_| point()).collect();
+            let mut batched = vec![0.0; count];
+            let mut scalar = vec![0.0; count];
+            distances(origin, &points, &mut batched);
+            distances_scalar(origin, &points, &mut scalar);
+
+            assert_eq!(batched, scalar);
+            for (point, distance) in points.iter().zip(batched.iter()) {
+                assert!((origin.distance(*point) - distance).abs() <= 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn flock_distances_are_in_index_order() {
+        let flock = Flock {
+            boids: (0..6)
+                .map(|i| Boid3D::new_with_angle(Vector3::new(i as f32 * 3.0, 4.0, 0.0), 0.0))
+                .collect(),
+            ..Flock::default()
+        };
+        let expected: Vec<f32> = (0..6)
+            .map(|i| Vector3::new(0.0, 0.0, 0.0).distance(Vector3::new(i as f32 * 3.0, 4.0, 0.0)))
+            .collect();
+        assert_eq!(flock.distances_from(Vector3::new(0.0, 0.0, 0.0)), expected);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn packed_neighbor_search_matches_checking_each_boid() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut flock = Flock {
+            boids: (0..90)
+                .map(|_| {
+                    let position = Vector3::new(
+                        rng.gen_range(-60.0..60.0),
+                        rng.gen_range(-60.0..60.0),
+                        rng.gen_range(-5.0..5.0),
+                    );
+                    Boid3D::new_with_angle(position, rng.gen_range(0.0..6.0))
+                })
+                .collect(),
+            ..Flock::default()
+        };
+        let origin = Vector3::new(3.0, -2.0, 1.0);
+        let expected: Vec<(usize, f32)> = flock
+            .boids
+            .iter()
+            .enumerate()
+            .map(|(index, boid)| (index, origin.distance(boid.position)))
+            .filter(|(_, distance)| *distance <= 25.0)
+            .collect();
+
+        flock.packed_positions = flock.pack_positions();
+        assert!(flock.packed_positions.is_some());
+        for &gridded in [false, true].iter() {
+            if gridded {
+                flock.rebuild_grid(20.0);
+                flock.grid_min_boids = 0;
+                assert!(flock.uses_grid());
+            }
+            let mut found = Vec::new();
+            flock.for_each_within(origin, 25.0, |index, distance| {
+                found.push((index, distance))
+            });
+            found.sort_by_key(|(index, _)| *index);
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn packed_updates_match_unpacked_ones() {
+        use crate::boids::Boid;
+
+        let mut rng = StdRng::seed_from_u64(12);
+        let mut flock = Flock {
+            boids: (0..80)
+                .map(|_| {
+                    let position =
+                        Vector3::new(rng.gen_range(-40.0..40.0), rng.gen_range(-40.0..40.0), 0.0);
+                    Boid3D::new_with_angle(position, rng.gen_range(0.0..6.0))
+                })
+                .collect(),
+            ..Flock::default()
+        };
+        for _ in 0..5 {
+            let expected: Vec<Boid3D<f32>> = flock
+                .boids
+                .iter()
+                .enumerate()
+                .map(|(index, boid)| boid.update_at(Some(index), &flock))
+                .collect();
+            flock.update();
+            assert_eq!(flock.boids, expected);
+        }
+    }
+}
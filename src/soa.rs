@@ -0,0 +1,228 @@
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+use crate::{
+    boids::{boid3d::Boid3D, BoidWeights},
+    error::{check_length, BoidError},
+    flock::Flock,
+};
+
+/// The boids of a flock stored as a struct of arrays, with one contiguous column per field.
+///
+/// Bulk math over a single field (such as every position) touches far less memory this way than
+/// walking a `Vec<Boid3D>`, and each column can be uploaded to a GPU as-is. Entry `i` of every column
+/// belongs to boid `i`, so all columns should be kept the same length.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FlockSoA<U: BaseNum + Float> {
+    pub positions: Vec<Vector3<U>>,
+    pub velocities: Vec<Vector3<U>>,
+    pub accelerations: Vec<Vector3<U>>,
+    pub last_velocities: Vec<Vector3<U>>,
+    pub max_speeds: Vec<U>,
+    pub min_speeds: Vec<U>,
+    pub max_forces: Vec<U>,
+    pub turn_rates: Vec<U>,
+    pub weights: Vec<BoidWeights<U>>,
+    pub groups: Vec<u16>,
+    pub radii: Vec<U>,
+    pub panic: Vec<U>,
+    pub panic_decay: Vec<U>,
+    pub smoothed_headings: Vec<Option<Vector3<U>>>,
+    pub smoothed_centers: Vec<Option<Vector3<U>>>,
+    pub targets: Vec<Option<Vector3<U>>>,
+}
+
+impl<U: BaseNum + Float> FlockSoA<U> {
+    /// Split boids into columns
+    pub fn from_boids(boids: &[Boid3D<U>]) -> Self {
+        Self {
+            positions: boids.iter().map(|boid| boid.position).collect(),
+            velocities: boids.iter().map(|boid| boid.velocity).collect(),
+            accelerations: boids.iter().map(|boid| boid.acceleration).collect(),
+            last_velocities: boids.iter().map(|boid| boid.last_velocity).collect(),
+            max_speeds: boids.iter().map(|boid| boid.max_speed).collect(),
+            min_speeds: boids.iter().map(|boid| boid.min_speed).collect(),
+            max_forces: boids.iter().map(|boid| boid.max_force).collect(),
+            turn_rates: boids.iter().map(|boid| boid.r).collect(),
+            weights: boids.iter().map(|boid| boid.weights).collect(),
+            groups: boids.iter().map(|boid| boid.group).collect(),
+            radii: boids.iter().map(|boid| boid.radius).collect(),
+            panic: boids.iter().map(|boid| boid.panic).collect(),
+            panic_decay: boids.iter().map(|boid| boid.panic_decay).collect(),
+            smoothed_headings: boids.iter().map(|boid| boid.smoothed_heading).collect(),
+            smoothed_centers: boids.iter().map(|boid| boid.smoothed_center).collect(),
+            targets: boids.iter().map(|boid| boid.target).collect(),
+        }
+    }
+
+    /// Reassemble the columns into boids.
+    ///
+    /// Only as many boids as the shortest column holds are produced.
+    pub fn to_boids(&self) -> Vec<Boid3D<U>> {
+        (0..self.len())
+            .map(|i| Boid3D {
+                position: self.positions[i],
+                velocity: self.velocities[i],
+                acceleration: self.accelerations[i],
+                last_velocity: self.last_velocities[i],
+                max_speed: self.max_speeds[i],
+                min_speed: self.min_speeds[i],
+                max_force: self.max_forces[i],
+                r: self.turn_rates[i],
+                weights: self.weights[i],
+                group: self.groups[i],
+                radius: self.radii[i],
+                panic: self.panic[i],
+                panic_decay: self.panic_decay[i],
+                smoothed_heading: self.smoothed_headings[i],
+                smoothed_center: self.smoothed_centers[i],
+                target: self.targets[i],
+            })
+            .collect()
+    }
+
+    /// Reassemble the columns into boids, failing if the columns are not all the same length
+    pub fn try_to_boids(&self) -> Result<Vec<Boid3D<U>>, BoidError> {
+        check_length(self.len(), self.positions.len())?;
+        for length in [
+            self.velocities.len(),
+            self.accelerations.len(),
+            self.last_velocities.len(),
+            self.max_speeds.len(),
+            self.min_speeds.len(),
+            self.max_forces.len(),
+            self.turn_rates.len(),
+            self.weights.len(),
+            self.groups.len(),
+            self.radii.len(),
+            self.panic.len(),
+            self.panic_decay.len(),
+            self.smoothed_headings.len(),
+            self.smoothed_centers.len(),
+            self.targets.len(),
+        ] {
+            check_length(self.positions.len(), length)?;
+        }
+        Ok(self.to_boids())
+    }
+
+    /// Get the number of complete boids stored, which is the length of the shortest column
+    pub fn len(&self) -> usize {
+        [
+            self.positions.len(),
+            self.velocities.len(),
+            self.accelerations.len(),
+            self.last_velocities.len(),
+            self.max_speeds.len(),
+            self.min_speeds.len(),
+            self.max_forces.len(),
+            self.turn_rates.len(),
+            self.weights.len(),
+            self.groups.len(),
+            self.radii.len(),
+            self.panic.len(),
+            self.panic_decay.len(),
+            self.smoothed_headings.len(),
+            self.smoothed_centers.len(),
+            self.targets.len(),
+        ]
+        .iter()
+        .copied()
+        .min()
+        .unwrap_or(0)
+    }
+
+    /// Check if no boids are stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the mean position of the stored boids, or `None` if there are none
+    pub fn centroid(&self) -> Option<Vector3<U>> {
+        let positions = &self.positions[..self.len()];
+        if positions.is_empty() {
+            return None;
+        }
+        let sum = positions.iter().fold(
+            Vector3::new(U::zero(), U::zero(), U::zero()),
+            |sum, position| sum + *position,
+        );
+        Some(sum / U::from(positions.len()).unwrap())
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Copy the boids into struct-of-arrays form
+    pub fn to_soa(&self) -> FlockSoA<U> {
+        FlockSoA::from_boids(&self.boids)
+    }
+
+    /// Replace the boids with the ones stored in `soa`, then refresh the spatial indexes.
+    ///
+    /// Every other setting of the flock is kept.
+    pub fn load_soa(&mut self, soa: &FlockSoA<U>) {
+        self.boids = soa.to_boids();
+        self.refresh_grid();
+    }
+
+    /// Replace the boids with the ones stored in `soa` like `load_soa`, but fail without changing
+    /// anything if its columns are not all the same length
+    pub fn try_load_soa(&mut self, soa: &FlockSoA<U>) -> Result<(), BoidError> {
+        self.boids = soa.try_to_boids()?;
+        self.refresh_grid();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::{boids::boid3d::Boid3D, error::BoidError, flock::Flock};
+
+    fn flock() -> Flock<Boid3D<f64>, f64> {
+        let mut flock = Flock {
+            boids: (0..10)
+                .map(|i| {
+                    let position = Vector3::new(i as f64 * 7.0, (i % 3) as f64 * 5.0, 0.0);
+                    Boid3D::new_with_angle(position, i as f64)
+                })
+                .collect(),
+            neighbor_smoothing: Some(2.0),
+            ..Flock::default()
+        };
+        flock.boids[3].raise_panic(0.5);
+        flock.boids[6].target = Some(Vector3::new(1.0, 2.0, 3.0));
+        for _ in 0..5 {
+            flock.update();
+        }
+        flock
+    }
+
+    #[test]
+    fn round_trips_exactly() {
+        let original = flock();
+        let mut restored = Flock::default();
+        restored.load_soa(&original.to_soa());
+        assert_eq!(restored.boids, original.boids);
+        assert_eq!(original.to_soa().centroid(), original.centroid());
+    }
+
+    #[test]
+    fn ragged_columns_are_rejected() {
+        let mut soa = flock().to_soa();
+        soa.radii.pop();
+        assert_eq!(soa.len(), 9);
+
+        let mut target = flock();
+        let before = target.boids.clone();
+        assert_eq!(
+            target.try_load_soa(&soa),
+            Err(BoidError::LengthMismatch {
+                expected: 9,
+                found: 10
+            })
+        );
+        assert_eq!(target.boids, before);
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, MetricSpace, Vector3};
+use rand::Rng;
+
+use crate::{
+    boids::{boid2d::Boid2D, boid3d::Boid3D, frame::heading_frame, Boid},
+    bounds::Bounds,
+    flock::Flock,
+};
+
+/// Number of candidates tried around each active sample before it is retired
+const POISSON_ATTEMPTS: usize = 30;
+
+/// How the initial headings of spawned 3D boids are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HeadingDistribution<U: BaseNum + Float> {
+    /// Uniform directions in the XY plane, with no vertical component
+    #[default]
+    Flat,
+    /// Uniform directions over the whole sphere
+    UniformSphere,
+    /// Uniform directions within `angle` radians of `axis`
+    Cone { axis: Vector3<U>, angle: U },
+}
+
+impl<U: BaseNum + Float> HeadingDistribution<U> {
+    /// Draw a unit heading
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Vector3<U> {
+        let tau = std::f64::consts::PI * 2.0;
+        match *self {
+            HeadingDistribution::Flat => {
+                let angle = U::from(rng.gen::<f64>() * tau).unwrap();
+                Vector3::new(angle.cos(), angle.sin(), U::zero())
+            }
+            HeadingDistribution::UniformSphere => {
+                // Archimedes: the height of a uniform point on a sphere is itself uniform
+                let z = U::from(rng.gen::<f64>() * 2.0 - 1.0).unwrap();
+                let angle = U::from(rng.gen::<f64>() * tau).unwrap();
+                let ring = (U::one() - z * z).max(U::zero()).sqrt();
+                Vector3::new(ring * angle.cos(), ring * angle.sin(), z)
+            }
+            HeadingDistribution::Cone { axis, angle } => {
+                let (forward, side, up) = heading_frame(axis);
+                let spread = angle
+                    .max(U::zero())
+                    .min(U::from(std::f64::consts::PI).unwrap());
+
+                // Drawing the cosine uniformly spreads the headings evenly over the cap
+                let cos = U::one() - U::from(rng.gen::<f64>()).unwrap() * (U::one() - spread.cos());
+                let sin = (U::one() - cos * cos).max(U::zero()).sqrt();
+                let around = U::from(rng.gen::<f64>() * tau).unwrap();
+                forward * cos + (side * around.cos() + up * around.sin()) * sin
+            }
+        }
+    }
+}
+
+/// Generate points inside `bounds` where no two points are closer than `min_distance`.
+///
+/// This uses Bridson's fast Poisson-disk sampling. Axes where `bounds` has no extent are held fixed,
+/// so flat bounds give a 2D pattern.
+pub fn poisson_disk<U: BaseNum + Float, R: Rng>(
+    bounds: Bounds<U>,
+    min_distance: U,
+    rng: &mut R,
+) -> Vec<Vector3<U>> {
+    let size = bounds.size();
+    if min_distance <= U::zero() || size.x < U::zero() || size.y < U::zero() || size.z < U::zero() {
+        return Vec::new();
+    }
+    let active_axes: Vec<usize> = (0..3).filter(|axis| size[*axis] > U::zero()).collect();
+    if active_axes.is_empty() {
+        return vec![bounds.min];
+    }
+
+    // Background grid sized so each cell holds at most one sample
+    let cell_size = min_distance / U::from(active_axes.len()).unwrap().sqrt();
+    let key = |point: Vector3<U>| {
+        let axis = |axis: usize| {
+            ((point[axis] - bounds.min[axis]) / cell_size)
+                .floor()
+                .to_i64()
+                .unwrap_or(0)
+        };
+        (axis(0), axis(1), axis(2))
+    };
+    let random = |rng: &mut R| U::from(rng.gen::<f64>()).unwrap();
+
+    let mut grid: HashMap<(i64, i64, i64), usize> = HashMap::new();
+    let mut samples = Vec::new();
+    let mut active = Vec::new();
+
+    // Start from a random point
+    let mut first = bounds.min;
+    for axis in active_axes.iter() {
+        first[*axis] = bounds.min[*axis] + size[*axis] * random(rng);
+    }
+    grid.insert(key(first), 0);
+    samples.push(first);
+    active.push(0);
+
+    let two = U::one() + U::one();
+    while !active.is_empty() {
+        let slot = rng.gen_range(0..active.len());
+        let origin = samples[active[slot]];
+        let mut found = false;
+
+        for _ in 0..POISSON_ATTEMPTS {
+            // Pick a random direction in the active axes
+            let mut direction = Vector3::new(U::zero(), U::zero(), U::zero());
+            for axis in active_axes.iter() {
+                direction[*axis] = random(rng) * two - U::one();
+            }
+            if direction.magnitude2() <= U::epsilon() || direction.magnitude2() > U::one() {
+                continue;
+            }
+            let candidate =
+                origin + direction.normalize() * (min_distance * (U::one() + random(rng)));
+            if !bounds.contains(candidate) {
+                continue;
+            }
+
+            // Reject candidates too close to an existing sample
+            let (x, y, z) = key(candidate);
+            let reach = |axis: usize| if active_axes.contains(&axis) { 2 } else { 0 };
+            let mut clear = true;
+            'search: for dx in -reach(0)..=reach(0) {
+                for dy in -reach(1)..=reach(1) {
+                    for dz in -reach(2)..=reach(2) {
+                        if let Some(other) = grid.get(&(x + dx, y + dy, z + dz)) {
+                            if samples[*other].distance(candidate) < min_distance {
+                                clear = false;
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+
+            if clear {
+                grid.insert((x, y, z), samples.len());
+                active.push(samples.len());
+                samples.push(candidate);
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            active.swap_remove(slot);
+        }
+    }
+
+    samples
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Give every boid a fresh random heading in the XY plane, keeping its position, parameters, and
+    /// speed.
+    ///
+    /// Stationary boids are sent off at their `max_speed` instead. `rng` only picks which of each
+    /// boid's own random streams the heading is drawn from, so a boid's new heading does not depend on
+    /// how many boids come before it.
+    pub fn reseed<R: Rng>(&mut self, rng: &mut R) {
+        self.reseed_headings(HeadingDistribution::Flat, rng);
+    }
+
+    /// Give every boid a fresh heading drawn from `headings`, keeping its speed
+    fn reseed_headings<R: Rng>(&mut self, headings: HeadingDistribution<U>, rng: &mut R) {
+        let stream = rng.gen::<u64>();
+        for index in 0..self.boids.len() {
+            let heading = headings.sample(&mut self.boid_rng(index, stream));
+            let boid = &mut self.boids[index];
+            let speed = boid.velocity().magnitude();
+            let speed = if speed > U::zero() {
+                speed
+            } else {
+                boid.max_speed()
+            };
+            boid.set_velocity(heading * speed);
+        }
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Add boids evenly spread through `bounds`, no two closer than `min_distance`, with random headings
+    /// in the XY plane.
+    ///
+    /// Returns the number of boids added.
+    pub fn spawn_poisson<R: Rng>(
+        &mut self,
+        bounds: Bounds<U>,
+        min_distance: U,
+        rng: &mut R,
+    ) -> usize {
+        self.spawn_poisson_with_headings(bounds, min_distance, HeadingDistribution::Flat, rng)
+    }
+
+    /// Add boids evenly spread through `bounds`, no two closer than `min_distance`, with headings drawn
+    /// from `headings`.
+    ///
+    /// Returns the number of boids added.
+    pub fn spawn_poisson_with_headings<R: Rng>(
+        &mut self,
+        bounds: Bounds<U>,
+        min_distance: U,
+        headings: HeadingDistribution<U>,
+        rng: &mut R,
+    ) -> usize {
+        let positions = poisson_disk(bounds, min_distance, rng);
+        let count = positions.len();
+        for position in positions {
+            let mut boid = Boid3D::new_with_angle(position, U::zero());
+            boid.velocity = headings.sample(rng);
+            boid.last_velocity = boid.velocity;
+            self.boids.push(boid);
+        }
+        self.refresh_grid();
+        count
+    }
+
+    /// Give every boid a fresh heading drawn from `headings`, like `reseed`
+    pub fn reseed_with<R: Rng>(&mut self, headings: HeadingDistribution<U>, rng: &mut R) {
+        self.reseed_headings(headings, rng);
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid2D<U>, U> {
+    /// Add boids evenly spread through `bounds`, no two closer than `min_distance`, with random headings.
+    ///
+    /// Only the X and Y extents of `bounds` are used. Returns the number of boids added.
+    pub fn spawn_poisson<R: Rng>(
+        &mut self,
+        bounds: Bounds<U>,
+        min_distance: U,
+        rng: &mut R,
+    ) -> usize {
+        let mut flat = bounds;
+        flat.min.z = U::zero();
+        flat.max.z = U::zero();
+        let positions = poisson_disk(flat, min_distance, rng);
+        let count = positions.len();
+        for position in positions {
+            let angle = U::from(rng.gen::<f64>() * std::f64::consts::PI * 2.0).unwrap();
+            self.boids
+                .push(Boid2D::new_with_angle(position.truncate(), angle));
+        }
+        self.refresh_grid();
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, MetricSpace, Vector3};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::HeadingDistribution;
+    use crate::{
+        boids::{boid2d::Boid2D, boid3d::Boid3D, Boid},
+        bounds::Bounds,
+        flock::Flock,
+    };
+
+    fn check_spacing<T: Boid<T, f64>>(
+        flock: &Flock<T, f64>,
+        bounds: Bounds<f64>,
+        min_distance: f64,
+    ) {
+        for (i, boid) in flock.boids.iter().enumerate() {
+            assert!(bounds.contains(boid.position()));
+            for other in flock.boids[i + 1..].iter() {
+                assert!(boid.position().distance(other.position()) >= min_distance);
+            }
+        }
+    }
+
+    #[test]
+    fn poisson_spawn_is_evenly_spaced_and_dense() {
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let plane = Bounds::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(100.0, 100.0, 0.0));
+        let mut flock_2d: Flock<Boid2D<f64>, f64> = Flock::default();
+        let added = flock_2d.spawn_poisson(plane, 5.0, &mut rng);
+        assert_eq!(added, flock_2d.boids.len());
+        check_spacing(&flock_2d, plane, 5.0);
+        // At least half as many as a square grid of the same spacing would fit
+        assert!(added > 200);
+
+        let cube = Bounds::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(50.0, 50.0, 50.0));
+        let mut flock_3d: Flock<Boid3D<f64>, f64> = Flock::default();
+        let added = flock_3d.spawn_poisson(cube, 5.0, &mut rng);
+        check_spacing(&flock_3d, cube, 5.0);
+        assert!(added > 500);
+    }
+
+    #[test]
+    fn heading_distributions_cover_their_shapes() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let sample = |headings: HeadingDistribution<f64>, rng: &mut StdRng| {
+            (0..500)
+                .map(|_| headings.sample(rng))
+                .collect::<Vec<Vector3<f64>>>()
+        };
+
+        let flat = sample(HeadingDistribution::Flat, &mut rng);
+        assert!(flat.iter().all(|heading| heading.z == 0.0));
+
+        // A uniform height means an average absolute height of a half
+        let sphere = sample(HeadingDistribution::UniformSphere, &mut rng);
+        let mean_height = sphere.iter().map(|heading| heading.z.abs()).sum::<f64>() / 500.0;
+        assert!((mean_height - 0.5).abs() < 0.05);
+        assert!(sphere.iter().any(|heading| heading.z > 0.9));
+        assert!(sphere.iter().any(|heading| heading.z < -0.9));
+
+        let axis = Vector3::new(0.0, 1.0, 1.0);
+        let cone = sample(HeadingDistribution::Cone { axis, angle: 0.3 }, &mut rng);
+        assert!(cone
+            .iter()
+            .all(|heading| heading.angle(axis).0 <= 0.3 + 1e-9));
+
+        for heading in flat.iter().chain(sphere.iter()).chain(cone.iter()) {
+            assert!((heading.magnitude() - 1.0).abs() < 1e-9);
+        }
+
+        // Spawning draws from the distribution too
+        let cube = Bounds::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(30.0, 30.0, 30.0));
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock.spawn_poisson_with_headings(cube, 5.0, HeadingDistribution::UniformSphere, &mut rng);
+        assert!(flock.boids.iter().any(|boid| boid.velocity.z.abs() > 0.5));
+        flock.reseed_with(HeadingDistribution::Flat, &mut rng);
+        assert!(flock.boids.iter().all(|boid| boid.velocity.z == 0.0));
+    }
+
+    #[test]
+    fn reseeding_keeps_positions_and_speeds() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        flock.spawn_poisson(
+            Bounds::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(20.0, 20.0, 0.0)),
+            3.0,
+            &mut StdRng::seed_from_u64(1),
+        );
+        flock.boids[0].velocity = Vector3::new(0.0, 0.0, 0.0);
+        let original = flock.clone();
+
+        let mut first = flock.clone();
+        first.reseed(&mut StdRng::seed_from_u64(2));
+        let mut second = flock;
+        second.reseed(&mut StdRng::seed_from_u64(2));
+
+        for ((first, second), original) in first
+            .boids
+            .iter()
+            .zip(second.boids.iter())
+            .zip(original.boids.iter())
+        {
+            assert_eq!(first.position, original.position);
+            assert_eq!(first.velocity, second.velocity);
+            assert_ne!(first.velocity, original.velocity);
+            let speed = original.velocity.magnitude();
+            let speed = if speed > 0.0 {
+                speed
+            } else {
+                original.max_speed
+            };
+            assert!((first.velocity.magnitude() - speed).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, Vector3};
+
+use crate::{
+    boids::{boid3d::Boid3D, BoidWeights},
+    flock::Flock,
+};
+
+/// A temporary panic that scatters a flock away from a point before it regroups
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Startle<U: BaseNum + Float> {
+    /// Point the boids flee from
+    pub source: Vector3<U>,
+    /// Number of updates the startle lasts for
+    pub duration: U,
+    /// Number of updates since the startle began
+    pub elapsed: U,
+    /// Separation weight multiplier at the peak of the startle
+    pub separation_boost: U,
+    /// Strength of the flee force at the peak of the startle
+    pub flee: U,
+    /// Largest angle, in radians, each boid's escape direction is turned away from straight out from the
+    /// source. Each boid gets its own fixed direction within this cone, so the flock fans out.
+    pub spread: U,
+}
+
+impl<U: BaseNum + Float> Startle<U> {
+    /// Create a new startle with the default response strengths
+    pub fn new(source: Vector3<U>, duration: U) -> Self {
+        Self {
+            source,
+            duration,
+            elapsed: U::zero(),
+            separation_boost: U::from(4.0).unwrap(),
+            flee: U::from(0.1).unwrap(),
+            spread: U::zero(),
+        }
+    }
+
+    /// Get how strong the startle currently is, decaying linearly from `1` to `0`
+    pub fn intensity(&self) -> U {
+        if self.duration <= U::zero() {
+            return U::zero();
+        }
+        (U::one() - self.elapsed / self.duration).max(U::zero())
+    }
+
+    /// Check if the startle has fully decayed
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Blend a boid's normal weights towards the panicked weights
+    pub fn apply(&self, weights: &BoidWeights<U>) -> BoidWeights<U> {
+        weights.panicked(self.intensity(), self.separation_boost)
+    }
+
+    /// Get the force pushing a boid at `position` away from the source
+    pub fn flee_force(&self, position: Vector3<U>) -> Vector3<U> {
+        let away = position - self.source;
+        if away.magnitude2() <= U::zero() {
+            return Vector3::new(U::zero(), U::zero(), U::zero());
+        }
+        away.normalize() * (self.flee * self.intensity())
+    }
+}
+
+impl<U: BaseNum + Float> Flock<Boid3D<U>, U> {
+    /// Spread panic one step outwards, so an alarm raised by a few boids ripples through the flock.
+    ///
+    /// Each boid's panic is raised to that of its most panicked neighbor within the alignment radius,
+    /// reduced by the fraction `decay`. Boids are only affected by their neighbors' panic from before
+    /// this call, so calling this once per update moves the wave one neighborhood per update.
+    pub fn propagate_alarm(&mut self, decay: U) {
+        let carried = U::one() - decay.max(U::zero()).min(U::one());
+        let panic: Vec<U> = self
+            .boids
+            .iter()
+            .enumerate()
+            .map(|(index, boid)| {
+                let mut loudest = boid.panic;
+                self.neighbors_excluding_self(
+                    boid,
+                    Some(index),
+                    self.goal_alignment(),
+                    |other, _| {
+                        loudest = loudest.max(self.boids[other].panic * carried);
+                    },
+                );
+                loudest
+            })
+            .collect();
+        for (boid, panic) in self.boids.iter_mut().zip(panic) {
+            boid.panic = panic;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use super::Startle;
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    #[test]
+    fn startle_boosts_separation_then_wears_off() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        for i in 0..4 {
+            flock.boids.push(Boid3D::new_with_angle(
+                Vector3::new(i as f64 * 30.0, 0.0, 0.0),
+                0.0,
+            ));
+        }
+        let normal = flock.boids[0].weights;
+
+        flock.startle(Vector3::new(0.0, 0.0, 0.0), 10.0);
+        let startled = flock.effective_weights(&normal);
+        assert!(startled.separation > normal.separation);
+        assert!(startled.cohesion < normal.cohesion);
+
+        for _ in 0..10 {
+            flock.update();
+        }
+        assert!(flock.startle.is_none());
+        assert_eq!(flock.effective_weights(&normal), normal);
+        assert_eq!(flock.boids[0].weights, normal);
+    }
+
+    #[test]
+    fn flee_force_points_away_from_the_source_and_fades() {
+        let mut startle = super::Startle::new(Vector3::new(0.0, 0.0, 0.0), 4.0);
+        let force = startle.flee_force(Vector3::new(0.0, 3.0, 0.0));
+        assert!(force.y > 0.0 && force.x == 0.0);
+
+        startle.elapsed = 2.0;
+        assert_eq!(startle.intensity(), 0.5);
+        startle.elapsed = 4.0;
+        assert!(startle.is_finished());
+        assert_eq!(startle.flee_force(Vector3::new(0.0, 3.0, 0.0)).y, 0.0);
+    }
+
+    #[test]
+    fn spread_fans_fleeing_boids_out() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        for i in 0..8 {
+            flock.boids.push(Boid3D::new_with_angle(
+                Vector3::new(100.0, i as f64 * 0.1, 0.0),
+                0.0,
+            ));
+        }
+        let mut startle = Startle::new(Vector3::new(0.0, 0.0, 0.0), 10.0);
+
+        // Widest angle between any two escape directions
+        let widest = |flock: &Flock<Boid3D<f64>, f64>| {
+            let directions: Vec<Vector3<f64>> = (0..flock.boids.len())
+                .map(|i| {
+                    flock
+                        .startle_force(flock.boids[i].position, Some(i))
+                        .normalize()
+                })
+                .collect();
+            let mut widest: f64 = 0.0;
+            for a in directions.iter() {
+                for b in directions.iter() {
+                    widest = widest.max(a.dot(*b).min(1.0).acos());
+                }
+            }
+            widest
+        };
+
+        flock.startle = Some(startle);
+        assert!(widest(&flock) < 0.01);
+
+        startle.spread = 0.6;
+        flock.startle = Some(startle);
+        let spread = widest(&flock);
+        assert!(spread > 0.3 && spread <= 1.2 + 1e-9);
+        // The directions are fixed per boid
+        assert_eq!(spread, widest(&flock));
+    }
+
+    #[test]
+    fn alarms_spread_one_neighborhood_per_step() {
+        // Only adjacent boids are within the alignment radius of each other
+        let mut flock = Flock {
+            boids: (0..6)
+                .map(|i| Boid3D::new_with_angle(Vector3::new(i as f64 * 40.0, 0.0, 0.0), 0.0))
+                .collect(),
+            ..Flock::default()
+        };
+        flock.refresh_grid();
+        flock.boids[0].raise_panic(1.0);
+
+        for step in 1..6 {
+            flock.propagate_alarm(0.25);
+            for (i, boid) in flock.boids.iter().enumerate() {
+                if i <= step {
+                    assert!((boid.panic - 0.75f64.powi(i as i32)).abs() < 1e-12);
+                } else {
+                    assert_eq!(boid.panic, 0.0);
+                }
+            }
+        }
+    }
+}
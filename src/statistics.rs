@@ -0,0 +1,411 @@
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, MetricSpace, Vector3};
+
+use crate::{boids::Boid, bounds::Bounds, flock::Flock};
+
+/// Aggregate measurements of a flock's state
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FlockStatistics<U: BaseNum + Float> {
+    /// Number of boids in the flock
+    pub count: usize,
+    /// Mean boid position
+    pub centroid: Vector3<U>,
+    /// Mean boid speed
+    pub mean_speed: U,
+    /// How aligned the boids' headings are, from `0` (disordered) to `1` (all heading the same way)
+    pub polarization: U,
+    /// Mean distance from each boid to its nearest neighbor
+    pub mean_nearest_neighbor: U,
+}
+
+/// A time series of flock measurements, recorded after every update of the flock it is attached to.
+///
+/// Attach one by setting `Flock::metrics`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MetricsCollector<U: BaseNum + Float> {
+    steps: Vec<u64>,
+    centroids: Vec<Vector3<U>>,
+    polarization: Vec<U>,
+    mean_speed: Vec<U>,
+    mean_nearest_neighbor: Vec<U>,
+}
+
+impl<U: BaseNum + Float> MetricsCollector<U> {
+    /// Create an empty collector
+    pub fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            centroids: Vec::new(),
+            polarization: Vec::new(),
+            mean_speed: Vec::new(),
+            mean_nearest_neighbor: Vec::new(),
+        }
+    }
+
+    /// Add one sample, taken at the given flock step
+    pub fn record(&mut self, step: u64, statistics: &FlockStatistics<U>) {
+        self.steps.push(step);
+        self.centroids.push(statistics.centroid);
+        self.polarization.push(statistics.polarization);
+        self.mean_speed.push(statistics.mean_speed);
+        self.mean_nearest_neighbor
+            .push(statistics.mean_nearest_neighbor);
+    }
+
+    /// Get the number of samples recorded
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Check if no samples have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Drop every recorded sample
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Get the flock step each sample was taken at
+    pub fn steps(&self) -> &[u64] {
+        &self.steps
+    }
+
+    /// Get the centroid column
+    pub fn centroids(&self) -> &[Vector3<U>] {
+        &self.centroids
+    }
+
+    /// Get the polarization column
+    pub fn polarization(&self) -> &[U] {
+        &self.polarization
+    }
+
+    /// Get the mean speed column
+    pub fn mean_speed(&self) -> &[U] {
+        &self.mean_speed
+    }
+
+    /// Get the mean nearest neighbor distance column
+    pub fn mean_nearest_neighbor(&self) -> &[U] {
+        &self.mean_nearest_neighbor
+    }
+
+    /// Pass every recorded value through `f`, for converting between scalar types
+    pub(crate) fn map<V: BaseNum + Float>(&self, f: impl Fn(U) -> V) -> MetricsCollector<V> {
+        MetricsCollector {
+            steps: self.steps.clone(),
+            centroids: self
+                .centroids
+                .iter()
+                .map(|centroid| centroid.map(&f))
+                .collect(),
+            polarization: self.polarization.iter().copied().map(&f).collect(),
+            mean_speed: self.mean_speed.iter().copied().map(&f).collect(),
+            mean_nearest_neighbor: self.mean_nearest_neighbor.iter().copied().map(&f).collect(),
+        }
+    }
+
+    /// Format every sample as CSV, one row per sample after a header row
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "step,centroid_x,centroid_y,centroid_z,polarization,mean_speed,mean_nearest_neighbor\n",
+        );
+        let number = |value: U| value.to_f64().unwrap_or(f64::NAN);
+        for i in 0..self.len() {
+            let centroid = self.centroids[i];
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                self.steps[i],
+                number(centroid.x),
+                number(centroid.y),
+                number(centroid.z),
+                number(self.polarization[i]),
+                number(self.mean_speed[i]),
+                number(self.mean_nearest_neighbor[i]),
+            ));
+        }
+        csv
+    }
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Get the mean position of all boids, or `None` if the flock is empty.
+    ///
+    /// This works for flocks of any dimension, since every boid reports its position in 3D.
+    pub fn centroid(&self) -> Option<Vector3<U>> {
+        if self.boids.is_empty() {
+            return None;
+        }
+
+        let sum = self.boids.iter().fold(
+            Vector3::new(U::zero(), U::zero(), U::zero()),
+            |sum, boid| sum + boid.position(),
+        );
+        Some(sum / U::from(self.boids.len()).unwrap())
+    }
+
+    /// Get the mean velocity of all boids, or zero if the flock is empty
+    pub fn centroid_velocity(&self) -> Vector3<U> {
+        if self.boids.is_empty() {
+            return Vector3::new(U::zero(), U::zero(), U::zero());
+        }
+        self.momentum() / U::from(self.boids.len()).unwrap()
+    }
+
+    /// Get the total momentum of the flock, treating every boid as having unit mass.
+    ///
+    /// This is the sum of every boid's velocity, so it is zero for an empty flock.
+    pub fn momentum(&self) -> Vector3<U> {
+        self.boids.iter().fold(
+            Vector3::new(U::zero(), U::zero(), U::zero()),
+            |sum, boid| sum + boid.velocity(),
+        )
+    }
+
+    /// Get the smallest box containing every boid, or `None` if the flock is empty
+    pub fn bounding_box(&self) -> Option<Bounds<U>> {
+        let first = self.boids.first()?.position();
+        Some(
+            self.boids
+                .iter()
+                .fold(Bounds::new(first, first), |bounds, boid| {
+                    let position = boid.position();
+                    Bounds::new(
+                        Vector3::new(
+                            bounds.min.x.min(position.x),
+                            bounds.min.y.min(position.y),
+                            bounds.min.z.min(position.z),
+                        ),
+                        Vector3::new(
+                            bounds.max.x.max(position.x),
+                            bounds.max.y.max(position.y),
+                            bounds.max.z.max(position.z),
+                        ),
+                    )
+                }),
+        )
+    }
+
+    /// Get the distance from boid `index` to the closest other boid, given the flock's `extent` (the
+    /// diagonal of its bounding box).
+    ///
+    /// With a spatial grid or sweep-and-prune index, the search starts at the separation radius and doubles
+    /// until it finds a neighbor, so packed flocks only look at nearby boids. Without one, every boid is
+    /// checked once.
+    fn nearest_neighbor_distance(&self, index: usize, extent: U) -> U {
+        let position = self.boids[index].position();
+        let indexed = self.uses_grid() || self.sweep.is_some();
+        let mut radius = if indexed && self.perception.separation() > U::zero() {
+            self.perception.separation()
+        } else {
+            U::infinity()
+        };
+        loop {
+            let mut nearest = U::infinity();
+            self.for_each_within(position, radius, |other, distance| {
+                if other != index {
+                    nearest = nearest.min(distance);
+                }
+            });
+            if nearest <= radius || radius == U::infinity() {
+                return nearest;
+            }
+            // Past the extent every boid is in range, so finish with one last unbounded search
+            radius = if radius > extent {
+                U::infinity()
+            } else {
+                radius + radius
+            };
+        }
+    }
+
+    /// Measure the flock. An empty flock reports zero for every measurement.
+    pub fn statistics(&self) -> FlockStatistics<U> {
+        #[cfg(feature = "puffin")]
+        puffin::profile_function!();
+
+        let zero = Vector3::new(U::zero(), U::zero(), U::zero());
+        let centroid = match self.centroid() {
+            Some(centroid) => centroid,
+            None => {
+                return FlockStatistics {
+                    count: 0,
+                    centroid: zero,
+                    mean_speed: U::zero(),
+                    polarization: U::zero(),
+                    mean_nearest_neighbor: U::zero(),
+                }
+            }
+        };
+        let count = U::from(self.boids.len()).unwrap();
+
+        // Speed and heading agreement
+        let mut speed = U::zero();
+        let mut heading = zero;
+        for boid in self.boids.iter() {
+            let velocity = boid.velocity();
+            let magnitude = velocity.magnitude();
+            speed += magnitude;
+            if magnitude > U::zero() {
+                heading += velocity / magnitude;
+            }
+        }
+
+        // Nearest neighbor spacing
+        let mut nearest = U::zero();
+        if self.boids.len() > 1 {
+            let extent = self
+                .bounding_box()
+                .map_or(U::zero(), |bounds| bounds.min.distance(bounds.max));
+            for index in 0..self.boids.len() {
+                nearest += self.nearest_neighbor_distance(index, extent);
+            }
+            nearest /= count;
+        }
+
+        FlockStatistics {
+            count: self.boids.len(),
+            centroid,
+            mean_speed: speed / count,
+            polarization: heading.magnitude() / count,
+            mean_nearest_neighbor: nearest,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector2, Vector3};
+
+    use crate::{
+        boids::{boid2d::Boid2D, boid3d::Boid3D, Boid},
+        flock::Flock,
+    };
+
+    use super::MetricsCollector;
+
+    fn check_square<T: Boid<T, f64>>(flock: &Flock<T, f64>) {
+        assert_eq!(flock.centroid(), Some(Vector3::new(1.0, 1.0, 0.0)));
+        let statistics = flock.statistics();
+        assert_eq!(statistics.count, 4);
+        assert_eq!(statistics.centroid, Vector3::new(1.0, 1.0, 0.0));
+        assert!((statistics.mean_speed - 1.0).abs() < 1e-12);
+        assert!((statistics.polarization - 1.0).abs() < 1e-12);
+        assert!((statistics.mean_nearest_neighbor - 2.0).abs() < 1e-12);
+        let bounds = flock.bounding_box().unwrap();
+        assert_eq!(bounds.min, Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(bounds.max, Vector3::new(2.0, 2.0, 0.0));
+    }
+
+    const SQUARE: [(f64, f64); 4] = [(0.0, 0.0), (2.0, 0.0), (0.0, 2.0), (2.0, 2.0)];
+
+    #[test]
+    fn statistics_are_shared_between_dimensions() {
+        let mut flock_3d: Flock<Boid3D<f64>, f64> = Flock::default();
+        let mut flock_2d: Flock<Boid2D<f64>, f64> = Flock::default();
+        for (x, y) in SQUARE {
+            flock_3d
+                .boids
+                .push(Boid3D::new_with_angle(Vector3::new(x, y, 0.0), 0.0));
+            flock_2d
+                .boids
+                .push(Boid2D::new_with_angle(Vector2::new(x, y), 0.0));
+        }
+        check_square(&flock_3d);
+        check_square(&flock_2d);
+    }
+
+    #[test]
+    fn empty_flock_reports_zeroes() {
+        let flock: Flock<Boid3D<f64>, f64> = Flock::default();
+        assert_eq!(flock.centroid(), None);
+        assert_eq!(flock.bounding_box(), None);
+        let statistics = flock.statistics();
+        assert_eq!(statistics.count, 0);
+        assert_eq!(statistics.mean_nearest_neighbor, 0.0);
+    }
+
+    #[test]
+    fn indexed_nearest_neighbors_match_a_direct_search() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            grid_min_boids: 0,
+            ..Flock::default()
+        };
+        for i in 0..40 {
+            let i = i as f64;
+            // Mostly tight clusters, with a few boids far from everything
+            let spread = if (i as usize).is_multiple_of(10) {
+                300.0
+            } else {
+                3.0
+            };
+            flock.boids.push(Boid3D::new_with_angle(
+                Vector3::new((i * 1.3).sin() * spread, (i * 0.7).cos() * spread, i % 3.0),
+                i,
+            ));
+        }
+        let direct = flock.statistics().mean_nearest_neighbor;
+
+        flock.rebuild_grid(10.0);
+        assert!(flock.uses_grid());
+        assert!((flock.statistics().mean_nearest_neighbor - direct).abs() < 1e-9);
+    }
+
+    #[test]
+    fn collector_records_every_update() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            metrics: Some(MetricsCollector::new()),
+            ..Flock::default()
+        };
+        for i in 0..6 {
+            flock.boids.push(Boid3D::new_with_angle(
+                Vector3::new(i as f64 * 8.0, (i % 2) as f64 * 5.0, 0.0),
+                i as f64,
+            ));
+        }
+
+        let mut expected = Vec::new();
+        for _ in 0..10 {
+            flock.update();
+            expected.push((flock.step, flock.statistics()));
+        }
+
+        let metrics = flock.metrics.as_ref().unwrap();
+        assert_eq!(metrics.len(), 10);
+        for (i, (step, statistics)) in expected.iter().enumerate() {
+            assert_eq!(metrics.steps()[i], *step);
+            assert_eq!(metrics.centroids()[i], statistics.centroid);
+            assert_eq!(metrics.polarization()[i], statistics.polarization);
+            assert_eq!(metrics.mean_speed()[i], statistics.mean_speed);
+            assert_eq!(
+                metrics.mean_nearest_neighbor()[i],
+                statistics.mean_nearest_neighbor
+            );
+        }
+        assert_eq!(metrics.to_csv().lines().count(), 11);
+    }
+
+    #[test]
+    fn momentum_of_a_uniformly_moving_flock() {
+        let velocity = Vector3::new(0.5, -1.0, 0.25);
+        let flock = |count: usize| Flock {
+            boids: (0..count)
+                .map(|i| {
+                    Boid3D::new_with_velocity(Vector3::new(i as f64 * 3.0, 0.0, 0.0), velocity)
+                })
+                .collect(),
+            ..Flock::default()
+        };
+
+        assert_eq!(flock(4).centroid_velocity(), velocity);
+        assert_eq!(flock(4).momentum(), velocity * 4.0);
+        assert_eq!(flock(8).momentum(), velocity * 8.0);
+
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        assert_eq!(flock(0).centroid_velocity(), zero);
+        assert_eq!(flock(0).momentum(), zero);
+    }
+}
@@ -0,0 +1,162 @@
+use cgmath::{num_traits::Float, BaseNum, Vector3};
+
+/// A sweep-and-prune index of boid indices, sorted along the axis the flock is most spread out on.
+///
+/// Queries only visit boids whose coordinate on that axis overlaps the query's range. This suits
+/// thin or elongated flocks (such as a migrating column), where a uniform grid would waste most of
+/// its cells. Like the spatial grid, it must be refreshed whenever boids move.
+#[derive(Debug, Clone)]
+pub struct SweepAndPrune<U: BaseNum + Float> {
+    axis: usize,
+    order: Vec<usize>,
+    keys: Vec<U>,
+}
+
+impl<U: BaseNum + Float> SweepAndPrune<U> {
+    /// Build an index from boid positions, indexed in iteration order
+    pub fn new(positions: impl IntoIterator<Item = Vector3<U>>) -> Self {
+        let mut index = Self {
+            axis: 0,
+            order: Vec::new(),
+            keys: Vec::new(),
+        };
+        index.refresh(positions);
+        index
+    }
+
+    /// Re-pick the sweep axis and re-sort the boids from new positions
+    pub fn refresh(&mut self, positions: impl IntoIterator<Item = Vector3<U>>) {
+        let positions: Vec<Vector3<U>> = positions.into_iter().collect();
+
+        // Sweep along whichever axis has the widest extent
+        let extent = |axis: usize| {
+            let (min, max) = positions.iter().fold(
+                (U::infinity(), U::neg_infinity()),
+                |(min, max), position| (min.min(position[axis]), max.max(position[axis])),
+            );
+            max - min
+        };
+        self.axis = (0..3)
+            .fold((0, U::neg_infinity()), |(best, widest), axis| {
+                let width = extent(axis);
+                if width > widest {
+                    (axis, width)
+                } else {
+                    (best, widest)
+                }
+            })
+            .0;
+
+        let axis = self.axis;
+        self.order = (0..positions.len()).collect();
+        self.order.sort_by(|a, b| {
+            positions[*a][axis]
+                .partial_cmp(&positions[*b][axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.keys = self
+            .order
+            .iter()
+            .map(|index| positions[*index][axis])
+            .collect();
+    }
+
+    /// Get the axis boids are sorted along, where `0`, `1`, and `2` are X, Y, and Z
+    pub fn axis(&self) -> usize {
+        self.axis
+    }
+
+    /// Get the number of indexed boids
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Check if the index is empty
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Call `f` with the index of every boid whose sweep coordinate is within `radius` of `position`'s.
+    ///
+    /// This is a broad phase: callers still need to check the exact distance.
+    pub fn for_each_candidate(&self, position: Vector3<U>, radius: U, f: impl FnMut(usize)) {
+        let offset = Vector3::new(radius, radius, radius);
+        self.for_each_candidate_in_box(position - offset, position + offset, f);
+    }
+
+    /// Call `f` with the index of every boid whose sweep coordinate overlaps a box.
+    ///
+    /// This is a broad phase: callers still need to check the exact position.
+    pub fn for_each_candidate_in_box(
+        &self,
+        min: Vector3<U>,
+        max: Vector3<U>,
+        mut f: impl FnMut(usize),
+    ) {
+        let (low, high) = (min[self.axis], max[self.axis]);
+        let start = self.keys.partition_point(|key| *key < low);
+        let end = self.keys.partition_point(|key| *key <= high);
+        if start < end {
+            self.order[start..end].iter().for_each(|index| f(*index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{MetricSpace, Vector3};
+
+    use super::SweepAndPrune;
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    /// A long thin column of boids along the Y axis
+    fn column() -> Vec<Vector3<f64>> {
+        (0..200)
+            .map(|i| {
+                let i = i as f64;
+                Vector3::new((i * 0.37).sin() * 2.0, i * 1.5, (i * 0.11).cos())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sweeps_along_the_long_axis_and_prunes() {
+        let positions = column();
+        let sweep = SweepAndPrune::new(positions.iter().copied());
+        assert_eq!(sweep.axis(), 1);
+        assert_eq!(sweep.len(), positions.len());
+
+        let radius = 5.0;
+        let mut comparisons = 0;
+        for (i, position) in positions.iter().enumerate() {
+            let mut found = Vec::new();
+            sweep.for_each_candidate(*position, radius, |index| {
+                comparisons += 1;
+                if index != i && position.distance(positions[index]) < radius {
+                    found.push(index);
+                }
+            });
+            found.sort_unstable();
+            let brute: Vec<usize> = (0..positions.len())
+                .filter(|&j| j != i && position.distance(positions[j]) < radius)
+                .collect();
+            assert_eq!(found, brute);
+        }
+        assert!(comparisons < positions.len() * positions.len() / 10);
+    }
+
+    #[test]
+    fn flock_queries_through_the_sweep_match_brute_force() {
+        let mut flock: Flock<Boid3D<f64>, f64> = Flock {
+            boids: column()
+                .into_iter()
+                .map(|position| Boid3D::new_with_angle(position, 0.0))
+                .collect(),
+            ..Flock::default()
+        };
+        let brute = flock.neighbor_graph(4.0);
+        flock.enable_sweep_and_prune();
+        assert!(flock.sweep.is_some() && !flock.uses_grid());
+        assert_eq!(flock.neighbor_graph(4.0), brute);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+use cgmath::{num_traits::Float, BaseNum};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{boids::Boid, flock::Flock};
+
+impl<T: Boid<T, U> + Clone, U: BaseNum + Float> Flock<T, U>
+where
+    T: std::marker::Send,
+    T: std::marker::Sync,
+    U: std::marker::Sync,
+{
+    /// Cap the number of boids in each cube of edge `cell_size`, returning the boids removed in index
+    /// order.
+    ///
+    /// The lowest-indexed boids in each cell are kept. If anything is removed, this goes through
+    /// `compact`, so constraints are remapped, the spatial indexes are rebuilt, and any boids already
+    /// marked with `remove_boid` are dropped as well. Nothing happens if `cell_size` is not positive.
+    pub fn thin(&mut self, cell_size: U, max_per_cell: usize) -> Vec<T> {
+        self.thin_cells(cell_size, max_per_cell, |_| {})
+    }
+
+    /// Cap the number of boids in each cell like `thin`, but keep a random selection of each cell's boids
+    pub fn thin_randomly<R: Rng>(
+        &mut self,
+        cell_size: U,
+        max_per_cell: usize,
+        rng: &mut R,
+    ) -> Vec<T> {
+        self.thin_cells(cell_size, max_per_cell, |indices| indices.shuffle(rng))
+    }
+
+    /// Thin out each cell, keeping the first `max_per_cell` boids once `order` has arranged them
+    fn thin_cells(
+        &mut self,
+        cell_size: U,
+        max_per_cell: usize,
+        mut order: impl FnMut(&mut [usize]),
+    ) -> Vec<T> {
+        if cell_size <= U::zero() {
+            return Vec::new();
+        }
+
+        // Bucket the boids, keeping each cell's indices in ascending order
+        let key = |value: U| (value / cell_size).floor().to_i64().unwrap_or(0);
+        let mut cells: BTreeMap<(i64, i64, i64), Vec<usize>> = BTreeMap::new();
+        for (index, boid) in self.boids.iter().enumerate() {
+            let position = boid.position();
+            cells
+                .entry((key(position.x), key(position.y), key(position.z)))
+                .or_default()
+                .push(index);
+        }
+
+        let mut excess = Vec::new();
+        for indices in cells.values_mut() {
+            if indices.len() > max_per_cell {
+                order(indices);
+                excess.extend_from_slice(&indices[max_per_cell..]);
+            }
+        }
+        if excess.is_empty() {
+            return Vec::new();
+        }
+        excess.sort_unstable();
+
+        let removed = excess
+            .iter()
+            .map(|index| self.boids[*index].clone())
+            .collect();
+        for index in excess {
+            self.remove_boid(index);
+        }
+        self.compact();
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use cgmath::Vector3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{boids::boid3d::Boid3D, flock::Flock};
+
+    fn flock(spacing: f64) -> Flock<Boid3D<f64>, f64> {
+        Flock {
+            boids: (0..27)
+                .map(|i| {
+                    let (x, y, z) = (i % 3, i / 3 % 3, i / 9);
+                    let position = Vector3::new(x as f64, y as f64, z as f64) * spacing;
+                    Boid3D::new_with_angle(position + Vector3::new(0.5, 0.5, 0.5), 0.0)
+                })
+                .collect(),
+            ..Flock::default()
+        }
+    }
+
+    fn largest_cell(flock: &Flock<Boid3D<f64>, f64>, cell_size: f64) -> usize {
+        let mut counts = HashMap::new();
+        for boid in flock.boids.iter() {
+            let key = boid
+                .position
+                .map(|value| (value / cell_size).floor() as i64);
+            *counts.entry((key.x, key.y, key.z)).or_insert(0) += 1;
+        }
+        counts.values().copied().max().unwrap_or(0)
+    }
+
+    #[test]
+    fn dense_cells_are_capped() {
+        // Boids a unit apart, so each cell of size 2 starts with up to eight
+        let mut dense = flock(1.0);
+        let before = dense.boids.clone();
+        let removed = dense.thin(2.0, 2);
+        assert!(largest_cell(&dense, 2.0) <= 2);
+        assert_eq!(dense.boids.len() + removed.len(), before.len());
+
+        // The first boid of every cell survives
+        assert_eq!(dense.boids[0], before[0]);
+        assert!(removed.iter().all(|boid| before.contains(boid)));
+
+        let mut random = flock(1.0);
+        let removed = random.thin_randomly(2.0, 2, &mut StdRng::seed_from_u64(4));
+        assert!(largest_cell(&random, 2.0) <= 2);
+        assert_eq!(random.boids.len() + removed.len(), before.len());
+    }
+
+    #[test]
+    fn sparse_flocks_are_untouched() {
+        let mut sparse = flock(10.0);
+        let before = sparse.boids.clone();
+        assert!(sparse.thin(2.0, 1).is_empty());
+        assert_eq!(sparse.boids, before);
+    }
+}
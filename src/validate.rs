@@ -0,0 +1,171 @@
+use cgmath::{num_traits::Float, BaseNum, InnerSpace, Vector3};
+
+use crate::{boids::Boid, bounds::Bounds, flock::Flock};
+
+/// A broken invariant found by `Flock::validate`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlockIssue<U: BaseNum + Float> {
+    /// A boid's position, velocity, or acceleration contains a NaN or infinity
+    NonFinite { index: usize },
+    /// A boid is moving faster than its `max_speed`
+    OverSpeed {
+        index: usize,
+        speed: U,
+        max_speed: U,
+    },
+    /// A boid has a negative behavior weight
+    NegativeWeight { index: usize },
+    /// A boid lies outside the bounds passed to `validate_within`
+    OutOfBounds { index: usize },
+    /// A perception radius or separation anisotropy multiplier is negative or not finite, or a
+    /// perception ellipsoid multiplier is not positive and finite
+    InvalidRadius,
+}
+
+fn is_finite<U: BaseNum + Float>(vector: Vector3<U>) -> bool {
+    vector.x.is_finite() && vector.y.is_finite() && vector.z.is_finite()
+}
+
+impl<T: Boid<T, U>, U: BaseNum + Float> Flock<T, U> {
+    /// Check the flock for broken invariants, listing every issue found.
+    ///
+    /// This is meant as a debugging aid, for example called after each update during development.
+    pub fn validate(&self) -> Result<(), Vec<FlockIssue<U>>> {
+        let mut issues = Vec::new();
+
+        // Flock-wide settings
+        let radii = [
+            self.goal_separation(),
+            self.goal_alignment(),
+            self.goal_cohesion(),
+        ];
+        let scales = self
+            .separation_anisotropy
+            .map(|anisotropy| [anisotropy.front, anisotropy.side, anisotropy.back]);
+        let ellipsoid = self
+            .perception_ellipsoid
+            .map(|ellipsoid| [ellipsoid.forward, ellipsoid.lateral]);
+        if radii
+            .iter()
+            .chain(scales.iter().flatten())
+            .any(|value| !value.is_finite() || *value < U::zero())
+            || ellipsoid
+                .iter()
+                .flatten()
+                .any(|value| !value.is_finite() || *value <= U::zero())
+        {
+            issues.push(FlockIssue::InvalidRadius);
+        }
+
+        // Per-boid state
+        let tolerance = U::one() + U::epsilon().sqrt();
+        for (index, boid) in self.boids.iter().enumerate() {
+            if !(is_finite(boid.position())
+                && is_finite(boid.velocity())
+                && is_finite(boid.acceleration()))
+            {
+                issues.push(FlockIssue::NonFinite { index });
+                continue;
+            }
+
+            let speed = boid.velocity().magnitude();
+            if speed > boid.max_speed() * tolerance {
+                issues.push(FlockIssue::OverSpeed {
+                    index,
+                    speed,
+                    max_speed: boid.max_speed(),
+                });
+            }
+
+            let weights = boid.get_weights();
+            if [
+                weights.alignment,
+                weights.cohesion,
+                weights.separation,
+                weights.targeting,
+                weights.global_cohesion,
+                weights.anchor,
+            ]
+            .iter()
+            .any(|weight| *weight < U::zero())
+            {
+                issues.push(FlockIssue::NegativeWeight { index });
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+
+    /// Check the flock like `validate`, and also flag any boid outside of `bounds`
+    pub fn validate_within(&self, bounds: Bounds<U>) -> Result<(), Vec<FlockIssue<U>>> {
+        let mut issues = self.validate().err().unwrap_or_default();
+        issues.extend(
+            self.boids
+                .iter()
+                .enumerate()
+                .filter(|(_, boid)| !bounds.contains(boid.position()))
+                .map(|(index, _)| FlockIssue::OutOfBounds { index }),
+        );
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::FlockIssue;
+    use crate::{boids::boid3d::Boid3D, bounds::Bounds, flock::Flock};
+
+    fn flock() -> Flock<Boid3D<f64>, f64> {
+        Flock {
+            boids: (0..4)
+                .map(|i| Boid3D::new_with_angle(Vector3::new(i as f64 * 10.0, 0.0, 0.0), 0.0))
+                .collect(),
+            ..Flock::default()
+        }
+    }
+
+    #[test]
+    fn healthy_flock_validates_clean() {
+        let mut flock = flock();
+        assert_eq!(flock.validate(), Ok(()));
+        flock.update();
+        assert_eq!(flock.validate(), Ok(()));
+    }
+
+    #[test]
+    fn broken_boids_are_flagged_by_index() {
+        let mut flock = flock();
+        flock.boids[1].velocity = Vector3::new(5.0, 0.0, 0.0);
+        flock.boids[2].position.y = f64::NAN;
+        flock.boids[3].weights.cohesion = -1.0;
+
+        assert_eq!(
+            flock.validate(),
+            Err(vec![
+                FlockIssue::OverSpeed {
+                    index: 1,
+                    speed: 5.0,
+                    max_speed: 2.0
+                },
+                FlockIssue::NonFinite { index: 2 },
+                FlockIssue::NegativeWeight { index: 3 },
+            ])
+        );
+
+        let bounds = Bounds::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(15.0, 1.0, 1.0));
+        let issues = flock.validate_within(bounds).unwrap_err();
+        assert!(issues.contains(&FlockIssue::OutOfBounds { index: 3 }));
+        assert!(!issues.contains(&FlockIssue::OutOfBounds { index: 1 }));
+    }
+}